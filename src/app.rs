@@ -0,0 +1,574 @@
+use eframe::egui::{self, Visuals};
+use epaint::emath;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use worldgen::{
+    cache_dir, export_heightmap, generator_thread, CancelToken, Project, ThreadMessage,
+    View3dSettings, WorldGenCommand, WorldGenerator, MASK_SIZE, VERSION,
+};
+
+use crate::logger;
+use crate::panel_2dview::{Panel2dAction, Panel2dView};
+use crate::panel_3dview::Panel3dView;
+use crate::panel_export::{ExportAction, PanelExport};
+use crate::panel_generator::{GeneratorAction, PanelGenerator};
+use crate::panel_save::{PanelSaveLoad, SaveLoadAction};
+
+/// open the main window
+pub fn run() {
+    let options = eframe::NativeOptions {
+        multisampling: 8,
+        depth_buffer: 24,
+        renderer: eframe::Renderer::Glow,
+        vsync: true,
+        viewport: egui::ViewportBuilder::default().with_maximized(true),
+        ..Default::default()
+    };
+    logger::init(log::LevelFilter::Debug);
+    println!(
+        "wgen v{} - {} cpus {} cores",
+        VERSION,
+        num_cpus::get(),
+        num_cpus::get_physical()
+    );
+    eframe::run_native(
+        "wgen",
+        options,
+        Box::new(|_cc| Ok(Box::new(MyApp::default()))),
+    )
+    .or_else(|e| {
+        eprintln!("Error: {}", e);
+        Ok::<(), ()>(())
+    })
+    .ok();
+}
+
+struct MyApp {
+    /// size in pixels of the 2D preview canvas
+    image_size: usize,
+    /// size of the preview heightmap longest side (from 64 to 512)
+    preview_size: usize,
+    /// size of the preview heightmap, with the same proportions as the exported terrain
+    preview_world_size: (usize, usize),
+    /// current world generator progress
+    progress: f32,
+    /// exporter progress
+    exporter_progress: f32,
+    /// exporter progress bar text
+    exporter_text: String,
+    /// exporter current step
+    exporter_cur_step: usize,
+    /// to stop the running export
+    exporter_cancel: Option<CancelToken>,
+    /// to stop the steps sent to the world generator : step index, token
+    step_cancel: Vec<(usize, CancelToken)>,
+    /// random number generator's seed
+    seed: u64,
+    // ui widgets
+    gen_panel: PanelGenerator,
+    export_panel: PanelExport,
+    panel_3d: Panel3dView,
+    panel_2d: Panel2dView,
+    load_save_panel: PanelSaveLoad,
+    // thread communication
+    /// channel to receive messages from either world generator or exporter
+    thread2main_rx: Receiver<ThreadMessage>,
+    /// channel to send messages to the world generator thread
+    main2wgen_tx: Sender<WorldGenCommand>,
+    /// channel to send messages to the main thread from the exporter thread
+    exp2main_tx: Sender<ThreadMessage>,
+    /// an error to display in a popup
+    err_msg: Option<String>,
+    /// a warning to display in a popup
+    warn_msg: Option<String>,
+    /// are we editing a mask ?
+    mask_step: Option<usize>,
+    /// last time the mask was updated
+    last_mask_updated: f64,
+}
+
+impl Default for MyApp {
+    fn default() -> Self {
+        let preview_size = 128;
+        let image_size = 790; //368;
+        let seed = 0xdeadbeef;
+        let export_panel = PanelExport::default();
+        let preview_world_size = export_panel.settings.preview_size(preview_size);
+        let wgen = WorldGenerator::new(seed, preview_world_size);
+        let panel_2d = Panel2dView::new(image_size, preview_world_size, &wgen.get_export_map());
+        // generator -> main channel
+        let (exp2main_tx, thread2main_rx) = mpsc::channel();
+        // main -> generator channel
+        let (main2gen_tx, gen_rx) = mpsc::channel();
+        let gen_tx = exp2main_tx.clone();
+        thread::spawn(move || {
+            generator_thread(seed, preview_world_size, gen_rx, gen_tx);
+        });
+        Self {
+            image_size,
+            preview_size,
+            preview_world_size,
+            seed,
+            panel_2d,
+            panel_3d: Panel3dView::new(image_size as f32, View3dSettings::default()),
+            progress: 1.0,
+            exporter_progress: 1.0,
+            exporter_text: String::new(),
+            exporter_cur_step: 0,
+            exporter_cancel: None,
+            step_cancel: Vec::new(),
+            mask_step: None,
+            gen_panel: PanelGenerator::default(),
+            export_panel,
+            load_save_panel: PanelSaveLoad::default(),
+            thread2main_rx,
+            main2wgen_tx: main2gen_tx,
+            exp2main_tx,
+            err_msg: None,
+            warn_msg: None,
+            last_mask_updated: 0.0,
+        }
+    }
+}
+
+impl MyApp {
+    fn export(&mut self) {
+        let steps = self.gen_panel.steps.clone();
+        let export_settings = self.export_panel.settings.clone();
+        let seed = self.seed;
+        let tx = self.exp2main_tx.clone();
+        let min_progress_step = 0.01 * self.gen_panel.enabled_steps() as f32;
+        let cancel = CancelToken::default();
+        self.exporter_cancel = Some(cancel.clone());
+        thread::spawn(move || {
+            let res = export_heightmap(
+                seed,
+                &steps,
+                &export_settings,
+                tx.clone(),
+                min_progress_step,
+                &cancel,
+            );
+            tx.send(ThreadMessage::ExporterDone(res)).unwrap();
+        });
+    }
+    fn regen(&mut self, must_delete: bool, from_idx: usize) {
+        self.progress = from_idx as f32 / self.gen_panel.enabled_steps() as f32;
+        // stop the step being computed if it's going to be recomputed anyway
+        self.step_cancel.retain(|(idx, cancel)| {
+            if *idx >= from_idx {
+                cancel.cancel();
+            }
+            *idx < from_idx
+        });
+        self.main2wgen_tx
+            .send(WorldGenCommand::Abort(from_idx))
+            .unwrap();
+        let len = self.gen_panel.steps.len();
+        if must_delete {
+            self.main2wgen_tx
+                .send(WorldGenCommand::DeleteStep(from_idx))
+                .unwrap();
+        }
+        if len == 0 {
+            return;
+        }
+        for i in from_idx.min(len - 1)..len {
+            let cancel = CancelToken::default();
+            self.step_cancel.push((i, cancel.clone()));
+            self.main2wgen_tx
+                .send(WorldGenCommand::ExecuteStep(
+                    i,
+                    self.gen_panel.steps[i].clone(),
+                    self.panel_2d.live_preview,
+                    0.01 * self.gen_panel.enabled_steps() as f32,
+                    cancel,
+                ))
+                .unwrap();
+        }
+        self.gen_panel.is_running = true;
+    }
+    fn set_seed(&mut self, new_seed: u64) {
+        self.seed = new_seed;
+        self.main2wgen_tx
+            .send(WorldGenCommand::SetSeed(new_seed))
+            .unwrap();
+        self.regen(false, 0);
+    }
+    /// the data to save in the project file
+    fn project(&self) -> Project {
+        Project {
+            seed: self.seed,
+            preview_size: self.preview_size,
+            steps: self.gen_panel.steps.clone(),
+            last_step_id: self.gen_panel.last_step_id,
+            export: self.export_panel.settings.clone(),
+            view_3d: self.panel_3d.settings(),
+            ..Default::default()
+        }
+    }
+    /// the steps heightmaps are also saved next to the project file if the disk cache is enabled
+    fn update_cache_dir(&mut self) {
+        let dir = self
+            .load_save_panel
+            .disk_cache
+            .then(|| cache_dir(self.load_save_panel.get_file_path()));
+        self.main2wgen_tx
+            .send(WorldGenCommand::SetCacheDir(dir))
+            .unwrap();
+    }
+    fn set_project(&mut self, project: &Project) {
+        self.gen_panel.set_project(project);
+        self.export_panel.settings = project.export.clone();
+        self.panel_3d.set_settings(project.view_3d);
+        self.main2wgen_tx.send(WorldGenCommand::Clear).unwrap();
+        self.resize(project.preview_size);
+        self.set_seed(project.seed);
+    }
+    /// change the preview longest side. The preview proportions follow the export settings
+    fn resize(&mut self, new_size: usize) {
+        let world_size = self.export_panel.settings.preview_size(new_size);
+        self.preview_size = new_size;
+        if self.preview_world_size == world_size {
+            return;
+        }
+        self.preview_world_size = world_size;
+        self.main2wgen_tx
+            .send(WorldGenCommand::SetSize(world_size))
+            .unwrap();
+        self.regen(false, 0);
+    }
+    fn render_left_panel(&mut self, ctx: &egui::Context) {
+        egui::SidePanel::left("Generation").show(ctx, |ui| {
+            ui.label(format!("wgen {}", VERSION));
+            ui.separator();
+            match self.export_panel.render(
+                ui,
+                self.exporter_progress,
+                &self.exporter_text,
+                &self.gen_panel.steps,
+            ) {
+                Some(ExportAction::Export) => {
+                    self.export_panel.enabled = false;
+                    self.exporter_progress = 0.0;
+                    self.exporter_cur_step = 0;
+                    self.export();
+                }
+                Some(ExportAction::Cancel) => {
+                    if let Some(ref cancel) = self.exporter_cancel {
+                        cancel.cancel();
+                        self.exporter_text = "Cancelling...".to_owned();
+                    }
+                }
+                None => (),
+            }
+            ui.separator();
+            match self.load_save_panel.render(ui) {
+                Some(SaveLoadAction::Load) => {
+                    match Project::load(self.load_save_panel.get_file_path()) {
+                        Err(msg) => {
+                            let err_msg = format!(
+                                "Error while reading project {} : {}",
+                                self.load_save_panel.get_file_path(),
+                                msg
+                            );
+                            println!("{}", err_msg);
+                            self.err_msg = Some(err_msg);
+                        }
+                        Ok((project, warning)) => {
+                            if let Some(warning) = warning {
+                                println!("{}", warning);
+                                self.warn_msg = Some(warning);
+                            }
+                            self.update_cache_dir();
+                            self.set_project(&project);
+                        }
+                    }
+                }
+                Some(SaveLoadAction::Save) => {
+                    if let Err(msg) = self.project().save(self.load_save_panel.get_file_path()) {
+                        let err_msg = format!(
+                            "Error while writing project {} : {}",
+                            self.load_save_panel.get_file_path(),
+                            msg
+                        );
+                        println!("{}", err_msg);
+                        self.err_msg = Some(err_msg);
+                    }
+                    self.update_cache_dir();
+                }
+                Some(SaveLoadAction::DiskCache) => {
+                    self.update_cache_dir();
+                }
+                None => (),
+            }
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                match self.gen_panel.render(ui, self.progress) {
+                    Some(GeneratorAction::Clear) => {
+                        self.main2wgen_tx.send(WorldGenCommand::Clear).unwrap();
+                    }
+                    Some(GeneratorAction::SetSeed(new_seed)) => {
+                        self.set_seed(new_seed);
+                    }
+                    Some(GeneratorAction::Regen(must_delete, from_idx)) => {
+                        self.regen(must_delete, from_idx);
+                    }
+                    Some(GeneratorAction::Restore(from_idx, removed)) => {
+                        // the mask editor displays a mask that may not exist anymore
+                        self.mask_step = None;
+                        self.last_mask_updated = 0.0;
+                        for _ in 0..removed {
+                            self.main2wgen_tx
+                                .send(WorldGenCommand::DeleteStep(from_idx))
+                                .unwrap();
+                        }
+                        if self.seed != self.gen_panel.seed {
+                            self.set_seed(self.gen_panel.seed);
+                        } else {
+                            self.regen(false, from_idx);
+                        }
+                    }
+                    Some(GeneratorAction::Disable(idx)) => {
+                        self.main2wgen_tx
+                            .send(WorldGenCommand::DisableStep(idx))
+                            .unwrap();
+                        self.regen(false, idx);
+                    }
+                    Some(GeneratorAction::Enable(idx)) => {
+                        self.main2wgen_tx
+                            .send(WorldGenCommand::EnableStep(idx))
+                            .unwrap();
+                        self.regen(false, idx);
+                    }
+                    Some(GeneratorAction::DisplayLayer(step)) => {
+                        self.main2wgen_tx
+                            .send(WorldGenCommand::GetStepMap(step))
+                            .unwrap();
+                        self.main2wgen_tx
+                            .send(WorldGenCommand::GetStepHistogram(step))
+                            .unwrap();
+                    }
+                    Some(GeneratorAction::DisplayMask(step)) => {
+                        self.mask_step = Some(step);
+                        let mask = if let Some(ref mask) = self.gen_panel.steps[step].mask {
+                            Some(mask.clone())
+                        } else {
+                            Some(vec![1.0; MASK_SIZE * MASK_SIZE])
+                        };
+                        self.panel_2d
+                            .display_mask(self.image_size, self.preview_world_size, mask);
+                    }
+                    None => (),
+                }
+            });
+        });
+    }
+    fn render_central_panel(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("Terrain preview");
+            ui.horizontal(|ui| {
+                egui::CollapsingHeader::new("2d preview")
+                    .default_open(true)
+                    .show(ui, |ui| match self.panel_2d.render(ui) {
+                        Some(Panel2dAction::ResizePreview(new_size)) => {
+                            self.resize(new_size);
+                            self.mask_step = None;
+                            self.gen_panel.mask_selected = false;
+                        }
+                        Some(Panel2dAction::MaskUpdated) => {
+                            self.last_mask_updated = ui.input(|r| r.time);
+                        }
+                        Some(Panel2dAction::MaskDelete) => {
+                            if let Some(step) = self.mask_step {
+                                self.gen_panel.steps[step].mask = None;
+                            }
+                            self.last_mask_updated = 0.0;
+                        }
+                        None => (),
+                    });
+                egui::CollapsingHeader::new("3d preview")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        self.panel_3d.render(ui);
+                    });
+            });
+        });
+    }
+    fn handle_threads_messages(&mut self) {
+        match self.thread2main_rx.try_recv() {
+            Ok(ThreadMessage::GeneratorStepProgress(progress)) => {
+                let progstep = 1.0 / self.gen_panel.enabled_steps() as f32;
+                self.progress = (self.progress / progstep).floor() * progstep;
+                self.progress += progress * progstep;
+            }
+            Ok(ThreadMessage::GeneratorDone(hmap)) => {
+                log::debug!("main<=Done");
+                self.panel_2d
+                    .refresh(self.image_size, self.preview_world_size, Some(&hmap));
+                self.gen_panel.selected_step = self.gen_panel.steps.len() - 1;
+                self.main2wgen_tx
+                    .send(WorldGenCommand::GetStepHistogram(
+                        self.gen_panel.selected_step,
+                    ))
+                    .unwrap();
+                self.panel_3d.update_mesh(&hmap);
+                self.gen_panel.is_running = false;
+                self.progress = 1.0;
+            }
+            Ok(ThreadMessage::GeneratorStepDone(step, hmap)) => {
+                log::debug!("main<=GeneratorStepDone({})", step);
+                if let Some(ref hmap) = hmap {
+                    self.panel_2d
+                        .refresh(self.image_size, self.preview_world_size, Some(hmap));
+                }
+                self.gen_panel.selected_step = step;
+                self.progress = (step + 1) as f32 / self.gen_panel.enabled_steps() as f32
+            }
+            Ok(ThreadMessage::GeneratorStepMap(_idx, hmap)) => {
+                // display heightmap from a specific step in the 2d preview
+                if let Some(step) = self.mask_step {
+                    // mask was updated, recompute terrain
+                    self.regen(false, step);
+                    self.mask_step = None;
+                }
+                self.panel_2d
+                    .refresh(self.image_size, self.preview_world_size, Some(&hmap));
+            }
+            Ok(ThreadMessage::GeneratorStepHistogram(step, histogram)) => {
+                // the selection may have changed since the request
+                if step == self.gen_panel.selected_step {
+                    self.gen_panel.input_histogram = Some(*histogram);
+                }
+            }
+            Ok(ThreadMessage::ExporterStepProgress(progress)) => {
+                let progstep = 1.0 / self.gen_panel.enabled_steps() as f32;
+                self.exporter_progress = (self.exporter_progress / progstep).floor() * progstep;
+                self.exporter_progress += progress * progstep;
+                self.exporter_text = format!(
+                    "{}% {}/{} {}",
+                    (self.exporter_progress * 100.0) as usize,
+                    self.exporter_cur_step + 1,
+                    self.gen_panel.steps.len(),
+                    self.gen_panel.steps[self.exporter_cur_step]
+                );
+            }
+            Ok(ThreadMessage::ExporterStepDone(step)) => {
+                log::debug!("main<=ExporterStepDone({})", step);
+                self.exporter_progress = (step + 1) as f32 / self.gen_panel.enabled_steps() as f32;
+                self.exporter_cur_step = step + 1;
+                if step + 1 == self.gen_panel.steps.len() {
+                    self.exporter_text =
+                        format!("Saving {}...", self.export_panel.settings.file_type);
+                } else {
+                    self.exporter_text = format!(
+                        "{}% {}/{} {}",
+                        (self.exporter_progress * 100.0) as usize,
+                        step + 1,
+                        self.gen_panel.steps.len(),
+                        self.gen_panel.steps[self.exporter_cur_step]
+                    );
+                }
+            }
+            Ok(ThreadMessage::ExporterDone(res)) => {
+                let cancelled = self
+                    .exporter_cancel
+                    .take()
+                    .is_some_and(|cancel| cancel.is_cancelled());
+                if cancelled {
+                    log::debug!("main<=ExporterCancelled");
+                } else if let Err(msg) = res {
+                    let err_msg = format!("Error while exporting heightmap : {}", msg);
+                    println!("{}", err_msg);
+                    self.err_msg = Some(err_msg);
+                }
+                log::debug!("main<=ExporterDone");
+                self.exporter_progress = 1.0;
+                self.export_panel.enabled = true;
+                self.exporter_cur_step = 0;
+                self.exporter_text = String::new();
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+impl eframe::App for MyApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let wsize = ctx.input(|i| {
+            if let Some(rect) = i.viewport().inner_rect {
+                rect.size()
+            } else {
+                emath::Vec2::new(0.0, 0.0)
+            }
+        });
+        let new_size = ((wsize.x - 340.0) * 0.5) as usize;
+        if new_size != self.image_size && new_size != 0 {
+            // handle window resizing
+            self.image_size = new_size;
+            self.panel_2d
+                .refresh(self.image_size, self.preview_world_size, None);
+            self.panel_3d = Panel3dView::new(self.image_size as f32, self.panel_3d.settings());
+            self.regen(false, 0);
+        }
+        if !ctx.input(|i| i.pointer.any_down())
+            && self.export_panel.settings.preview_size(self.preview_size) != self.preview_world_size
+        {
+            // the exported terrain proportions changed
+            self.resize(self.preview_size);
+            self.panel_2d
+                .refresh(self.image_size, self.preview_world_size, None);
+            self.mask_step = None;
+            self.gen_panel.mask_selected = false;
+        }
+        ctx.set_visuals(Visuals::dark());
+        self.handle_threads_messages();
+        self.render_left_panel(ctx);
+        self.render_central_panel(ctx);
+        if self.last_mask_updated > 0.0 && ctx.input(|i| i.time) - self.last_mask_updated >= 0.5 {
+            if let Some(step) = self.mask_step {
+                // mask was updated, copy mask to generator step
+                if let Some(mask) = self.panel_2d.get_current_mask() {
+                    self.gen_panel.steps[step].mask = Some(mask);
+                }
+            }
+            self.last_mask_updated = 0.0;
+        }
+
+        if let Some(ref err_msg) = self.err_msg {
+            // display error popup
+            let mut open = true;
+            egui::Window::new("Error")
+                .resizable(false)
+                .collapsible(false)
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.scope(|ui| {
+                        ui.visuals_mut().override_text_color = Some(egui::Color32::RED);
+                        ui.label(err_msg);
+                    });
+                });
+            if !open {
+                self.err_msg = None;
+            }
+        }
+        if let Some(ref warn_msg) = self.warn_msg {
+            // display warning popup
+            let mut open = true;
+            egui::Window::new("Warning")
+                .resizable(false)
+                .collapsible(false)
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.scope(|ui| {
+                        ui.visuals_mut().override_text_color = Some(egui::Color32::YELLOW);
+                        ui.label(warn_msg);
+                    });
+                });
+            if !open {
+                self.warn_msg = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! cache of the steps heightmaps, so that only the steps whose inputs changed are recomputed.
+//! A step result is identified by a hash of its configuration, mask, seed, the world size, the previous step result's hash
+//! and the program version, so that the disk cache isn't reused by a version whose generators give a different terrain.
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::{Step, VERSION};
+
+/// maximum memory used by the heightmaps kept in memory, and default size of the disk cache directory
+const MAX_CACHE_MEMORY: usize = 256 * 1024 * 1024;
+/// smallest heightmap (in cells) saved in the disk cache. Smaller ones are recomputed quickly enough
+pub const DISK_CACHE_MIN_CELLS: usize = 256 * 256;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// FNV-1a hash. Unlike the standard library hasher, it gives the same result with every rust version, which matters for the disk cache
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// hash of a step result. upstream is the hash of the previous step result, 0 for the first step
+pub fn step_hash(upstream: u64, step: &Step, project_seed: u64, size: (usize, usize)) -> u64 {
+    // the step configuration and mask, as saved in the project
+    let conf = ron::to_string(step).unwrap_or_default();
+    let mut hash = fnv1a(FNV_OFFSET, VERSION.as_bytes());
+    hash = fnv1a(hash, &upstream.to_le_bytes());
+    hash = fnv1a(hash, &step.seed(project_seed).to_le_bytes());
+    hash = fnv1a(hash, &(size.0 as u64).to_le_bytes());
+    hash = fnv1a(hash, &(size.1 as u64).to_le_bytes());
+    fnv1a(hash, conf.as_bytes())
+}
+
+/// directory of the disk cache of a project
+pub fn cache_dir(project_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.cache", project_path))
+}
+
+/// steps heightmaps indexed by their hash. The least recently used ones are dropped when the cache is full.
+/// Big heightmaps can also be saved on disk so that they survive the program.
+/// The disk files are also dropped from the least recently used when they exceed the disk budget
+#[derive(Clone)]
+pub struct StepCache {
+    maps: HashMap<u64, Vec<f32>>,
+    /// hashes from the least to the most recently used
+    order: VecDeque<u64>,
+    /// memory used by the heightmaps in bytes
+    memory: usize,
+    /// directory where the big heightmaps are also saved
+    dir: Option<PathBuf>,
+    /// maximum size of the files in dir in bytes
+    disk_budget: usize,
+}
+
+impl Default for StepCache {
+    fn default() -> Self {
+        Self {
+            maps: HashMap::new(),
+            order: VecDeque::new(),
+            memory: 0,
+            dir: None,
+            disk_budget: MAX_CACHE_MEMORY,
+        }
+    }
+}
+
+impl StepCache {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            ..Default::default()
+        }
+    }
+    /// change the maximum size of the disk cache directory
+    pub fn with_disk_budget(self, disk_budget: usize) -> Self {
+        Self {
+            disk_budget,
+            ..self
+        }
+    }
+    /// change the disk cache directory. The heightmaps already in memory are saved in the new directory
+    pub fn set_dir(&mut self, dir: Option<PathBuf>) {
+        self.dir = dir;
+        for (hash, h) in self.maps.iter() {
+            self.write(*hash, h);
+        }
+    }
+    /// number of heightmaps in memory
+    pub fn len(&self) -> usize {
+        self.maps.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.maps.is_empty()
+    }
+    fn file_path(&self, hash: u64, len: usize) -> Option<PathBuf> {
+        if len < DISK_CACHE_MIN_CELLS {
+            return None;
+        }
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{:016x}.hmap", hash)))
+    }
+    fn touch(&mut self, hash: u64) {
+        if let Some(pos) = self.order.iter().position(|h| *h == hash) {
+            self.order.remove(pos);
+        }
+        self.order.push_back(hash);
+    }
+    /// the heightmap with this hash and len cells, from memory or from the disk cache
+    pub fn get(&mut self, hash: u64, len: usize) -> Option<&[f32]> {
+        if !self.maps.contains_key(&hash) {
+            let h = self.read(hash, len)?;
+            self.store(hash, h);
+        }
+        self.touch(hash);
+        self.touch_file(hash, len);
+        self.maps.get(&hash).map(|h| h.as_slice())
+    }
+    /// add a heightmap to the cache, also saving it on disk if it's big enough
+    pub fn insert(&mut self, hash: u64, h: &[f32]) {
+        if self.maps.contains_key(&hash) {
+            self.touch(hash);
+            return;
+        }
+        self.write(hash, h);
+        self.store(hash, h.to_vec());
+    }
+    fn store(&mut self, hash: u64, h: Vec<f32>) {
+        let size = h.len() * std::mem::size_of::<f32>();
+        while self.memory + size > MAX_CACHE_MEMORY {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(old) = self.maps.remove(&oldest) {
+                self.memory -= old.len() * std::mem::size_of::<f32>();
+            }
+        }
+        self.memory += size;
+        self.maps.insert(hash, h);
+        self.touch(hash);
+    }
+    fn read(&self, hash: u64, len: usize) -> Option<Vec<f32>> {
+        let bytes = fs::read(self.file_path(hash, len)?).ok()?;
+        if bytes.len() != len * std::mem::size_of::<f32>() {
+            return None;
+        }
+        Some(
+            bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        )
+    }
+    fn write(&self, hash: u64, h: &[f32]) {
+        let Some(path) = self.file_path(hash, h.len()) else {
+            return;
+        };
+        if path.exists() {
+            return;
+        }
+        let buf: Vec<u8> = h.iter().flat_map(|v| v.to_le_bytes()).collect();
+        // the cache is only an optimization : errors are not reported to the user
+        if let Err(e) = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, buf))
+        {
+            log::warn!("Error while writing {} : {}", path.display(), e);
+        }
+        self.prune_dir();
+    }
+    /// mark a disk file as recently used
+    fn touch_file(&self, hash: u64, len: usize) {
+        if let Some(path) = self.file_path(hash, len) {
+            let _ = fs::File::options()
+                .write(true)
+                .open(path)
+                .and_then(|f| f.set_modified(SystemTime::now()));
+        }
+    }
+    /// delete the least recently used files of the disk cache until it fits in the disk budget
+    fn prune_dir(&self) {
+        let Some(ref dir) = self.dir else {
+            return;
+        };
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let path = entry.path();
+                if path.extension()? != "hmap" {
+                    return None;
+                }
+                let meta = entry.metadata().ok()?;
+                Some((meta.modified().ok()?, meta.len(), path))
+            })
+            .collect();
+        let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+        // the oldest first. Files written at the same time are ordered by name so that the result is deterministic
+        files.sort();
+        for (_, len, path) in files {
+            if total <= self.disk_budget as u64 {
+                break;
+            }
+            if let Err(e) = fs::remove_file(&path) {
+                log::warn!("Error while deleting {} : {}", path.display(), e);
+            }
+            total -= len;
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::sync::mpsc;
+use std::thread;
+
+use worldgen::{
+    export_heightmap, CancelToken, ExportFileType, ExportSettings, Project, ThreadMessage,
+    MASK_SIZE, VERSION,
+};
+
+use crate::logger;
+
+const USAGE: &str = "usage :
+  worldgen                        start the graphical interface (if built with the gui feature)
+  worldgen export <project.wgen> [options]
+                                  the options override the export settings saved in the project
+      --tile <width>x<height>     size of each exported image in pixels
+      --tiles <h>x<v>             number of horizontal and vertical tiles
+      --format <png|exr>          exported file format
+      --out <path>                exported files prefix
+      --seamless                  repeat border pixels on adjacent tiles
+      --streaming                 compute the heightmap by parts stored in temporary files
+                                  instead of keeping it in memory (for huge terrains)
+  worldgen info <project.wgen>      display the project's seed and steps
+  worldgen validate <project.wgen>  check that the project can be loaded
+  worldgen help                   display this message
+  --verbose                       with any command, display the steps computation details";
+
+/// run the command line subcommand in args (without the program name).
+/// returns the process exit code
+pub fn run(args: &[String]) -> i32 {
+    if args.iter().any(|a| a == "--verbose") {
+        logger::init(log::LevelFilter::Info);
+    }
+    let args: Vec<String> = args.iter().filter(|a| *a != "--verbose").cloned().collect();
+    let Some(cmd) = args.first() else {
+        eprintln!("Error: missing command\n{}", USAGE);
+        return 1;
+    };
+    let res = match cmd.as_str() {
+        "export" => export(&args[1..]),
+        "info" => info(&args[1..]),
+        "validate" => validate(&args[1..]),
+        "help" | "-h" | "--help" => {
+            println!("wgen v{}\n{}", VERSION, USAGE);
+            return 0;
+        }
+        cmd => Err(format!("unknown command {}\n{}", cmd, USAGE)),
+    };
+    match res {
+        Ok(()) => 0,
+        Err(msg) => {
+            eprintln!("Error: {}", msg);
+            1
+        }
+    }
+}
+
+fn load_project(args: &[String]) -> Result<Project, String> {
+    let file_path = args
+        .first()
+        .ok_or(format!("missing project file\n{}", USAGE))?;
+    let (project, warning) = Project::load(file_path)
+        .map_err(|msg| format!("Error while reading project {} : {}", file_path, msg))?;
+    if let Some(warning) = warning {
+        eprintln!("Warning: {}", warning);
+    }
+    Ok(project)
+}
+
+/// parse a <width>x<height> value
+fn parse_size(opt: &str, value: Option<&String>) -> Result<(f32, f32), String> {
+    let value = value.ok_or(format!("missing value for {}", opt))?;
+    let bad_value = || {
+        format!(
+            "bad value for {} : {}. Expected <width>x<height>",
+            opt, value
+        )
+    };
+    let (w, h) = value.split_once('x').ok_or_else(bad_value)?;
+    let w: u32 = w.parse().map_err(|_| bad_value())?;
+    let h: u32 = h.parse().map_err(|_| bad_value())?;
+    if w == 0 || h == 0 {
+        return Err(bad_value());
+    }
+    Ok((w as f32, h as f32))
+}
+
+fn parse_export_options(
+    args: &[String],
+    mut export_data: ExportSettings,
+) -> Result<ExportSettings, String> {
+    let mut i = 0;
+    while i < args.len() {
+        let opt = args[i].as_str();
+        let value = args.get(i + 1);
+        match opt {
+            "--tile" => {
+                (export_data.export_width, export_data.export_height) = parse_size(opt, value)?;
+                i += 1;
+            }
+            "--tiles" => {
+                (export_data.tiles_h, export_data.tiles_v) = parse_size(opt, value)?;
+                i += 1;
+            }
+            "--format" => {
+                export_data.file_type = match value.map(|v| v.as_str()) {
+                    Some("png") => ExportFileType::Png,
+                    Some("exr") => ExportFileType::Exr,
+                    _ => return Err("bad value for --format. Expected png or exr".to_owned()),
+                };
+                i += 1;
+            }
+            "--out" => {
+                export_data.file_path = value.ok_or("missing value for --out")?.to_owned();
+                i += 1;
+            }
+            "--seamless" => export_data.seamless = true,
+            "--streaming" => export_data.streaming = true,
+            _ => return Err(format!("unknown option {}", opt)),
+        }
+        i += 1;
+    }
+    Ok(export_data)
+}
+
+fn export(args: &[String]) -> Result<(), String> {
+    let project = load_project(args)?;
+    let export_data = parse_export_options(&args[1..], project.export.clone())?;
+    if project.steps.is_empty() {
+        return Err("the project has no step".to_owned());
+    }
+    let steps = project.steps.clone();
+    let seed = project.seed;
+    let min_progress_step = 0.01 * project.enabled_steps() as f32;
+    let (tx, rx) = mpsc::channel();
+    let exporter = thread::spawn(move || {
+        let res = export_heightmap(
+            seed,
+            &steps,
+            &export_data,
+            tx.clone(),
+            min_progress_step,
+            &CancelToken::default(),
+        );
+        tx.send(ThreadMessage::ExporterDone(res)).unwrap();
+    });
+    let step_count = project.steps.len();
+    let mut cur_step = 0;
+    let mut res = Err("exporter thread stopped unexpectedly".to_owned());
+    for msg in rx {
+        match msg {
+            ThreadMessage::ExporterStepProgress(progress) => {
+                // progress messages can still arrive after the last step is done
+                if let Some(step) = project.steps.get(cur_step) {
+                    println!(
+                        "{:3}% {}/{} {}",
+                        ((cur_step as f32 + progress) * 100.0 / step_count as f32) as usize,
+                        cur_step + 1,
+                        step_count,
+                        step
+                    );
+                }
+            }
+            ThreadMessage::ExporterStepDone(step) => {
+                cur_step = step + 1;
+                if cur_step == step_count {
+                    println!("Saving tiles...");
+                }
+            }
+            ThreadMessage::ExporterDone(export_res) => {
+                res = export_res;
+                break;
+            }
+            _ => (),
+        }
+    }
+    exporter.join().map_err(|_| "exporter thread panicked")?;
+    res?;
+    println!("Export done");
+    Ok(())
+}
+
+fn info(args: &[String]) -> Result<(), String> {
+    let project = load_project(args)?;
+    println!("project {}", args[0]);
+    println!("seed : {}", project.seed);
+    let export = &project.export;
+    println!(
+        "export : {} tiles {}x{} of {}x{} pixels{} to {}_x*_y*.{}",
+        export.file_type,
+        export.tiles_h,
+        export.tiles_v,
+        export.export_width,
+        export.export_height,
+        if export.seamless { " [seamless]" } else { "" },
+        export.file_path,
+        export.file_type
+    );
+    println!(
+        "steps : {} ({} enabled)",
+        project.steps.len(),
+        project.enabled_steps()
+    );
+    for (i, step) in project.steps.iter().enumerate() {
+        println!(
+            "{:3} {}{}{} seed {}{} {:?}",
+            i,
+            step,
+            if step.disabled { " [disabled]" } else { "" },
+            if step.mask.is_some() { " [mask]" } else { "" },
+            step.seed(project.seed),
+            if step.seed.is_some() {
+                " (override)"
+            } else {
+                ""
+            },
+            step.typ
+        );
+    }
+    Ok(())
+}
+
+fn validate(args: &[String]) -> Result<(), String> {
+    let project = load_project(args)?;
+    let mut errors = Vec::new();
+    if project.steps.is_empty() {
+        errors.push("the project has no step".to_owned());
+    }
+    for (i, step) in project.steps.iter().enumerate() {
+        if let Some(ref mask) = step.mask {
+            if mask.len() != MASK_SIZE * MASK_SIZE {
+                errors.push(format!(
+                    "step {} {} : bad mask size. Expected {} values, found {}",
+                    i,
+                    step,
+                    MASK_SIZE * MASK_SIZE,
+                    mask.len()
+                ));
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    println!("{} : ok", args[0]);
+    Ok(())
+}
@@ -1,102 +1,257 @@
 use std::{path::Path, sync::mpsc::Sender};
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
-    panel_export::{ExportFileType, PanelExport},
-    worldgen::{Step, WorldGenerator},
-    ThreadMessage,
+    streaming::{self, generate_layers, CANCELLED},
+    worldgen::{with_step_ids, Step, WorldGenerator},
+    CancelToken, ThreadMessage,
 };
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ExportFileType {
+    Png,
+    Exr,
+}
+
+impl std::fmt::Display for ExportFileType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Png => "png",
+                Self::Exr => "exr",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportSettings {
+    /// width of each image in pixels
+    pub export_width: f32,
+    /// height of each image in pixels
+    pub export_height: f32,
+    /// number of horizontal tiles
+    pub tiles_h: f32,
+    /// number of vertical tiles
+    pub tiles_v: f32,
+    /// image filename prefix
+    pub file_path: String,
+    /// should we repeat the same pixel row on two adjacent tiles ?
+    /// not needed for unreal engine which handles multi-textures heightmaps
+    /// might be needed for other engines (for example godot heightmap terrain plugin)
+    pub seamless: bool,
+    /// format to export, either png or exr
+    pub file_type: ExportFileType,
+    /// compute the heightmap by bands stored in temporary files instead of keeping it in memory.
+    /// Slower, but the terrain size is only limited by the disk space
+    pub streaming: bool,
+    /// maximum number of cells computed at once by the streaming exporter. Not saved in the project
+    #[serde(skip)]
+    pub band_cells: usize,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        let cur_dir = std::env::current_dir().unwrap_or_default();
+        Self {
+            export_width: 1024.0,
+            export_height: 1024.0,
+            tiles_h: 1.0,
+            tiles_v: 1.0,
+            file_path: format!("{}/wgen", cur_dir.display()),
+            seamless: false,
+            file_type: ExportFileType::Png,
+            streaming: false,
+            band_cells: streaming::BAND_CELLS,
+        }
+    }
+}
+
+impl ExportSettings {
+    /// size of the whole terrain in pixels. Seamless tiles share their border pixels
+    pub fn world_size(&self) -> (usize, usize) {
+        let tiles_h = self.tiles_h as usize;
+        let tiles_v = self.tiles_v as usize;
+        (
+            tile_offset(tiles_h - 1, self.export_width, self.seamless) + self.export_width as usize,
+            tile_offset(tiles_v - 1, self.export_height, self.seamless)
+                + self.export_height as usize,
+        )
+    }
+    /// size of a preview of the terrain with the same proportions, max_size cells along its longest side
+    pub fn preview_size(&self, max_size: usize) -> (usize, usize) {
+        let (width, height) = self.world_size();
+        let scale = max_size as f32 / width.max(height) as f32;
+        // at least 2 cells to build the 3D mesh
+        (
+            ((width as f32 * scale).round() as usize).max(2),
+            ((height as f32 * scale).round() as usize).max(2),
+        )
+    }
+}
+
+/// estimate of the peak memory in bytes used to export the steps with these settings
+pub fn estimate_export_memory(steps: &[Step], export_data: &ExportSettings) -> usize {
+    let world_size = export_data.world_size();
+    let cells = world_size.0 * world_size.1 * std::mem::size_of::<f32>();
+    // 16 bits per pixel image buffer
+    let tile = export_data.export_width as usize * export_data.export_height as usize * 2;
+    if export_data.streaming {
+        let tile_rows = world_size.0 * export_data.export_height as usize;
+        let writing = tile_rows * std::mem::size_of::<f32>() + tile;
+        streaming::estimate_memory(world_size, steps, export_data.band_cells).max(writing)
+    } else {
+        let enabled = || steps.iter().filter(|s| !s.disabled);
+        // working buffer plus one copy for masked or blended steps
+        let buffers = if enabled().any(|s| s.mask.is_some() || s.is_blended()) {
+            2
+        } else {
+            1
+        };
+        let extra = enabled()
+            .map(|s| s.typ.extra_memory(world_size))
+            .max()
+            .unwrap_or(0);
+        (cells * buffers + extra).max(cells + tile)
+    }
+}
+
 pub fn export_heightmap(
     // random number generator's seed to use
     seed: u64,
     // list of generator steps with their configuration and optional masks
     steps: &[Step],
     // size and number of files to export, file name pattern
-    export_data: &PanelExport,
+    export_data: &ExportSettings,
     // channel to send feedback messages to the main thread
     tx: Sender<ThreadMessage>,
     // minimum amount of progress to report (below this value, the global %age won't change)
     min_progress_step: f32,
+    // stops the export as soon as possible, returning an error. Already written files are kept
+    cancel: &CancelToken,
+) -> Result<(), String> {
+    let world_size = export_data.world_size();
+    let steps = &with_step_ids(steps);
+    if export_data.streaming {
+        let (layer, extra_layers) = generate_layers(
+            seed,
+            world_size,
+            steps,
+            &export_data.file_path,
+            export_data.band_cells,
+            tx,
+            min_progress_step,
+            cancel,
+        )?;
+        let (min, max) = layer.as_ref().map_or((0.0, 0.0), |l| l.get_min_max());
+        // only keep in memory the rows of the current row of tiles
+        for ty in 0..export_data.tiles_v as usize {
+            if cancel.is_cancelled() {
+                return Err(CANCELLED.to_owned());
+            }
+            let offset_y = tile_offset(ty, export_data.export_height, export_data.seamless);
+            let rows = export_data.export_height as usize;
+            let band = match layer {
+                Some(ref layer) => layer.read_rows(offset_y, rows)?,
+                None => vec![0.0; world_size.0 * rows],
+            };
+            write_tile_row(export_data, "", ty, min, max, |x, y| {
+                band[x + y * world_size.0]
+            })?;
+            for (name, extra_layer) in extra_layers.iter() {
+                let band = extra_layer.read_rows(offset_y, rows)?;
+                write_tile_row(export_data, name, ty, 0.0, 1.0, |x, y| {
+                    band[x + y * world_size.0]
+                })?;
+            }
+        }
+    } else {
+        let mut wgen = WorldGenerator::new_lean(seed, world_size);
+        wgen.generate(steps, tx, min_progress_step, cancel);
+        if cancel.is_cancelled() {
+            return Err(CANCELLED.to_owned());
+        }
+        let (min, max) = wgen.get_min_max();
+        for ty in 0..export_data.tiles_v as usize {
+            if cancel.is_cancelled() {
+                return Err(CANCELLED.to_owned());
+            }
+            let offset_y = tile_offset(ty, export_data.export_height, export_data.seamless);
+            write_tile_row(export_data, "", ty, min, max, |x, y| {
+                wgen.combined_height(x, y + offset_y)
+            })?;
+            for extra_layer in wgen.extra_layers() {
+                write_tile_row(export_data, extra_layer.name, ty, 0.0, 1.0, |x, y| {
+                    extra_layer.values[x + (y + offset_y) * world_size.0]
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// position of a tile in the world
+fn tile_offset(tile: usize, tile_size: f32, seamless: bool) -> usize {
+    if seamless {
+        tile * (tile_size as usize - 1)
+    } else {
+        tile * tile_size as usize
+    }
+}
+
+/// write the tiles of the row ty. height returns the height at a world x and a y relative to the row of tiles.
+/// The files of an additional layer are suffixed by its name
+fn write_tile_row<F: Fn(usize, usize) -> f32 + Sync>(
+    export_data: &ExportSettings,
+    layer_name: &str,
+    ty: usize,
+    min: f32,
+    max: f32,
+    height: F,
 ) -> Result<(), String> {
     let file_width = export_data.export_width as usize;
     let file_height = export_data.export_height as usize;
-    let mut wgen = WorldGenerator::new(
-        seed,
-        (
-            (export_data.export_width * export_data.tiles_h) as usize,
-            (export_data.export_height * export_data.tiles_v) as usize,
-        ),
-    );
-    wgen.generate(steps, tx, min_progress_step);
-
-    let (min, max) = wgen.get_min_max();
-    let coef = if max - min > std::f32::EPSILON {
+    let coef = if max - min > f32::EPSILON {
         1.0 / (max - min)
     } else {
         1.0
     };
-
-    for ty in 0..export_data.tiles_v as usize {
-        for tx in 0..export_data.tiles_h as usize {
-            let offset_x = if export_data.seamless {
-                tx * (file_width - 1)
-            } else {
-                tx * file_width
-            };
-            let offset_y = if export_data.seamless {
-                ty * (file_height - 1)
-            } else {
-                ty * file_height
-            };
-            let path = format!(
+    for tx in 0..export_data.tiles_h as usize {
+        let offset_x = tile_offset(tx, export_data.export_width, export_data.seamless);
+        let path = if layer_name.is_empty() {
+            format!(
                 "{}_x{}_y{}.{}",
-                export_data.file_path,
-                tx,
-                ty,
-                export_data.file_type.to_string()
-            );
-            match export_data.file_type {
-                ExportFileType::PNG => write_png(
-                    file_width,
-                    file_height,
-                    offset_x,
-                    offset_y,
-                    &wgen,
-                    min,
-                    coef,
-                    &path,
-                )?,
-                ExportFileType::EXR => write_exr(
-                    file_width,
-                    file_height,
-                    offset_x,
-                    offset_y,
-                    &wgen,
-                    min,
-                    coef,
-                    &path,
-                )?,
-            }
+                export_data.file_path, tx, ty, export_data.file_type
+            )
+        } else {
+            format!(
+                "{}_{}_x{}_y{}.{}",
+                export_data.file_path, layer_name, tx, ty, export_data.file_type
+            )
+        };
+        let tile_height = |px: usize, py: usize| (height(px + offset_x, py) - min) * coef;
+        match export_data.file_type {
+            ExportFileType::Png => write_png(file_width, file_height, tile_height, &path)?,
+            ExportFileType::Exr => write_exr(file_width, file_height, tile_height, &path)?,
         }
     }
     Ok(())
 }
 
-fn write_png(
+fn write_png<F: Fn(usize, usize) -> f32>(
     file_width: usize,
     file_height: usize,
-    offset_x: usize,
-    offset_y: usize,
-    wgen: &WorldGenerator,
-    min: f32,
-    coef: f32,
+    height: F,
     path: &str,
 ) -> Result<(), String> {
     let mut buf = vec![0u8; file_width * file_height * 2];
     for py in 0..file_height {
         for px in 0..file_width {
-            let mut h = wgen.combined_height(px + offset_x, py + offset_y);
-            h = (h - min) * coef;
+            let h = height(px, py);
             let offset = (px + py * file_width) * 2;
             let pixel = (h * 65535.0) as u16;
             let upixel = pixel.to_ne_bytes();
@@ -105,7 +260,7 @@ fn write_png(
         }
     }
     image::save_buffer(
-        &Path::new(&path),
+        Path::new(&path),
         &buf,
         file_width as u32,
         file_height as u32,
@@ -114,25 +269,17 @@ fn write_png(
     .map_err(|e| format!("Error while saving {}: {}", &path, e))
 }
 
-fn write_exr(
+fn write_exr<F: Fn(usize, usize) -> f32 + Sync>(
     file_width: usize,
     file_height: usize,
-    offset_x: usize,
-    offset_y: usize,
-    wgen: &WorldGenerator,
-    min: f32,
-    coef: f32,
+    height: F,
     path: &str,
 ) -> Result<(), String> {
     use exr::prelude::*;
 
     let channel = SpecificChannels::new(
         (ChannelDescription::named("Y", SampleType::F16),),
-        |Vec2(px, py)| {
-            let h = wgen.combined_height(px + offset_x, py + offset_y);
-            let h = f16::from_f32((h - min) * coef);
-            (h,)
-        },
+        |Vec2(px, py)| (f16::from_f32(height(px, py)),),
     );
 
     Image::from_encoded_channels(
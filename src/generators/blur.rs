@@ -0,0 +1,203 @@
+#[cfg(feature = "gui")]
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    compute_rows_with_progress, get_min_max, Chunk, Generator, Locality, Progress, StepContext,
+};
+
+pub struct Blur;
+
+impl Generator for Blur {
+    type Conf = BlurConf;
+    fn name(&self) -> &'static str {
+        "Blur"
+    }
+    fn description(&self) -> &'static str {
+        "Smooth the terrain with a gaussian blur, optionally preserving the cliffs and ridges (bilateral filter)"
+    }
+    fn run(
+        &self,
+        _seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &BlurConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_blur(size, hmap, conf, progress);
+    }
+    fn extra_memory(&self, size: (usize, usize), _conf: &BlurConf) -> usize {
+        // the horizontally blurred heightmap
+        size.0 * size.1 * std::mem::size_of::<f32>()
+    }
+    fn locality(&self, size: (usize, usize), conf: &BlurConf) -> Locality {
+        Locality::Local(kernel_radius(blur_sigma(size, conf.radius)))
+    }
+    fn run_chunk(
+        &self,
+        _seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &BlurConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        blur(
+            chunk.size,
+            hmap,
+            blur_sigma(chunk.world_size, conf.radius),
+            edge_sigma(conf, (chunk.layer.min, chunk.layer.max)),
+            progress,
+        );
+        Ok(())
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut BlurConf) {
+        render_blur(ui, conf);
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlurConf {
+    /// radius of the blur in % of the world width
+    pub radius: f32,
+    /// bilateral filter : the cells much higher or lower than the blurred cell have less weight
+    pub edge_preserving: bool,
+    /// height difference in % of the terrain height range above which the cells are barely blurred together
+    pub edge_threshold: f32,
+}
+
+impl Default for BlurConf {
+    fn default() -> Self {
+        Self {
+            radius: 1.0,
+            edge_preserving: false,
+            edge_threshold: 5.0,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+fn render_blur(ui: &mut egui::Ui, conf: &mut BlurConf) {
+    ui.horizontal(|ui| {
+        ui.label("radius %")
+            .on_hover_text("radius of the blur in percent of the terrain width");
+        ui.add(
+            egui::DragValue::new(&mut conf.radius)
+                .speed(0.01)
+                .range(0.0..=10.0),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut conf.edge_preserving, "preserve edges")
+            .on_hover_text("don't blur the cliffs and ridges (bilateral filter)");
+        ui.label("threshold %").on_hover_text(
+            "height difference in percent of the terrain height above which the cells are not blurred together",
+        );
+        ui.add_enabled(
+            conf.edge_preserving,
+            egui::DragValue::new(&mut conf.edge_threshold)
+                .speed(0.1)
+                .range(0.1..=100.0),
+        );
+    });
+}
+
+pub fn gen_blur(size: (usize, usize), hmap: &mut [f32], conf: &BlurConf, progress: &mut Progress) {
+    let min_max = get_min_max(hmap);
+    blur(
+        size,
+        hmap,
+        blur_sigma(size, conf.radius),
+        edge_sigma(conf, min_max),
+        progress,
+    );
+}
+
+/// standard deviation in cells of the gaussian blur, radius being in % of the world width.
+/// It is not rounded so that the low resolution preview is blurred as much as the export
+pub(super) fn blur_sigma(size: (usize, usize), radius: f32) -> f32 {
+    radius * 0.01 * size.0 as f32 / 3.0
+}
+
+/// radius in cells of the kernel of a gaussian blur (3 standard deviations), at least 1 cell if sigma > 0
+pub(super) fn kernel_radius(sigma: f32) -> usize {
+    if sigma <= 0.0 {
+        return 0;
+    }
+    ((3.0 * sigma).ceil() as usize).max(1)
+}
+
+/// standard deviation of the bilateral filter height weights for a terrain whose heights are in the min_max range
+fn edge_sigma(conf: &BlurConf, (min, max): (f32, f32)) -> Option<f32> {
+    conf.edge_preserving
+        .then(|| (conf.edge_threshold * 0.01 * (max - min)).max(f32::EPSILON))
+}
+
+/// weighted average of the cells -radius..=radius around a center cell.
+/// sample returns None for the cells outside the heightmap
+fn convolve(
+    weights: &[f32],
+    edge_sigma: Option<f32>,
+    center: f32,
+    sample: impl Fn(i32) -> Option<f32>,
+) -> f32 {
+    let radius = weights.len() as i32 - 1;
+    let mut sum = 0.0;
+    let mut weight_sum = 0.0;
+    for i in -radius..=radius {
+        let Some(h) = sample(i) else {
+            continue;
+        };
+        let mut weight = weights[i.unsigned_abs() as usize];
+        if let Some(sigma) = edge_sigma {
+            let dh = h - center;
+            weight *= (-dh * dh / (2.0 * sigma * sigma)).exp();
+        }
+        sum += h * weight;
+        weight_sum += weight;
+    }
+    sum / weight_sum
+}
+
+/// separable gaussian blur of a heightmap with a standard deviation of sigma cells.
+/// With an edge_sigma, the weights also decrease with the height difference (bilateral filter).
+/// The bilateral filter is also computed as a horizontal then a vertical pass, which is much faster than
+/// a 2D kernel on large radii but only approximates it : the edges that are not aligned with the axes are
+/// slightly less preserved
+pub(super) fn blur(
+    size: (usize, usize),
+    hmap: &mut [f32],
+    sigma: f32,
+    edge_sigma: Option<f32>,
+    progress: &mut Progress,
+) {
+    let radius = kernel_radius(sigma);
+    if radius == 0 {
+        return;
+    }
+    let weights: Vec<f32> = (0..=radius)
+        .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let (width, height) = (size.0 as i32, size.1 as i32);
+    let mut horizontal = vec![0.0; hmap.len()];
+    compute_rows_with_progress(size.0, &mut horizontal, &progress.part(0.0, 0.5), |x, y| {
+        let row = &hmap[y * size.0..(y + 1) * size.0];
+        convolve(&weights, edge_sigma, row[x], |i| {
+            let sx = x as i32 + i;
+            (sx >= 0 && sx < width).then(|| row[sx as usize])
+        })
+    });
+    if progress.cancelled() {
+        return;
+    }
+    compute_rows_with_progress(size.0, hmap, &progress.part(0.5, 0.5), |x, y| {
+        convolve(&weights, edge_sigma, horizontal[x + y * size.0], |i| {
+            let sy = y as i32 + i;
+            (sy >= 0 && sy < height).then(|| horizontal[x + sy as usize * size.0])
+        })
+    });
+    progress.report(1.0);
+}
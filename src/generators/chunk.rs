@@ -0,0 +1,86 @@
+use super::{get_min_max, reference_size, resample};
+
+/// how a step can be computed by the streaming exporter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locality {
+    /// the step needs the whole heightmap. The streaming exporter loads the layer in memory to compute it
+    Global,
+    /// a cell only depends on the previous layer cells within this distance (in cells)
+    /// and on the previous layer [`LayerInfo`]. The streaming exporter computes the step by chunks
+    Local(usize),
+}
+
+/// part of the world computed by a local step
+pub struct Chunk<'a> {
+    /// size of the whole world in cells
+    pub world_size: (usize, usize),
+    /// world coordinates of the chunk top-left cell, halo included
+    pub pos: (usize, usize),
+    /// size of the chunk in cells, halo included
+    pub size: (usize, usize),
+    /// information about the whole previous layer
+    pub layer: &'a LayerInfo,
+    /// the data returned by the step's prepare function
+    pub prepared: &'a [f32],
+}
+
+/// information about a whole layer, for the steps that need more than the cells of the chunk
+pub struct LayerInfo {
+    /// size of the layer in cells
+    pub size: (usize, usize),
+    /// minimum height
+    pub min: f32,
+    /// maximum height
+    pub max: f32,
+    /// number of cells in each of 256 height ranges between min and max
+    pub histogram: [usize; 256],
+    /// the layer resampled to the reference grid
+    pub reference: Vec<f32>,
+}
+
+impl LayerInfo {
+    /// information about a layer held in memory
+    pub fn new(size: (usize, usize), hmap: &[f32]) -> Self {
+        let (min, max) = get_min_max(hmap);
+        let mut histogram = [0; 256];
+        height_histogram(hmap, min, max, &mut histogram);
+        Self {
+            size,
+            min,
+            max,
+            histogram,
+            reference: resample(hmap, size, reference_size(size)),
+        }
+    }
+    /// information about a layer where all heights are 0.0
+    pub fn zero(size: (usize, usize)) -> Self {
+        let ref_size = reference_size(size);
+        let mut histogram = [0; 256];
+        histogram[0] = size.0 * size.1;
+        Self {
+            size,
+            min: 0.0,
+            max: 0.0,
+            histogram,
+            reference: vec![0.0; ref_size.0 * ref_size.1],
+        }
+    }
+    /// height normalized in the range 0.0..1.0, with the same rounding as [`super::normalize`]
+    pub fn normalized(&self, h: f32) -> f32 {
+        let invmax = if self.min == self.max {
+            0.0
+        } else {
+            1.0 / (self.max - self.min)
+        };
+        (h - self.min) * invmax
+    }
+}
+
+/// count the heights of each of 256 ranges between min and max
+pub fn height_histogram(hmap: &[f32], min: f32, max: f32, histogram: &mut [usize; 256]) {
+    let coef = if min == max { 0.0 } else { 1.0 / (max - min) };
+    for h in hmap {
+        let ih = (((h - min) * coef) * 255.0) as usize;
+        histogram[ih.min(255)] += 1;
+    }
+}
@@ -0,0 +1,309 @@
+#[cfg(feature = "gui")]
+use eframe::egui;
+use rand::{prelude::*, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    interpolate, reference_size, resample, Chunk, Generator, LayerInfo, Locality, Progress,
+    StepContext,
+};
+
+/// distance from the center where the ejecta stop, in crater radius
+const EJECTA_RANGE: f32 = 2.0;
+
+pub struct Craters;
+
+impl Generator for Craters {
+    type Conf = CratersConf;
+    fn name(&self) -> &'static str {
+        "Craters"
+    }
+    fn description(&self) -> &'static str {
+        "Add impact craters with raised rims and ejecta, later impacts overwriting older ones"
+    }
+    fn run(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &CratersConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_craters(seed, size, hmap, conf, progress);
+    }
+    fn extra_memory(&self, size: (usize, usize), _conf: &CratersConf) -> usize {
+        let ref_size = reference_size(size);
+        ref_size.0 * ref_size.1 * std::mem::size_of::<f32>()
+    }
+    fn locality(&self, _size: (usize, usize), _conf: &CratersConf) -> Locality {
+        Locality::Local(0)
+    }
+    fn prepare(
+        &self,
+        seed: u64,
+        layer: &LayerInfo,
+        conf: &CratersConf,
+        _ctx: &StepContext,
+        _progress: &mut Progress,
+    ) -> Vec<f32> {
+        let craters = crater_list(seed, layer.size, conf);
+        base_heights(layer.size, layer.reference.clone(), &craters)
+    }
+    fn run_chunk(
+        &self,
+        seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &CratersConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        let craters = crater_list(seed, chunk.world_size, conf);
+        craters_chunk(
+            &craters,
+            chunk.prepared,
+            chunk.world_size,
+            chunk.pos,
+            chunk.size,
+            hmap,
+            progress,
+        );
+        Ok(())
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut CratersConf) {
+        render_craters(ui, conf);
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CratersConf {
+    pub count: usize,
+    /// smallest crater radius in % of the world width
+    pub min_radius: f32,
+    /// biggest crater radius in % of the world width
+    pub max_radius: f32,
+    /// the probability of a radius r is proportional to r^-size_exponent. Bigger values give more small craters
+    pub size_exponent: f32,
+    /// depth of the biggest craters below the terrain. Smaller craters are proportionally shallower
+    pub depth: f32,
+    /// height of the biggest craters rim above the terrain
+    pub rim_height: f32,
+}
+
+impl Default for CratersConf {
+    fn default() -> Self {
+        Self {
+            count: 100,
+            min_radius: 1.0,
+            max_radius: 10.0,
+            size_exponent: 2.0,
+            depth: 0.1,
+            rim_height: 0.03,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+fn render_craters(ui: &mut egui::Ui, conf: &mut CratersConf) {
+    ui.horizontal(|ui| {
+        ui.label("count");
+        ui.add(
+            egui::DragValue::new(&mut conf.count)
+                .speed(1.0)
+                .range(1.0..=5000.0),
+        );
+        ui.label("radius %")
+            .on_hover_text("smallest and biggest radius in percent of the terrain width");
+        ui.add(
+            egui::DragValue::new(&mut conf.min_radius)
+                .speed(0.1)
+                .range(0.1..=conf.max_radius),
+        );
+        ui.add(
+            egui::DragValue::new(&mut conf.max_radius)
+                .speed(0.1)
+                .range(conf.min_radius..=50.0),
+        );
+        ui.label("size exponent")
+            .on_hover_text("increase to get more small craters");
+        ui.add(
+            egui::DragValue::new(&mut conf.size_exponent)
+                .speed(0.01)
+                .range(0.0..=5.0),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("depth")
+            .on_hover_text("depth of the biggest craters");
+        ui.add(
+            egui::DragValue::new(&mut conf.depth)
+                .speed(0.01)
+                .range(0.0..=1.0),
+        );
+        ui.label("rim height")
+            .on_hover_text("height of the biggest craters rim");
+        ui.add(
+            egui::DragValue::new(&mut conf.rim_height)
+                .speed(0.01)
+                .range(0.0..=1.0),
+        );
+    });
+}
+
+pub fn gen_craters(
+    seed: u64,
+    size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &CratersConf,
+    progress: &mut Progress,
+) {
+    let craters = crater_list(seed, size, conf);
+    // computed on the reference grid like the exporter, so that the preview looks like the export
+    let bases = base_heights(size, resample(hmap, size, reference_size(size)), &craters);
+    craters_chunk(&craters, &bases, size, (0, 0), size, hmap, progress);
+}
+
+/// an impact, in world cells and height units
+struct Crater {
+    x: f32,
+    y: f32,
+    radius: f32,
+    depth: f32,
+    rim_height: f32,
+}
+
+impl Crater {
+    /// height of a cell at distance d (in radius) from the center, relative to the crater base height
+    fn profile(&self, d: f32) -> f32 {
+        if d < 1.0 {
+            // parabolic bowl rising to the rim
+            -self.depth + (self.depth + self.rim_height) * d * d
+        } else {
+            // ejecta decreasing outside the rim
+            let t = (EJECTA_RANGE - d) / (EJECTA_RANGE - 1.0);
+            self.rim_height * t * t
+        }
+    }
+    /// new height of a cell at distance d (in radius) from the center, whose current height is h
+    fn height(&self, base: f32, h: f32, d: f32) -> f32 {
+        if d < 1.0 {
+            // the impact replaces the older terrain
+            base + self.profile(d)
+        } else {
+            // the older terrain is progressively covered by the ejecta
+            let t = (EJECTA_RANGE - d) / (EJECTA_RANGE - 1.0);
+            h + (base - h) * t * t + self.profile(d)
+        }
+    }
+}
+
+/// the craters from the oldest to the newest
+fn crater_list(seed: u64, size: (usize, usize), conf: &CratersConf) -> Vec<Crater> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let min_radius = conf.min_radius.min(conf.max_radius);
+    let max_radius = conf.max_radius;
+    (0..conf.count)
+        .map(|_| {
+            let u: f32 = rng.random_range(0.0..1.0);
+            // inverse of the cumulative distribution of the power law truncated to min_radius..max_radius
+            let radius = if min_radius == max_radius {
+                min_radius
+            } else if (conf.size_exponent - 1.0).abs() < 1e-3 {
+                min_radius * (max_radius / min_radius).powf(u)
+            } else {
+                let e = 1.0 - conf.size_exponent;
+                (min_radius.powf(e) + u * (max_radius.powf(e) - min_radius.powf(e))).powf(1.0 / e)
+            };
+            let x = rng.random_range(0.0..size.0 as f32);
+            let y = rng.random_range(0.0..size.1 as f32);
+            let scale = radius / max_radius;
+            Crater {
+                x,
+                y,
+                radius: radius * size.0 as f32 / 100.0,
+                depth: conf.depth * scale,
+                rim_height: conf.rim_height * scale,
+            }
+        })
+        .collect()
+}
+
+/// apply the craters to the reference grid heightmap of a world of the given size
+/// and return the height of the terrain at each crater center before its impact
+fn base_heights(size: (usize, usize), mut reference: Vec<f32>, craters: &[Crater]) -> Vec<f32> {
+    let ref_size = reference_size(size);
+    let coef = ref_size.0 as f32 / size.0 as f32;
+    let mut bases = Vec::with_capacity(craters.len());
+    for crater in craters.iter() {
+        let ref_crater = Crater {
+            x: crater.x * coef,
+            y: crater.y * coef,
+            radius: crater.radius * coef,
+            ..*crater
+        };
+        let base = interpolate(
+            &reference,
+            (ref_crater.x - 0.5).clamp(0.0, (ref_size.0 - 1) as f32),
+            (ref_crater.y - 0.5).clamp(0.0, (ref_size.1 - 1) as f32),
+            ref_size,
+        );
+        apply_crater(
+            &ref_crater,
+            base,
+            ref_size,
+            (0, 0),
+            ref_size,
+            &mut reference,
+        );
+        bases.push(base);
+    }
+    bases
+}
+
+/// apply the craters of a world of the given size to the chunk at pos of size chunk_size
+fn craters_chunk(
+    craters: &[Crater],
+    bases: &[f32],
+    size: (usize, usize),
+    pos: (usize, usize),
+    chunk_size: (usize, usize),
+    hmap: &mut [f32],
+    progress: &mut Progress,
+) {
+    for (i, (crater, base)) in craters.iter().zip(bases.iter()).enumerate() {
+        apply_crater(crater, *base, size, pos, chunk_size, hmap);
+        progress.report(i as f32 / craters.len() as f32);
+        if progress.cancelled() {
+            return;
+        }
+    }
+}
+
+fn apply_crater(
+    crater: &Crater,
+    base: f32,
+    size: (usize, usize),
+    pos: (usize, usize),
+    chunk_size: (usize, usize),
+    hmap: &mut [f32],
+) {
+    let range = crater.radius * EJECTA_RANGE;
+    let minx = ((crater.x - range).max(0.0) as usize).max(pos.0);
+    let maxx = ((crater.x + range).min(size.0 as f32) as usize).min(pos.0 + chunk_size.0);
+    let miny = ((crater.y - range).max(0.0) as usize).max(pos.1);
+    let maxy = ((crater.y + range).min(size.1 as f32) as usize).min(pos.1 + chunk_size.1);
+    for py in miny..maxy {
+        let dy = py as f32 + 0.5 - crater.y;
+        for px in minx..maxx {
+            let dx = px as f32 + 0.5 - crater.x;
+            let d = (dx * dx + dy * dy).sqrt() / crater.radius;
+            if d < EJECTA_RANGE {
+                let off = px - pos.0 + (py - pos.1) * chunk_size.0;
+                hmap[off] = crater.height(base, hmap[off], d);
+            }
+        }
+    }
+}
@@ -0,0 +1,290 @@
+#[cfg(feature = "gui")]
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use super::{get_min_max, Chunk, Generator, Locality, Progress, StepContext};
+
+/// maximum distance in pixels between the mouse cursor and a control point to drag it
+#[cfg(feature = "gui")]
+const PICK_DISTANCE: f32 = 8.0;
+
+pub struct Curve;
+
+impl Generator for Curve {
+    type Conf = CurveConf;
+    fn name(&self) -> &'static str {
+        "Curve"
+    }
+    fn description(&self) -> &'static str {
+        "Remap the heights with a spline curve to shape plains, plateaus and peaks"
+    }
+    fn run(
+        &self,
+        _seed: u64,
+        _size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &CurveConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_curve(hmap, conf, progress);
+    }
+    fn locality(&self, _size: (usize, usize), _conf: &CurveConf) -> Locality {
+        Locality::Local(0)
+    }
+    fn run_chunk(
+        &self,
+        _seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &CurveConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        curve(hmap, (chunk.layer.min, chunk.layer.max), conf, progress);
+        Ok(())
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut CurveConf) {
+        render_curve(ui, conf);
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CurveConf {
+    /// control points (input height, output height) sorted by input height, both normalized in the 0.0-1.0 range
+    pub points: Vec<(f32, f32)>,
+}
+
+impl Default for CurveConf {
+    fn default() -> Self {
+        Self {
+            points: vec![(0.0, 0.0), (1.0, 1.0)],
+        }
+    }
+}
+
+/// monotone cubic interpolation of the control points (Fritsch, Carlson 1980).
+/// The curve doesn't overshoot the control points, so flat parts stay flat
+struct Spline {
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    /// tangent at each control point
+    tangents: Vec<f32>,
+}
+
+impl Spline {
+    fn new(points: &[(f32, f32)]) -> Self {
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let xs: Vec<f32> = points.iter().map(|p| p.0).collect();
+        let ys: Vec<f32> = points.iter().map(|p| p.1).collect();
+        let n = xs.len();
+        // slope of each segment
+        let slopes: Vec<f32> = (1..n)
+            .map(|i| {
+                let dx = xs[i] - xs[i - 1];
+                if dx > f32::EPSILON {
+                    (ys[i] - ys[i - 1]) / dx
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let mut tangents = vec![0.0; n];
+        for i in 0..n {
+            tangents[i] = if n < 2 {
+                0.0
+            } else if i == 0 {
+                slopes[0]
+            } else if i == n - 1 {
+                slopes[n - 2]
+            } else if slopes[i - 1] * slopes[i] <= 0.0 {
+                // local extremum
+                0.0
+            } else {
+                (slopes[i - 1] + slopes[i]) * 0.5
+            };
+        }
+        // limit the tangents to keep the curve monotone on each segment
+        for (i, slope) in slopes.iter().enumerate() {
+            if *slope == 0.0 {
+                tangents[i] = 0.0;
+                tangents[i + 1] = 0.0;
+                continue;
+            }
+            let a = tangents[i] / slope;
+            let b = tangents[i + 1] / slope;
+            let len = (a * a + b * b).sqrt();
+            if len > 3.0 {
+                tangents[i] = 3.0 * a / len * slope;
+                tangents[i + 1] = 3.0 * b / len * slope;
+            }
+        }
+        Self { xs, ys, tangents }
+    }
+    fn value(&self, x: f32) -> f32 {
+        let n = self.xs.len();
+        if n == 0 {
+            return x;
+        }
+        if x <= self.xs[0] {
+            return self.ys[0];
+        }
+        if x >= self.xs[n - 1] {
+            return self.ys[n - 1];
+        }
+        // segment i..i+1 containing x
+        let i = self.xs.partition_point(|px| *px <= x) - 1;
+        let dx = self.xs[i + 1] - self.xs[i];
+        let t = (x - self.xs[i]) / dx;
+        let (t2, t3) = (t * t, t * t * t);
+        // cubic hermite basis
+        (2.0 * t3 - 3.0 * t2 + 1.0) * self.ys[i]
+            + (t3 - 2.0 * t2 + t) * dx * self.tangents[i]
+            + (-2.0 * t3 + 3.0 * t2) * self.ys[i + 1]
+            + (t3 - t2) * dx * self.tangents[i + 1]
+    }
+}
+
+#[cfg(feature = "gui")]
+fn render_curve(ui: &mut egui::Ui, conf: &mut CurveConf) {
+    use egui::{pos2, vec2, Color32, PointerButton, Pos2, Rect, Sense, Shape, Stroke};
+
+    let width = ui.available_width().clamp(100.0, 300.0);
+    let (response, painter) =
+        ui.allocate_painter(vec2(width, width * 0.6), Sense::click_and_drag());
+    let rect = response.rect;
+    let to_screen = |(x, y): (f32, f32)| {
+        pos2(
+            rect.left() + x * rect.width(),
+            rect.bottom() - y * rect.height(),
+        )
+    };
+    let from_screen = |p: Pos2| {
+        (
+            ((p.x - rect.left()) / rect.width()).clamp(0.0, 1.0),
+            ((rect.bottom() - p.y) / rect.height()).clamp(0.0, 1.0),
+        )
+    };
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+    // histogram of the step input heights, on a square root scale so that the small counts are visible
+    let histogram = ui.data(|d| d.get_temp::<[usize; 256]>(super::input_histogram_id()));
+    if let Some(histogram) = histogram {
+        let max = histogram.iter().copied().max().unwrap_or(0).max(1) as f32;
+        let bar_width = rect.width() / histogram.len() as f32;
+        for (i, count) in histogram.iter().enumerate() {
+            if *count > 0 {
+                let bar_height = (*count as f32 / max).sqrt() * rect.height();
+                let left = rect.left() + i as f32 * bar_width;
+                painter.rect_filled(
+                    Rect::from_min_max(
+                        pos2(left, rect.bottom() - bar_height),
+                        pos2(left + bar_width, rect.bottom()),
+                    ),
+                    0.0,
+                    Color32::from_gray(60),
+                );
+            }
+        }
+    }
+    // identity diagonal
+    painter.line_segment(
+        [rect.left_bottom(), rect.right_top()],
+        Stroke::new(1.0, Color32::from_gray(80)),
+    );
+    // which control point is dragged, kept between frames
+    let dragged_id = response.id.with("dragged point");
+    let nearest = |pos: Pos2, points: &[(f32, f32)]| {
+        points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i, to_screen(*p).distance(pos)))
+            .filter(|(_, d)| *d < PICK_DISTANCE)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    };
+    let last = conf.points.len().saturating_sub(1);
+    if let Some(pos) = response.interact_pointer_pos() {
+        if response.drag_started_by(PointerButton::Primary) || response.clicked() {
+            let picked = nearest(pos, &conf.points).or_else(|| {
+                // add a control point under the cursor
+                let p = from_screen(pos);
+                let i = conf.points.partition_point(|cp| cp.0 < p.0);
+                conf.points.insert(i, p);
+                Some(i)
+            });
+            ui.data_mut(|d| d.insert_temp(dragged_id, picked));
+        }
+        if response.dragged_by(PointerButton::Primary) {
+            if let Some(Some(i)) = ui.data(|d| d.get_temp::<Option<usize>>(dragged_id)) {
+                let last = conf.points.len() - 1;
+                let (mut x, y) = from_screen(pos);
+                // the points stay sorted and the ends cover the whole height range
+                if i == 0 {
+                    x = 0.0;
+                } else if i == last {
+                    x = 1.0;
+                } else {
+                    x = x.clamp(conf.points[i - 1].0, conf.points[i + 1].0);
+                }
+                conf.points[i] = (x, y);
+            }
+        }
+    }
+    if response.drag_stopped() {
+        ui.data_mut(|d| d.remove::<Option<usize>>(dragged_id));
+    }
+    if response.secondary_clicked() {
+        // remove a control point, except the ends
+        if let Some(i) = response
+            .interact_pointer_pos()
+            .and_then(|pos| nearest(pos, &conf.points))
+        {
+            if i > 0 && i < last {
+                conf.points.remove(i);
+            }
+        }
+    }
+    let spline = Spline::new(&conf.points);
+    let samples = rect.width() as usize;
+    let line: Vec<Pos2> = (0..=samples)
+        .map(|i| {
+            let x = i as f32 / samples as f32;
+            to_screen((x, spline.value(x).clamp(0.0, 1.0)))
+        })
+        .collect();
+    painter.add(Shape::line(
+        line,
+        Stroke::new(2.0, ui.visuals().strong_text_color()),
+    ));
+    for p in conf.points.iter() {
+        painter.circle_filled(to_screen(*p), 4.0, Color32::from_rgb(255, 160, 0));
+    }
+    response.on_hover_text(
+        "x : input height, y : output height\nclick or drag to add or move a point, right click to remove it",
+    );
+    if ui.button("Reset").clicked() {
+        *conf = CurveConf::default();
+    }
+}
+
+pub fn gen_curve(hmap: &mut [f32], conf: &CurveConf, progress: &mut Progress) {
+    let min_max = get_min_max(hmap);
+    curve(hmap, min_max, conf, progress);
+}
+
+/// remap the heights in the min_max range, keeping this range
+fn curve(hmap: &mut [f32], (min, max): (f32, f32), conf: &CurveConf, progress: &mut Progress) {
+    if max <= min {
+        return;
+    }
+    let spline = Spline::new(&conf.points);
+    let range = max - min;
+    for h in hmap.iter_mut() {
+        let t = (*h - min) / range;
+        *h = min + spline.value(t) * range;
+    }
+    progress.report(1.0);
+}
@@ -0,0 +1,225 @@
+#[cfg(feature = "gui")]
+use eframe::egui;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "gui")]
+use super::noise::render_noise;
+use super::noise::{noise_fn, noise_value};
+use super::{interpolate, Chunk, Generator, Locality, NoiseConf, Progress, StepContext};
+
+pub struct DomainWarp;
+
+impl Generator for DomainWarp {
+    type Conf = DomainWarpConf;
+    fn name(&self) -> &'static str {
+        "DomainWarp"
+    }
+    fn description(&self) -> &'static str {
+        "Displace the terrain or a noise by a noise vector field, giving a swirling eroded look"
+    }
+    fn run(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &DomainWarpConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_domain_warp(seed, size, hmap, conf, progress);
+    }
+    fn is_source(&self, conf: &DomainWarpConf) -> bool {
+        conf.source == WarpSource::Noise
+    }
+    fn extra_memory(&self, size: (usize, usize), conf: &DomainWarpConf) -> usize {
+        match conf.source {
+            // copy of the unwarped heightmap
+            WarpSource::Heightmap => size.0 * size.1 * std::mem::size_of::<f32>(),
+            WarpSource::Noise => 0,
+        }
+    }
+    fn locality(&self, size: (usize, usize), conf: &DomainWarpConf) -> Locality {
+        match conf.source {
+            // one more cell for the bilinear interpolation
+            WarpSource::Heightmap => {
+                Locality::Local(max_displacement(size, conf).ceil() as usize + 1)
+            }
+            WarpSource::Noise => Locality::Local(0),
+        }
+    }
+    fn run_chunk(
+        &self,
+        seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &DomainWarpConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        domain_warp(
+            seed,
+            chunk.world_size,
+            chunk.pos,
+            chunk.size,
+            hmap,
+            conf,
+            progress,
+        );
+        Ok(())
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut DomainWarpConf) {
+        render_domain_warp(ui, conf);
+    }
+}
+
+/// what is displaced by the warp field
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum WarpSource {
+    /// the heightmap computed by the previous steps
+    #[default]
+    Heightmap,
+    /// a noise added to the heightmap, like the Noise step
+    Noise,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DomainWarpConf {
+    pub source: WarpSource,
+    /// maximum displacement in % of the world width
+    pub strength: f32,
+    /// number of warp field features across the world width
+    pub frequency: f32,
+    /// octaves of the warp field
+    pub octaves: f32,
+    /// the warped noise when the source is Noise
+    pub noise: NoiseConf,
+}
+
+impl Default for DomainWarpConf {
+    fn default() -> Self {
+        Self {
+            source: WarpSource::Heightmap,
+            strength: 5.0,
+            frequency: 4.0,
+            octaves: 4.0,
+            noise: NoiseConf::default(),
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+fn render_domain_warp(ui: &mut egui::Ui, conf: &mut DomainWarpConf) {
+    ui.horizontal(|ui| {
+        ui.label("warp");
+        ui.radio_value(&mut conf.source, WarpSource::Heightmap, "heightmap");
+        ui.radio_value(&mut conf.source, WarpSource::Noise, "noise");
+    });
+    ui.horizontal(|ui| {
+        ui.label("strength %")
+            .on_hover_text("maximum displacement in percent of the terrain width");
+        ui.add(
+            egui::DragValue::new(&mut conf.strength)
+                .speed(0.1)
+                .range(0.0..=50.0),
+        );
+        ui.label("frequency")
+            .on_hover_text("number of swirls across the terrain width");
+        ui.add(
+            egui::DragValue::new(&mut conf.frequency)
+                .speed(0.1)
+                .range(0.1..=100.0),
+        );
+        ui.label("octaves");
+        ui.add(
+            egui::DragValue::new(&mut conf.octaves)
+                .speed(0.5)
+                .range(1.0..=Fbm::<Perlin>::MAX_OCTAVES as f32),
+        );
+    });
+    if conf.source == WarpSource::Noise {
+        render_noise(ui, &mut conf.noise);
+    }
+}
+
+pub fn gen_domain_warp(
+    seed: u64,
+    size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &DomainWarpConf,
+    progress: &mut Progress,
+) {
+    domain_warp(seed, size, (0, 0), size, hmap, conf, progress);
+}
+
+/// maximum displacement in cells
+fn max_displacement(size: (usize, usize), conf: &DomainWarpConf) -> f32 {
+    conf.strength * 0.01 * size.0 as f32
+}
+
+/// one component of the warp field
+fn warp_field(seed: u64, conf: &DomainWarpConf) -> Fbm<Perlin> {
+    Fbm::<Perlin>::new(seed as u32).set_octaves(conf.octaves as usize)
+}
+
+/// warp the chunk at pos of size chunk_size of a world of the given size
+fn domain_warp(
+    seed: u64,
+    size: (usize, usize),
+    pos: (usize, usize),
+    chunk_size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &DomainWarpConf,
+    progress: &mut Progress,
+) {
+    // the unwarped heightmap, sampled at the displaced positions
+    let src = (conf.source == WarpSource::Heightmap).then(|| hmap.to_vec());
+    let src = src.as_deref();
+    let amplitude = max_displacement(size, conf);
+    // same unit on both axes so that rectangular worlds aren't stretched
+    let coef = conf.frequency / size.0 as f32;
+    let num_threads = num_cpus::get();
+    std::thread::scope(|s| {
+        let size_per_job = chunk_size.1.div_ceil(num_threads);
+        for (i, chunk) in hmap.chunks_mut(size_per_job * chunk_size.0).enumerate() {
+            let mut progress = progress.clone();
+            s.spawn(move || {
+                // the warped noise uses the step seed so that a null strength gives the Noise step terrain
+                let noise = (conf.source == WarpSource::Noise).then(|| noise_fn(seed, &conf.noise));
+                let warp_x = warp_field(seed.wrapping_add(1), conf);
+                let warp_y = warp_field(seed.wrapping_add(2), conf);
+                let yoffset = pos.1 + i * size_per_job;
+                for (y, row) in chunk.chunks_mut(chunk_size.0).enumerate() {
+                    if progress.cancelled() {
+                        return;
+                    }
+                    let wy = (y + yoffset) as f32;
+                    for (x, cell) in row.iter_mut().enumerate() {
+                        let wx = (x + pos.0) as f32;
+                        let p = [(wx * coef) as f64, (wy * coef) as f64];
+                        // clamped so that the displacement never exceeds the halo
+                        let dx = warp_x.get(p).clamp(-1.0, 1.0) as f32 * amplitude;
+                        let dy = warp_y.get(p).clamp(-1.0, 1.0) as f32 * amplitude;
+                        if let Some(src) = src {
+                            let sx = (wx + dx).clamp(0.0, (size.0 - 1) as f32) - pos.0 as f32;
+                            let sy = (wy + dy).clamp(0.0, (size.1 - 1) as f32) - pos.1 as f32;
+                            *cell = interpolate(
+                                src,
+                                sx.clamp(0.0, (chunk_size.0 - 1) as f32),
+                                sy.clamp(0.0, (chunk_size.1 - 1) as f32),
+                                chunk_size,
+                            );
+                        } else if let Some(noise) = &noise {
+                            *cell += noise_value(&**noise, &conf.noise, size.0, wx + dx, wy + dy);
+                        }
+                    }
+                    if i == 0 {
+                        progress.report((y + 1) as f32 / size_per_job as f32);
+                    }
+                }
+            });
+        }
+    });
+}
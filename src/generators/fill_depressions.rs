@@ -0,0 +1,279 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+#[cfg(feature = "gui")]
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use super::{ExtraLayer, Generator, Progress, StepContext, DIRX, DIRY};
+
+/// DIRX/DIRY index of the neighbours, starting with the right neighbour and turning counter-clockwise (y goes down)
+const NEIGHBOURS: [usize; 8] = [5, 3, 2, 1, 4, 6, 7, 8];
+
+pub struct FillDepressions;
+
+impl Generator for FillDepressions {
+    type Conf = FillDepressionsConf;
+    fn name(&self) -> &'static str {
+        "FillDepressions"
+    }
+    fn description(&self) -> &'static str {
+        "Fill the pits and basins that trap water so that it can flow to the terrain border, optionally keeping the big ones as flat lakes exported as a water mask"
+    }
+    fn run(
+        &self,
+        _seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &FillDepressionsConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_fill_depressions(size, hmap, conf, progress);
+    }
+    fn run_layers(
+        &self,
+        _seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &FillDepressionsConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Vec<ExtraLayer> {
+        let lakes = gen_fill_depressions(size, hmap, conf, progress);
+        if conf.lakes {
+            vec![ExtraLayer {
+                name: "lakes",
+                values: lakes,
+            }]
+        } else {
+            Vec::new()
+        }
+    }
+    fn extra_memory(&self, size: (usize, usize), _conf: &FillDepressionsConf) -> usize {
+        // filled heights, flood queue and lakes mask
+        size.0 * size.1 * 28
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut FillDepressionsConf) {
+        render_fill_depressions(ui, conf);
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FillDepressionsConf {
+    /// slope of the filled areas towards their outlet, in height units per % of the world width. 0.0 gives flat areas
+    pub epsilon: f32,
+    /// keep the big depressions as flat lake surfaces
+    pub lakes: bool,
+    /// minimum area of a lake in % of the world area. Smaller depressions are filled
+    pub min_lake_area: f32,
+}
+
+impl Default for FillDepressionsConf {
+    fn default() -> Self {
+        Self {
+            epsilon: 0.0,
+            lakes: false,
+            min_lake_area: 0.1,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+fn render_fill_depressions(ui: &mut egui::Ui, conf: &mut FillDepressionsConf) {
+    ui.horizontal(|ui| {
+        ui.label("epsilon").on_hover_text(
+            "slope of the filled areas so that the water flows out of them. 0 : flat areas",
+        );
+        ui.add(
+            egui::DragValue::new(&mut conf.epsilon)
+                .speed(0.0001)
+                .range(0.0..=0.01),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut conf.lakes, "lakes")
+            .on_hover_text("keep the big depressions as flat lakes, exported as a water mask");
+        ui.label("min area %")
+            .on_hover_text("minimum area of a lake in percent of the terrain area");
+        ui.add_enabled(
+            conf.lakes,
+            egui::DragValue::new(&mut conf.min_lake_area)
+                .speed(0.01)
+                .range(0.0..=50.0),
+        );
+    });
+}
+
+/// fill the depressions and return the lakes mask, 1.0 on the lakes and 0.0 elsewhere
+pub fn gen_fill_depressions(
+    size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &FillDepressionsConf,
+    progress: &mut Progress,
+) -> Vec<f32> {
+    let cells = size.0 * size.1;
+    let mut lakes = vec![0.0; cells];
+    // same slope whatever the heightmap resolution
+    let epsilon = conf.epsilon * 100.0 / size.0 as f32;
+    if !conf.lakes {
+        let filled = priority_flood(size, hmap, epsilon, |_| false, progress);
+        hmap.copy_from_slice(&filled);
+        return lakes;
+    }
+    // the lakes surfaces are flat
+    let flat = priority_flood(size, hmap, 0.0, |_| false, &mut progress.part(0.0, 0.4));
+    if progress.cancelled() {
+        return lakes;
+    }
+    let min_cells = (conf.min_lake_area * 0.01 * cells as f32).max(1.0) as usize;
+    find_lakes(size, hmap, &flat, min_cells, &mut lakes);
+    for ((h, flat), lake) in hmap.iter_mut().zip(flat.iter()).zip(lakes.iter()) {
+        if *lake > 0.0 {
+            *h = *flat;
+        }
+    }
+    progress.report(0.5);
+    // the smaller depressions drain into the lakes
+    let filled = priority_flood(
+        size,
+        hmap,
+        epsilon,
+        |off| lakes[off] > 0.0,
+        &mut progress.part(0.5, 0.5),
+    );
+    hmap.copy_from_slice(&filled);
+    lakes
+}
+
+/// mark the depressions of at least min_cells cells. A depression is a connected area
+/// where the flat filled surface is above the terrain, all at the same height
+fn find_lakes(
+    size: (usize, usize),
+    hmap: &[f32],
+    flat: &[f32],
+    min_cells: usize,
+    lakes: &mut [f32],
+) {
+    let cells = size.0 * size.1;
+    let mut visited = vec![false; cells];
+    let mut stack = Vec::new();
+    let mut depression = Vec::new();
+    for start in 0..cells {
+        if visited[start] || flat[start] <= hmap[start] {
+            continue;
+        }
+        let surface = flat[start];
+        visited[start] = true;
+        stack.push(start);
+        depression.clear();
+        while let Some(off) = stack.pop() {
+            depression.push(off);
+            let (x, y) = (off % size.0, off / size.0);
+            for dir in 0..8 {
+                if let Some(noff) = neighbour(size, x, y, dir) {
+                    if !visited[noff] && flat[noff] == surface && flat[noff] > hmap[noff] {
+                        visited[noff] = true;
+                        stack.push(noff);
+                    }
+                }
+            }
+        }
+        if depression.len() >= min_cells {
+            for off in depression.iter() {
+                lakes[*off] = 1.0;
+            }
+        }
+    }
+}
+
+/// a cell in the flood queue, the lowest first
+pub(super) struct FloodCell {
+    pub(super) h: f32,
+    pub(super) off: usize,
+}
+
+impl PartialEq for FloodCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FloodCell {}
+
+impl PartialOrd for FloodCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FloodCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed so that the BinaryHeap returns the lowest cell
+        other
+            .h
+            .total_cmp(&self.h)
+            .then_with(|| other.off.cmp(&self.off))
+    }
+}
+
+/// neighbour of the cell x,y in the direction dir (see NEIGHBOURS), if it's inside the world
+pub(super) fn neighbour(size: (usize, usize), x: usize, y: usize, dir: usize) -> Option<usize> {
+    let nx = x as i32 + DIRX[NEIGHBOURS[dir]];
+    let ny = y as i32 + DIRY[NEIGHBOURS[dir]];
+    if nx < 0 || ny < 0 || nx >= size.0 as i32 || ny >= size.1 as i32 {
+        None
+    } else {
+        Some(nx as usize + ny as usize * size.0)
+    }
+}
+
+/// priority-flood the depressions (Barnes, Lehman, Mulla 2014) so that the water of every cell can reach
+/// the world border or an outlet cell. Each filled cell is at least epsilon higher than the cell it drains into
+pub(super) fn priority_flood<F: Fn(usize) -> bool>(
+    size: (usize, usize),
+    hmap: &[f32],
+    epsilon: f32,
+    is_outlet: F,
+    progress: &mut Progress,
+) -> Vec<f32> {
+    let cells = size.0 * size.1;
+    let mut filled = hmap.to_vec();
+    let mut closed = vec![false; cells];
+    let mut queue = BinaryHeap::new();
+    for y in 0..size.1 {
+        for x in 0..size.0 {
+            let off = x + y * size.0;
+            if x == 0 || y == 0 || x == size.0 - 1 || y == size.1 - 1 || is_outlet(off) {
+                closed[off] = true;
+                queue.push(FloodCell { h: hmap[off], off });
+            }
+        }
+    }
+    let mut done = 0;
+    while let Some(FloodCell { h, off }) = queue.pop() {
+        let (x, y) = (off % size.0, off / size.0);
+        for dir in 0..8 {
+            if let Some(noff) = neighbour(size, x, y, dir) {
+                if !closed[noff] {
+                    closed[noff] = true;
+                    filled[noff] = filled[noff].max(h + epsilon);
+                    queue.push(FloodCell {
+                        h: filled[noff],
+                        off: noff,
+                    });
+                }
+            }
+        }
+        done += 1;
+        if done % size.0 == 0 {
+            progress.report(done as f32 / cells as f32);
+            if progress.cancelled() {
+                break;
+            }
+        }
+    }
+    filled
+}
@@ -1,14 +1,65 @@
-use std::sync::mpsc::Sender;
-
+#[cfg(feature = "gui")]
 use eframe::egui;
 use rand::{prelude::*, rngs::StdRng};
 use serde::{Deserialize, Serialize};
 
-use crate::ThreadMessage;
+use super::{Chunk, Generator, Locality, Progress, StepContext};
+
+pub struct Hills;
 
-use super::report_progress;
+impl Generator for Hills {
+    type Conf = HillsConf;
+    fn name(&self) -> &'static str {
+        "Hills"
+    }
+    fn description(&self) -> &'static str {
+        "Add round hills to generate a smooth land"
+    }
+    fn run(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &HillsConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_hills(seed, size, hmap, conf, progress);
+    }
+    fn is_source(&self, _conf: &HillsConf) -> bool {
+        true
+    }
+    fn locality(&self, _size: (usize, usize), _conf: &HillsConf) -> Locality {
+        Locality::Local(0)
+    }
+    fn run_chunk(
+        &self,
+        seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &HillsConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        hills(
+            seed,
+            chunk.world_size,
+            chunk.pos,
+            chunk.size,
+            hmap,
+            conf,
+            progress,
+        );
+        Ok(())
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut HillsConf) {
+        render_hills(ui, conf);
+    }
+}
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct HillsConf {
     pub nb_hill: usize,
     pub base_radius: f32,
@@ -27,7 +78,8 @@ impl Default for HillsConf {
     }
 }
 
-pub fn render_hills(ui: &mut egui::Ui, conf: &mut HillsConf) {
+#[cfg(feature = "gui")]
+fn render_hills(ui: &mut egui::Ui, conf: &mut HillsConf) {
     ui.horizontal(|ui| {
         ui.label("count");
         ui.add(
@@ -57,15 +109,25 @@ pub fn gen_hills(
     size: (usize, usize),
     hmap: &mut [f32],
     conf: &HillsConf,
-    export: bool,
-    tx: Sender<ThreadMessage>,
-    min_progress_step: f32,
+    progress: &mut Progress,
+) {
+    hills(seed, size, (0, 0), size, hmap, conf, progress);
+}
+
+/// add the hills of a world of the given size to the chunk at pos of size chunk_size
+fn hills(
+    seed: u64,
+    size: (usize, usize),
+    pos: (usize, usize),
+    chunk_size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &HillsConf,
+    progress: &mut Progress,
 ) {
     let mut rng = StdRng::seed_from_u64(seed);
     let real_radius = conf.base_radius * size.0 as f32 / 200.0;
     let hill_min_radius = real_radius * (1.0 - conf.radius_var);
     let hill_max_radius = real_radius * (1.0 + conf.radius_var);
-    let mut progress = 0.0;
     for i in 0..conf.nb_hill {
         let radius: f32 = if conf.radius_var == 0.0 {
             hill_min_radius
@@ -76,23 +138,22 @@ pub fn gen_hills(
         let yh: f32 = rng.random_range(0.0..size.1 as f32);
         let radius2 = radius * radius;
         let coef = conf.height / radius2;
-        let minx = (xh - radius).max(0.0) as usize;
-        let maxx = (xh + radius).min(size.0 as f32) as usize;
-        let miny = (yh - radius).max(0.0) as usize;
-        let maxy = (yh + radius).min(size.1 as f32) as usize;
+        let minx = ((xh - radius).max(0.0) as usize).max(pos.0);
+        let maxx = ((xh + radius).min(size.0 as f32) as usize).min(pos.0 + chunk_size.0);
+        let miny = ((yh - radius).max(0.0) as usize).max(pos.1);
+        let maxy = ((yh + radius).min(size.1 as f32) as usize).min(pos.1 + chunk_size.1);
         for px in minx..maxx {
             let xdist = (px as f32 - xh).powi(2);
             for py in miny..maxy {
                 let z = radius2 - xdist - (py as f32 - yh).powi(2);
                 if z > 0.0 {
-                    hmap[px + py * size.0] += z * coef;
+                    hmap[px - pos.0 + (py - pos.1) * chunk_size.0] += z * coef;
                 }
             }
         }
-        let new_progress = i as f32 / conf.nb_hill as f32;
-        if new_progress - progress >= min_progress_step {
-            progress = new_progress;
-            report_progress(progress, export, tx.clone());
+        progress.report(i as f32 / conf.nb_hill as f32);
+        if progress.cancelled() {
+            return;
         }
     }
 }
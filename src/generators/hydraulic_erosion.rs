@@ -0,0 +1,286 @@
+#[cfg(feature = "gui")]
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    compute_rows, per_iteration, reference_scale, Generator, Progress, StepContext,
+    HORIZONTAL_SCALE,
+};
+
+// shallow water pipe model adapted from "Fast Hydraulic Erosion Simulation and Visualization on GPU" (Mei, Decaudin, Hu 2007)
+// cells are HORIZONTAL_SCALE / width wide
+/// simulation time step on a REFERENCE_SIZE wide grid
+const DT: f32 = 0.05;
+const GRAVITY: f32 = 9.81;
+/// Manning's roughness coefficient of the terrain
+const MANNING: f32 = 0.2;
+/// minimum tilt used to compute the sediment capacity, so that water still carries sediment on flat areas
+const MIN_TILT: f32 = 0.05;
+/// below this water depth, the velocity is considered null
+const MIN_DEPTH: f32 = 1e-6;
+/// water depth above which the sediment capacity doesn't increase anymore
+const EROSION_DEPTH: f32 = 0.01;
+
+/// pipes to the left, right, top and bottom neighbours
+const PIPE_DX: [i32; 4] = [-1, 1, 0, 0];
+const PIPE_DY: [i32; 4] = [0, 0, -1, 1];
+/// index of the pipe in the opposite direction
+const OPPOSITE: [usize; 4] = [1, 0, 3, 2];
+
+pub struct HydraulicErosion;
+
+impl Generator for HydraulicErosion {
+    type Conf = HydraulicErosionConf;
+    fn name(&self) -> &'static str {
+        "HydraulicErosion"
+    }
+    fn description(&self) -> &'static str {
+        "Simulate rain water flowing on the terrain and carrying sediments, forming river valleys and alluvial plains"
+    }
+    fn run(
+        &self,
+        _seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &HydraulicErosionConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_hydraulic_erosion(size, hmap, conf, progress);
+    }
+    fn extra_memory(&self, size: (usize, usize), _conf: &HydraulicErosionConf) -> usize {
+        // water, sediment and outflow with their double buffers and the velocity
+        size.0 * size.1 * std::mem::size_of::<f32>() * 16
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut HydraulicErosionConf) {
+        render_hydraulic_erosion(ui, conf);
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HydraulicErosionConf {
+    pub iterations: f32,
+    /// water height added to every cell at each iteration
+    pub rain_rate: f32,
+    /// proportion of the water evaporating at each iteration 0.0-1.0
+    pub evaporation: f32,
+    /// amount of sediment the water can carry depending on its speed and the slope
+    pub capacity: f32,
+    /// proportion of the missing sediment dissolved from the terrain at each iteration 0.0-1.0
+    pub dissolving: f32,
+    /// proportion of the excess sediment deposited on the terrain at each iteration 0.0-1.0
+    pub depositing: f32,
+}
+
+impl Default for HydraulicErosionConf {
+    fn default() -> Self {
+        Self {
+            iterations: 200.0,
+            rain_rate: 0.0005,
+            evaporation: 0.02,
+            capacity: 0.05,
+            dissolving: 0.3,
+            depositing: 0.3,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+fn render_hydraulic_erosion(ui: &mut egui::Ui, conf: &mut HydraulicErosionConf) {
+    ui.horizontal(|ui| {
+        ui.label("iterations");
+        ui.add(
+            egui::DragValue::new(&mut conf.iterations)
+                .speed(1.0)
+                .range(1.0..=2000.0),
+        );
+        ui.label("rain rate")
+            .on_hover_text("water height added to the terrain at each iteration");
+        ui.add(
+            egui::DragValue::new(&mut conf.rain_rate)
+                .speed(0.0001)
+                .range(0.0..=0.01),
+        );
+        ui.label("evaporation");
+        ui.add(
+            egui::DragValue::new(&mut conf.evaporation)
+                .speed(0.001)
+                .range(0.0..=1.0),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("capacity")
+            .on_hover_text("amount of sediment carried by fast water on steep slopes");
+        ui.add(
+            egui::DragValue::new(&mut conf.capacity)
+                .speed(0.001)
+                .range(0.0..=1.0),
+        );
+        ui.label("dissolving")
+            .on_hover_text("how fast the water erodes the terrain");
+        ui.add(
+            egui::DragValue::new(&mut conf.dissolving)
+                .speed(0.01)
+                .range(0.0..=1.0),
+        );
+        ui.label("depositing")
+            .on_hover_text("how fast the water deposits its sediment");
+        ui.add(
+            egui::DragValue::new(&mut conf.depositing)
+                .speed(0.01)
+                .range(0.0..=1.0),
+        );
+    });
+}
+
+/// offset of the neighbour of (x,y) through pipe p, if it's inside the grid
+fn pipe_neighbour(size: (usize, usize), x: usize, y: usize, p: usize) -> Option<usize> {
+    let nx = (x as i32 + PIPE_DX[p]) as usize;
+    let ny = (y as i32 + PIPE_DY[p]) as usize;
+    (nx < size.0 && ny < size.1).then(|| nx + ny * size.0)
+}
+
+pub fn gen_hydraulic_erosion(
+    size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &HydraulicErosionConf,
+    progress: &mut Progress,
+) {
+    let len = size.0 * size.1;
+    let cell_width = HORIZONTAL_SCALE / size.0 as f32;
+    let cell_area = cell_width * cell_width;
+    // on a finer grid, the same duration is simulated with more, shorter iterations
+    let scale = reference_scale(size.0);
+    let dt = DT / scale;
+    let rain_rate = conf.rain_rate / scale;
+    let evaporation = per_iteration(conf.evaporation, scale);
+    let dissolving = per_iteration(conf.dissolving, scale);
+    let depositing = per_iteration(conf.depositing, scale);
+    let mut water = vec![0.0; len];
+    let mut sediment = vec![0.0; len];
+    let mut outflow = vec![[0.0; 4]; len];
+    let mut new_outflow = vec![[0.0; 4]; len];
+    // water, velocity
+    let mut new_water = vec![(0.0, (0.0f32, 0.0f32)); len];
+    // terrain, sediment
+    let mut eroded = vec![(0.0, 0.0); len];
+    let mut new_sediment = vec![0.0; len];
+    let iterations = (conf.iterations.trunc() * scale).round() as usize;
+    for iteration in 0..iterations {
+        if progress.cancelled() {
+            return;
+        }
+        for w in water.iter_mut() {
+            *w += rain_rate;
+        }
+        // outflow to the neighbours through the pipes, depending on the water surface height difference
+        let (b, d, f, v) = (&hmap[..], &water[..], &outflow[..], &new_water[..]);
+        compute_rows(size.0, &mut new_outflow, |x, y| {
+            let off = x + y * size.0;
+            // semi-implicit bed friction depending on the previous velocity (Manning's formula)
+            let (vx, vy) = v[off].1;
+            let speed = (vx * vx + vy * vy).sqrt();
+            let friction = 1.0
+                + dt * GRAVITY * MANNING * MANNING * speed / d[off].max(MIN_DEPTH).powf(4.0 / 3.0);
+            let mut flux = [0.0; 4];
+            for (p, pipe) in flux.iter_mut().enumerate() {
+                if let Some(n) = pipe_neighbour(size, x, y, p) {
+                    let dh = b[off] + d[off] - b[n] - d[n];
+                    // the pipe cross section is the water depth times the cell width
+                    *pipe = ((f[off][p] + dt * GRAVITY * d[off] * dh) / friction).max(0.0);
+                }
+            }
+            // a cell can't lose more water than it has
+            let total: f32 = flux.iter().sum();
+            if total > 0.0 {
+                let k = (d[off] * cell_area / (total * dt)).min(1.0);
+                for pipe in flux.iter_mut() {
+                    *pipe *= k;
+                }
+            }
+            flux
+        });
+        std::mem::swap(&mut outflow, &mut new_outflow);
+        // water height and velocity
+        let f = &outflow[..];
+        compute_rows(size.0, &mut new_water, |x, y| {
+            let off = x + y * size.0;
+            // inflow through each pipe of this cell
+            let inflow =
+                |p: usize| pipe_neighbour(size, x, y, p).map_or(0.0, |n| f[n][OPPOSITE[p]]);
+            let total_in: f32 = (0..4).map(inflow).sum();
+            let total_out: f32 = f[off].iter().sum();
+            let new_d = (d[off] + dt * (total_in - total_out) / cell_area).max(0.0);
+            let mean_d = (d[off] + new_d) * 0.5;
+            let vel = if mean_d > MIN_DEPTH {
+                let flow_x = (inflow(0) - f[off][0] + f[off][1] - inflow(1)) * 0.5;
+                let flow_y = (inflow(2) - f[off][2] + f[off][3] - inflow(3)) * 0.5;
+                (
+                    flow_x / (cell_width * mean_d),
+                    flow_y / (cell_width * mean_d),
+                )
+            } else {
+                (0.0, 0.0)
+            };
+            (new_d, vel)
+        });
+        // erosion and deposition depending on the sediment capacity of the water
+        let (w, s) = (&new_water[..], &sediment[..]);
+        compute_rows(size.0, &mut eroded, |x, y| {
+            let off = x + y * size.0;
+            // steepest descent to a neighbour. Central differences would not see the small ripples formed by the erosion
+            let drop = (0..4)
+                .filter_map(|p| pipe_neighbour(size, x, y, p).map(|n| b[off] - b[n]))
+                .fold(0.0, f32::max);
+            let slope = drop / cell_width;
+            let sin_tilt = (slope / (1.0 + slope * slope).sqrt()).max(MIN_TILT);
+            let (depth, vel) = w[off];
+            let speed = (vel.0 * vel.0 + vel.1 * vel.1).sqrt();
+            // the thin water layer of the slopes carries less sediment than the rivers
+            let depth_coef = (depth / EROSION_DEPTH).min(1.0);
+            let capacity = conf.capacity * sin_tilt * speed * depth_coef;
+            if capacity > s[off] {
+                let amount = dissolving * (capacity - s[off]);
+                (b[off] - amount, s[off] + amount)
+            } else {
+                let amount = depositing * (s[off] - capacity);
+                (b[off] + amount, s[off] - amount)
+            }
+        });
+        for (i, (new_b, new_s)) in eroded.iter().enumerate() {
+            hmap[i] = *new_b;
+            sediment[i] = *new_s;
+        }
+        // the sediment follows the water through the pipes
+        let s = &sediment[..];
+        // proportion of the water of a cell leaving through pipe p
+        let out_ratio = |off: usize, p: usize| {
+            if d[off] > MIN_DEPTH {
+                (f[off][p] * dt / (d[off] * cell_area)).min(1.0)
+            } else {
+                0.0
+            }
+        };
+        compute_rows(size.0, &mut new_sediment, |x, y| {
+            let off = x + y * size.0;
+            let staying = 1.0 - (0..4).map(|p| out_ratio(off, p)).sum::<f32>();
+            let incoming: f32 = (0..4)
+                .filter_map(|p| {
+                    pipe_neighbour(size, x, y, p).map(|n| s[n] * out_ratio(n, OPPOSITE[p]))
+                })
+                .sum();
+            s[off] * staying.max(0.0) + incoming
+        });
+        std::mem::swap(&mut sediment, &mut new_sediment);
+        for (i, (new_d, _)) in new_water.iter().enumerate() {
+            water[i] = new_d * (1.0 - evaporation);
+        }
+        progress.report((iteration + 1) as f32 / iterations as f32);
+    }
+    // the water still carrying sediment drops it
+    for (h, s) in hmap.iter_mut().zip(sediment.iter()) {
+        *h += s;
+    }
+}
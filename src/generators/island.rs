@@ -1,13 +1,61 @@
-use std::sync::mpsc::Sender;
-
+#[cfg(feature = "gui")]
 use eframe::egui;
 use serde::{Deserialize, Serialize};
 
-use crate::ThreadMessage;
+use super::{get_min_max, Chunk, Generator, Locality, Progress, StepContext};
+
+pub struct Island;
 
-use super::{get_min_max, report_progress};
+impl Generator for Island {
+    type Conf = IslandConf;
+    fn name(&self) -> &'static str {
+        "Island"
+    }
+    fn description(&self) -> &'static str {
+        "Lower height on the map borders"
+    }
+    fn run(
+        &self,
+        _seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &IslandConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_island(size, hmap, conf, progress);
+    }
+    fn locality(&self, _size: (usize, usize), _conf: &IslandConf) -> Locality {
+        Locality::Local(0)
+    }
+    fn run_chunk(
+        &self,
+        _seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &IslandConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        island(
+            chunk.world_size,
+            chunk.pos,
+            chunk.size,
+            hmap,
+            chunk.layer.min,
+            conf,
+            progress,
+        );
+        Ok(())
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut IslandConf) {
+        render_island(ui, conf);
+    }
+}
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct IslandConf {
     pub coast_range: f32,
 }
@@ -18,7 +66,8 @@ impl Default for IslandConf {
     }
 }
 
-pub fn render_island(ui: &mut egui::Ui, conf: &mut IslandConf) {
+#[cfg(feature = "gui")]
+fn render_island(ui: &mut egui::Ui, conf: &mut IslandConf) {
     ui.horizontal(|ui| {
         ui.label("coast range %");
         ui.add(
@@ -33,40 +82,44 @@ pub fn gen_island(
     size: (usize, usize),
     hmap: &mut [f32],
     conf: &IslandConf,
-    export: bool,
-    tx: Sender<ThreadMessage>,
-    min_progress_step: f32,
+    progress: &mut Progress,
 ) {
-    let coast_h_dist = size.0 as f32 * conf.coast_range / 100.0;
-    let coast_v_dist = size.1 as f32 * conf.coast_range / 100.0;
     let (min, _) = get_min_max(hmap);
-    let mut progress = 0.0;
-    for x in 0..size.0 {
-        for y in 0..coast_v_dist as usize {
-            let h_coef = y as f32 / coast_v_dist as f32;
-            let h = hmap[x + y * size.0];
-            hmap[x + y * size.0] = (h - min) * h_coef + min;
-            let h = hmap[x + (size.1 - 1 - y) * size.0];
-            hmap[x + (size.1 - 1 - y) * size.0] = (h - min) * h_coef + min;
+    island(size, (0, 0), size, hmap, min, conf, progress);
+}
+
+/// lower the heights of the chunk at pos of size chunk_size towards min near the world borders
+fn island(
+    world_size: (usize, usize),
+    pos: (usize, usize),
+    chunk_size: (usize, usize),
+    hmap: &mut [f32],
+    min: f32,
+    conf: &IslandConf,
+    progress: &mut Progress,
+) {
+    let coast_h_dist = world_size.0 as f32 * conf.coast_range / 100.0;
+    let coast_v_dist = world_size.1 as f32 * conf.coast_range / 100.0;
+    for y in 0..chunk_size.1 {
+        let v_coef = coast_coef(pos.1 + y, world_size.1, coast_v_dist);
+        for x in 0..chunk_size.0 {
+            let h_coef = coast_coef(pos.0 + x, world_size.0, coast_h_dist) * v_coef;
+            let h = hmap[x + y * chunk_size.0];
+            hmap[x + y * chunk_size.0] = (h - min) * h_coef + min;
         }
-        let new_progress = 0.5 * x as f32 / size.0 as f32;
-        if new_progress - progress >= min_progress_step {
-            progress = new_progress;
-            report_progress(progress, export, tx.clone());
+        progress.report(y as f32 / chunk_size.1 as f32);
+        if progress.cancelled() {
+            return;
         }
     }
-    for y in 0..size.1 {
-        for x in 0..coast_h_dist as usize {
-            let h_coef = x as f32 / coast_h_dist as f32;
-            let h = hmap[x + y * size.0];
-            hmap[x + y * size.0] = (h - min) * h_coef + min;
-            let h = hmap[(size.0 - 1 - x) + y * size.0];
-            hmap[(size.0 - 1 - x) + y * size.0] = (h - min) * h_coef + min;
-        }
-        let new_progress = 0.5 + 0.5 * y as f32 / size.0 as f32;
-        if new_progress - progress >= min_progress_step {
-            progress = new_progress;
-            report_progress(progress, export, tx.clone());
-        }
+}
+
+/// height coefficient depending on the distance to the world border
+fn coast_coef(pos: usize, len: usize, coast_dist: f32) -> f32 {
+    let border_dist = pos.min(len - 1 - pos);
+    if border_dist < coast_dist as usize {
+        border_dist as f32 / coast_dist
+    } else {
+        1.0
     }
 }
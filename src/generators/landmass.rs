@@ -1,13 +1,57 @@
-use std::sync::mpsc::Sender;
-
+#[cfg(feature = "gui")]
 use eframe::egui;
 use serde::{Deserialize, Serialize};
 
-use crate::ThreadMessage;
+use super::{height_histogram, normalize, Chunk, Generator, Locality, Progress, StepContext};
+
+pub struct LandMass;
 
-use super::{normalize, report_progress};
+impl Generator for LandMass {
+    type Conf = LandMassConf;
+    fn name(&self) -> &'static str {
+        "LandMass"
+    }
+    fn description(&self) -> &'static str {
+        "Scale the terrain so that only a proportion of land is above water level"
+    }
+    fn run(
+        &self,
+        _seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &LandMassConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_landmass(size, hmap, conf, progress);
+    }
+    fn locality(&self, _size: (usize, usize), _conf: &LandMassConf) -> Locality {
+        Locality::Local(0)
+    }
+    fn run_chunk(
+        &self,
+        _seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &LandMassConf,
+        _ctx: &StepContext,
+        _progress: &mut Progress,
+    ) -> Result<(), String> {
+        let cells = chunk.world_size.0 * chunk.world_size.1;
+        let new_water_level = compute_water_level(&chunk.layer.histogram, cells, conf);
+        for h in hmap {
+            *h = landmass_height(chunk.layer.normalized(*h), new_water_level, conf);
+        }
+        Ok(())
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut LandMassConf) {
+        render_landmass(ui, conf);
+    }
+}
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct LandMassConf {
     /// what proportion of the map should be above water 0.0-1.0
     pub land_proportion: f32,
@@ -30,7 +74,8 @@ impl Default for LandMassConf {
     }
 }
 
-pub fn render_landmass(ui: &mut egui::Ui, conf: &mut LandMassConf) {
+#[cfg(feature = "gui")]
+fn render_landmass(ui: &mut egui::Ui, conf: &mut LandMassConf) {
     ui.horizontal(|ui| {
         ui.label("land proportion")
             .on_hover_text("what proportion of the map should be above water");
@@ -69,70 +114,52 @@ pub fn gen_landmass(
     size: (usize, usize),
     hmap: &mut [f32],
     conf: &LandMassConf,
-    export: bool,
-    tx: Sender<ThreadMessage>,
-    min_progress_step: f32,
+    progress: &mut Progress,
 ) {
-    let mut height_count: [f32; 256] = [0.0; 256];
-    let mut progress = 0.0;
+    let mut height_count = [0; 256];
     normalize(hmap, 0.0, 1.0);
+    height_histogram(hmap, 0.0, 1.0, &mut height_count);
+    let new_water_level = compute_water_level(&height_count, size.0 * size.1, conf);
+    progress.report(0.33);
     for y in 0..size.1 {
         let yoff = y * size.0;
         for x in 0..size.0 {
-            let h = hmap[x + yoff];
-            let ih = (h * 255.0) as usize;
-            height_count[ih] += 1.0;
+            hmap[x + yoff] = landmass_height(hmap[x + yoff], new_water_level, conf);
         }
-        let new_progress = 0.33 * y as f32 / size.1 as f32;
-        if new_progress - progress >= min_progress_step {
-            progress = new_progress;
-            report_progress(progress, export, tx.clone());
+        progress.report(0.33 + 0.66 * y as f32 / size.1 as f32);
+        if progress.cancelled() {
+            return;
         }
     }
+}
+
+/// height of the normalized heightmap that should be raised/lowered to the water level
+fn compute_water_level(height_count: &[usize; 256], cells: usize, conf: &LandMassConf) -> f32 {
     let mut water_level = 0;
-    let mut water_cells = 0.0;
-    let target_water_cells = (size.0 * size.1) as f32 * (1.0 - conf.land_proportion);
-    while water_level < 256 && water_cells < target_water_cells {
+    let mut water_cells = 0;
+    let target_water_cells = cells as f32 * (1.0 - conf.land_proportion);
+    while water_level < 256 && (water_cells as f32) < target_water_cells {
         water_cells += height_count[water_level];
         water_level += 1;
     }
-    let new_water_level = water_level as f32 / 255.0;
+    water_level as f32 / 255.0
+}
+
+/// new height of a cell from its normalized height
+fn landmass_height(mut h: f32, new_water_level: f32, conf: &LandMassConf) -> f32 {
     let land_coef = (1.0 - conf.water_level) / (1.0 - new_water_level);
     let water_coef = conf.water_level / new_water_level;
     // water level should be raised/lowered to newWaterLevel
-    for y in 0..size.1 {
-        let yoff = y * size.0;
-        for x in 0..size.0 {
-            let mut h = hmap[x + yoff];
-            if h > new_water_level {
-                h = conf.water_level + (h - new_water_level) * land_coef;
-            } else {
-                h = h * water_coef - conf.shore_height;
-            }
-            hmap[x + yoff] = h;
-        }
-        let new_progress = 0.33 + 0.33 * y as f32 / size.1 as f32;
-        if new_progress - progress >= min_progress_step {
-            progress = new_progress;
-            report_progress(progress, export, tx.clone());
-        }
+    if h > new_water_level {
+        h = conf.water_level + (h - new_water_level) * land_coef;
+    } else {
+        h = h * water_coef - conf.shore_height;
     }
     // fix land/mountain ratio using h^plain_factor curve above sea level
-    for y in 0..size.1 {
-        let yoff = y * size.0;
-        for x in 0..size.0 {
-            let mut h = hmap[x + yoff];
-            if h >= conf.water_level {
-                let coef = (h - conf.water_level) / (1.0 - conf.water_level);
-                let coef = coef.powf(conf.plain_factor);
-                h = conf.water_level + coef * (1.0 - conf.water_level);
-                hmap[x + y * size.0] = h;
-            }
-        }
-        let new_progress = 0.66 + 0.33 * y as f32 / size.1 as f32;
-        if new_progress - progress >= min_progress_step {
-            progress = new_progress;
-            report_progress(progress, export, tx.clone());
-        }
+    if h >= conf.water_level {
+        let coef = (h - conf.water_level) / (1.0 - conf.water_level);
+        let coef = coef.powf(conf.plain_factor);
+        h = conf.water_level + coef * (1.0 - conf.water_level);
     }
+    h
 }
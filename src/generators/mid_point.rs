@@ -1,14 +1,46 @@
-use std::sync::mpsc::Sender;
-
+#[cfg(feature = "gui")]
 use eframe::egui;
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
-use crate::ThreadMessage;
+use super::{interpolate, Generator, Progress, StepContext};
+
+pub struct MidPoint;
 
-use super::report_progress;
+impl Generator for MidPoint {
+    type Conf = MidPointConf;
+    fn name(&self) -> &'static str {
+        "MidPoint"
+    }
+    fn description(&self) -> &'static str {
+        "Use mid point deplacement to generate a mountainous land"
+    }
+    fn run(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &MidPointConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_mid_point(seed, size, hmap, conf, progress);
+    }
+    fn is_source(&self, _conf: &MidPointConf) -> bool {
+        true
+    }
+    fn extra_memory(&self, size: (usize, usize), _conf: &MidPointConf) -> usize {
+        let grid_len = grid_len(size);
+        grid_len * grid_len * std::mem::size_of::<f32>()
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut MidPointConf) {
+        render_mid_point(ui, conf);
+    }
+}
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MidPointConf {
     pub roughness: f32,
 }
@@ -19,14 +51,13 @@ impl Default for MidPointConf {
     }
 }
 
-pub struct ProgressTracking {
+pub struct ProgressTracking<'a> {
     count: usize,
-    progress: f32,
-    min_progress_step: f32,
-    export: bool,
+    progress: &'a mut Progress,
 }
 
-pub fn render_mid_point(ui: &mut egui::Ui, conf: &mut MidPointConf) {
+#[cfg(feature = "gui")]
+fn render_mid_point(ui: &mut egui::Ui, conf: &mut MidPointConf) {
     ui.horizontal(|ui| {
         ui.label("roughness");
         ui.add(
@@ -40,80 +71,92 @@ pub fn render_mid_point(ui: &mut egui::Ui, conf: &mut MidPointConf) {
 pub fn gen_mid_point(
     seed: u64,
     size: (usize, usize),
-    hmap: &mut Vec<f32>,
+    hmap: &mut [f32],
     conf: &MidPointConf,
-    export: bool,
-    tx: Sender<ThreadMessage>,
-    min_progress_step: f32,
+    progress: &mut Progress,
 ) {
+    // compute the displacement on a (2^n+1)x(2^n+1) grid. The coarse levels, and thus the random values,
+    // are the same whatever the world size. Only the finest details change with the resolution
+    let world_len = size.0.max(size.1);
+    let grid_len = grid_len(size);
+    let grid_size = (grid_len, grid_len);
+    let mut grid = vec![0.0; grid_len * grid_len];
     let mut rng = StdRng::seed_from_u64(seed);
-    hmap[0] = rng.random_range(0.0..1.0);
-    hmap[size.0 - 1] = rng.random_range(0.0..1.0);
-    hmap[size.0 * (size.1 - 1)] = rng.random_range(0.0..1.0);
-    hmap[size.0 * size.1 - 1] = rng.random_range(0.0..1.0);
+    grid[0] = rng.random_range(0.0..1.0);
+    grid[grid_len - 1] = rng.random_range(0.0..1.0);
+    grid[grid_len * (grid_len - 1)] = rng.random_range(0.0..1.0);
+    grid[grid_len * grid_len - 1] = rng.random_range(0.0..1.0);
     let mut track = ProgressTracking {
-        count: size.0 * size.1 * 2,
-        progress: 0.0,
-        min_progress_step,
-        export,
+        count: grid_len * grid_len * 2,
+        progress,
     };
     diamond_square(
-        hmap,
+        &mut grid,
         &mut rng,
-        size,
-        size.0 / 2,
+        grid_size,
+        grid_len - 1,
         conf.roughness,
         &mut track,
-        tx,
     );
+    let coef = (grid_len - 1) as f32 / (world_len - 1).max(1) as f32;
+    for y in 0..size.1 {
+        for x in 0..size.0 {
+            hmap[x + y * size.0] = interpolate(&grid, x as f32 * coef, y as f32 * coef, grid_size);
+        }
+    }
 }
 
-fn check_progress(track: &mut ProgressTracking, size: (usize, usize), tx: Sender<ThreadMessage>) {
-    let new_progress = 1.0 - track.count as f32 / (size.0 * size.1 * 2) as f32;
-    if new_progress - track.progress >= track.min_progress_step {
-        track.progress = new_progress;
-        report_progress(track.progress, track.export, tx);
-    }
+/// size of the (2^n+1)x(2^n+1) grid covering the world
+fn grid_len(size: (usize, usize)) -> usize {
+    (size.0.max(size.1) - 1).next_power_of_two() + 1
+}
+
+fn check_progress(track: &mut ProgressTracking, size: (usize, usize)) {
+    track
+        .progress
+        .report(1.0 - track.count as f32 / (size.0 * size.1 * 2) as f32);
 }
 
 pub fn diamond_square(
-    hmap: &mut Vec<f32>,
+    hmap: &mut [f32],
     rng: &mut StdRng,
     size: (usize, usize),
     cur_size: usize,
     roughness: f32,
     track: &mut ProgressTracking,
-    tx: Sender<ThreadMessage>,
 ) {
     let half = cur_size / 2;
-    if half < 1 {
+    if half < 1 || track.progress.cancelled() {
         return;
     }
     for y in (half..size.1).step_by(cur_size) {
         for x in (half..size.0).step_by(cur_size) {
             square_step(hmap, rng, x, y, size, half, roughness);
             track.count -= 1;
-            check_progress(track, size, tx.clone());
+            check_progress(track, size);
         }
     }
     let mut col = 0;
     for x in (0..size.0).step_by(half) {
+        if track.progress.cancelled() {
+            return;
+        }
         col += 1;
         if col % 2 == 1 {
             for y in (half..size.1).step_by(cur_size) {
                 diamond_step(hmap, rng, x, y, size, half, roughness);
                 track.count -= 1;
-                check_progress(track, size, tx.clone());
+                check_progress(track, size);
             }
         } else {
             for y in (0..size.1).step_by(cur_size) {
                 diamond_step(hmap, rng, x, y, size, half, roughness);
                 track.count -= 1;
-                check_progress(track, size, tx.clone());
+                check_progress(track, size);
             }
         }
     }
-    diamond_square(hmap, rng, size, cur_size / 2, roughness * 0.5, track, tx);
+    diamond_square(hmap, rng, size, cur_size / 2, roughness * 0.5, track);
 }
 
 fn square_step(
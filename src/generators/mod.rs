@@ -1,29 +1,680 @@
-mod fbm;
+mod blur;
+mod chunk;
+mod craters;
+mod curve;
+mod domain_warp;
+mod fill_depressions;
 mod hills;
+mod hydraulic_erosion;
 mod island;
 mod landmass;
 mod mid_point;
 mod mudslide;
+mod noise;
 mod normalize;
+mod rivers;
+mod sharpen;
+mod terrace;
+mod thermal_erosion;
+mod voronoi;
 mod water_erosion;
 
+use std::any::Any;
+use std::fmt::Debug;
+use std::ops::Range;
 use std::sync::mpsc::Sender;
+use std::sync::{OnceLock, RwLock};
 
-pub use fbm::{gen_fbm, render_fbm, FbmConf};
-pub use hills::{gen_hills, render_hills, HillsConf};
-pub use island::{gen_island, render_island, IslandConf};
-pub use landmass::{gen_landmass, render_landmass, LandMassConf};
-pub use mid_point::{gen_mid_point, render_mid_point, MidPointConf};
-pub use mudslide::{gen_mudslide, render_mudslide, MudSlideConf};
-pub use normalize::{gen_normalize, NormalizeConf};
-pub use water_erosion::{gen_water_erosion, render_water_erosion, WaterErosionConf};
+#[cfg(feature = "gui")]
+use eframe::egui;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::ThreadMessage;
+pub use blur::{gen_blur, Blur, BlurConf};
+pub use chunk::{height_histogram, Chunk, LayerInfo, Locality};
+pub use craters::{gen_craters, Craters, CratersConf};
+pub use curve::{gen_curve, Curve, CurveConf};
+pub use domain_warp::{gen_domain_warp, DomainWarp, DomainWarpConf, WarpSource};
+pub use fill_depressions::{gen_fill_depressions, FillDepressions, FillDepressionsConf};
+pub use hills::{gen_hills, Hills, HillsConf};
+pub use hydraulic_erosion::{gen_hydraulic_erosion, HydraulicErosion, HydraulicErosionConf};
+pub use island::{gen_island, Island, IslandConf};
+pub use landmass::{gen_landmass, LandMass, LandMassConf};
+pub use mid_point::{gen_mid_point, MidPoint, MidPointConf};
+pub use mudslide::{gen_mudslide, MudSlide, MudSlideConf};
+pub use noise::{gen_noise, FractalType, Noise, NoiseConf, NoiseSource};
+pub use normalize::{gen_normalize, Normalize, NormalizeConf};
+pub use rivers::{gen_rivers, FlowModel, Rivers, RiversConf};
+pub use sharpen::{gen_sharpen, Sharpen, SharpenConf};
+pub use terrace::{gen_terrace, Terrace, TerraceConf};
+pub use thermal_erosion::{
+    gen_thermal_erosion, ThermalErosion, ThermalErosionConf, HORIZONTAL_SCALE,
+};
+pub use voronoi::{gen_voronoi, DistanceMetric, Voronoi, VoronoiCombination, VoronoiConf};
+pub use water_erosion::{gen_water_erosion, WaterErosion, WaterErosionConf};
+
+use crate::{CancelToken, ThreadMessage};
+
+/// A heightmap generator step.
+/// Implement this trait and call [`register_generator`] to add a new step type.
+pub trait Generator: Send + Sync + 'static {
+    /// the step parameters, saved in the project file.
+    /// Use `#[serde(default)]` so that fields added later get their default value in older projects
+    type Conf: Default
+        + Debug
+        + Clone
+        + PartialEq
+        + Serialize
+        + DeserializeOwned
+        + Send
+        + Sync
+        + 'static;
+    /// unique name, displayed in the UI and used to identify the step in project files
+    fn name(&self) -> &'static str;
+    /// help text displayed when hovering the step in the UI
+    fn description(&self) -> &'static str;
+    /// parameters of a newly added step
+    fn default_conf(&self) -> Self::Conf {
+        Self::Conf::default()
+    }
+    /// apply the step to the heightmap
+    fn run(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &Self::Conf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    );
+    /// apply the step to the heightmap and return the additional layers it computes (a river network, a water mask...).
+    /// They are exported next to the heightmap. Only global steps can compute additional layers
+    fn run_layers(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &Self::Conf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Vec<ExtraLayer> {
+        self.run(seed, size, hmap, conf, ctx, progress);
+        Vec::new()
+    }
+    /// estimate of the memory in bytes allocated by run or prepare besides the heightmap, displayed by the exporter
+    fn extra_memory(&self, _size: (usize, usize), _conf: &Self::Conf) -> usize {
+        0
+    }
+    /// the step generates new heights (hills, noise...) instead of transforming the incoming heightmap.
+    /// With a blend mode other than replace, source steps run on a flat heightmap
+    fn is_source(&self, _conf: &Self::Conf) -> bool {
+        false
+    }
+    /// how the streaming exporter can compute this step. Global steps are computed on the whole heightmap in memory
+    fn locality(&self, _size: (usize, usize), _conf: &Self::Conf) -> Locality {
+        Locality::Global
+    }
+    /// for local steps, compute once per layer the data shared by all the chunks
+    /// (for example the craters base heights). It is available in [`Chunk::prepared`]
+    fn prepare(
+        &self,
+        _seed: u64,
+        _layer: &LayerInfo,
+        _conf: &Self::Conf,
+        _ctx: &StepContext,
+        _progress: &mut Progress,
+    ) -> Vec<f32> {
+        Vec::new()
+    }
+    /// for local steps, apply the step to a chunk of the heightmap. hmap contains the chunk, halo included.
+    /// Must be implemented by the steps whose locality is [`Locality::Local`]
+    fn run_chunk(
+        &self,
+        _seed: u64,
+        _chunk: &Chunk,
+        _hmap: &mut [f32],
+        _conf: &Self::Conf,
+        _ctx: &StepContext,
+        _progress: &mut Progress,
+    ) -> Result<(), String> {
+        Err(format!("{} cannot be computed by chunks", self.name()))
+    }
+    /// render the UI to edit the step parameters
+    #[cfg(feature = "gui")]
+    fn render(&self, _ui: &mut egui::Ui, _conf: &mut Self::Conf) {}
+}
+
+/// what a step knows about the previous steps of the project
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct StepContext {
+    /// water level of the nearest enabled LandMass step before this step. None if there is none
+    pub water_level: Option<f32>,
+}
+
+/// a map computed by a step besides the heightmap, with values in the 0.0-1.0 range
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtraLayer {
+    /// suffix of the exported files
+    pub name: &'static str,
+    pub values: Vec<f32>,
+}
+
+/// type erased step parameters
+pub trait StepConf: Any + Debug + Send + Sync + erased_serde::Serialize {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn clone_box(&self) -> Box<dyn StepConf>;
+    fn eq_box(&self, other: &dyn StepConf) -> bool;
+}
+
+erased_serde::serialize_trait_object!(StepConf);
+
+impl<T> StepConf for T
+where
+    T: Any + Debug + Clone + PartialEq + Serialize + Send + Sync,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn clone_box(&self) -> Box<dyn StepConf> {
+        Box::new(self.clone())
+    }
+    fn eq_box(&self, other: &dyn StepConf) -> bool {
+        other.as_any().downcast_ref::<T>() == Some(self)
+    }
+}
+
+/// type erased [`Generator`], as stored in the registry
+pub trait DynGenerator: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    fn default_conf(&self) -> Box<dyn StepConf>;
+    fn deserialize_conf(
+        &self,
+        de: &mut dyn erased_serde::Deserializer,
+    ) -> Result<Box<dyn StepConf>, erased_serde::Error>;
+    fn run(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &dyn StepConf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    );
+    fn run_layers(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &dyn StepConf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Vec<ExtraLayer>;
+    fn extra_memory(&self, size: (usize, usize), conf: &dyn StepConf) -> usize;
+    fn is_source(&self, conf: &dyn StepConf) -> bool;
+    fn locality(&self, size: (usize, usize), conf: &dyn StepConf) -> Locality;
+    fn prepare(
+        &self,
+        seed: u64,
+        layer: &LayerInfo,
+        conf: &dyn StepConf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Vec<f32>;
+    fn run_chunk(
+        &self,
+        seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &dyn StepConf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String>;
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut dyn StepConf);
+}
+
+fn downcast_conf<G: Generator>(conf: &dyn StepConf) -> &G::Conf {
+    conf.as_any()
+        .downcast_ref::<G::Conf>()
+        .expect("step configuration does not match its generator")
+}
+
+impl<G: Generator> DynGenerator for G {
+    fn name(&self) -> &'static str {
+        Generator::name(self)
+    }
+    fn description(&self) -> &'static str {
+        Generator::description(self)
+    }
+    fn default_conf(&self) -> Box<dyn StepConf> {
+        Box::new(Generator::default_conf(self))
+    }
+    fn deserialize_conf(
+        &self,
+        de: &mut dyn erased_serde::Deserializer,
+    ) -> Result<Box<dyn StepConf>, erased_serde::Error> {
+        Ok(Box::new(erased_serde::deserialize::<G::Conf>(de)?))
+    }
+    fn run(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &dyn StepConf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        Generator::run(
+            self,
+            seed,
+            size,
+            hmap,
+            downcast_conf::<G>(conf),
+            ctx,
+            progress,
+        );
+    }
+    fn run_layers(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &dyn StepConf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Vec<ExtraLayer> {
+        Generator::run_layers(
+            self,
+            seed,
+            size,
+            hmap,
+            downcast_conf::<G>(conf),
+            ctx,
+            progress,
+        )
+    }
+    fn extra_memory(&self, size: (usize, usize), conf: &dyn StepConf) -> usize {
+        Generator::extra_memory(self, size, downcast_conf::<G>(conf))
+    }
+    fn is_source(&self, conf: &dyn StepConf) -> bool {
+        Generator::is_source(self, downcast_conf::<G>(conf))
+    }
+    fn locality(&self, size: (usize, usize), conf: &dyn StepConf) -> Locality {
+        Generator::locality(self, size, downcast_conf::<G>(conf))
+    }
+    fn prepare(
+        &self,
+        seed: u64,
+        layer: &LayerInfo,
+        conf: &dyn StepConf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Vec<f32> {
+        Generator::prepare(self, seed, layer, downcast_conf::<G>(conf), ctx, progress)
+    }
+    fn run_chunk(
+        &self,
+        seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &dyn StepConf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        Generator::run_chunk(
+            self,
+            seed,
+            chunk,
+            hmap,
+            downcast_conf::<G>(conf),
+            ctx,
+            progress,
+        )
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut dyn StepConf) {
+        let conf = conf
+            .as_any_mut()
+            .downcast_mut::<G::Conf>()
+            .expect("step configuration does not match its generator");
+        Generator::render(self, ui, conf);
+    }
+}
+
+fn registry() -> &'static RwLock<Vec<&'static dyn DynGenerator>> {
+    static REGISTRY: OnceLock<RwLock<Vec<&'static dyn DynGenerator>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        RwLock::new(vec![
+            &Hills,
+            &Noise,
+            &MidPoint,
+            &Normalize,
+            &LandMass,
+            &MudSlide,
+            &WaterErosion,
+            &Island,
+            &ThermalErosion,
+            &HydraulicErosion,
+            &DomainWarp,
+            &Voronoi,
+            &Terrace,
+            &Craters,
+            &Rivers,
+            &FillDepressions,
+            &Blur,
+            &Sharpen,
+            &Curve,
+        ])
+    })
+}
+
+/// add a new step type. Fails if a generator with the same name already exists
+pub fn register_generator<G: Generator>(generator: G) -> Result<(), String> {
+    let mut generators = registry().write().unwrap();
+    let name = Generator::name(&generator);
+    if generators.iter().any(|g| g.name() == name) {
+        return Err(format!("a generator named {} already exists", name));
+    }
+    generators.push(Box::leak(Box::new(generator)));
+    Ok(())
+}
+
+/// all the registered generators, in the order they appear in the UI
+pub fn generators() -> Vec<&'static dyn DynGenerator> {
+    registry().read().unwrap().clone()
+}
+
+/// generators renamed since older versions : old name, current name
+const RENAMED_GENERATORS: &[(&str, &str)] = &[("Fbm", "Noise")];
+
+/// the generator with this name. Also accepts the names used by older versions
+pub fn find_generator(name: &str) -> Option<&'static dyn DynGenerator> {
+    let name = RENAMED_GENERATORS
+        .iter()
+        .find(|(old, _)| *old == name)
+        .map_or(name, |(_, new)| new);
+    registry()
+        .read()
+        .unwrap()
+        .iter()
+        .find(|g| g.name() == name)
+        .copied()
+}
+
+/// key of the histogram of the selected step input heightmap ([usize; 256], see [`height_histogram`])
+/// in the egui temporary data, set by the generators panel before rendering the step parameters
+#[cfg(feature = "gui")]
+pub fn input_histogram_id() -> egui::Id {
+    egui::Id::new("step input histogram")
+}
+
+/// sends the progress of the current step to the main thread
+#[derive(Clone)]
+pub struct Progress {
+    tx: Sender<ThreadMessage>,
+    /// are we computing the heightmap for the exporter or the preview ?
+    export: bool,
+    /// minimum amount of progress to report (below this value, the global %age won't change)
+    min_progress_step: f32,
+    /// last reported progress
+    progress: f32,
+    /// when reporting the progress of a part of the step, progress of the step when the part starts
+    offset: f32,
+    /// when reporting the progress of a part of the step, share of the part in the step
+    scale: f32,
+    /// set by the main thread to stop the computation
+    cancel: CancelToken,
+}
+
+impl Progress {
+    pub fn new(
+        tx: Sender<ThreadMessage>,
+        export: bool,
+        min_progress_step: f32,
+        cancel: CancelToken,
+    ) -> Self {
+        Self {
+            tx,
+            export,
+            min_progress_step,
+            progress: 0.0,
+            offset: 0.0,
+            scale: 1.0,
+            cancel,
+        }
+    }
+    /// should the step stop as soon as possible ? Its result will be discarded
+    pub fn cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+    /// progress reporter for a part of the step, starting at offset and representing scale of the current progress range
+    pub fn part(&self, offset: f32, scale: f32) -> Self {
+        let offset = self.offset + offset * self.scale;
+        Self {
+            tx: self.tx.clone(),
+            cancel: self.cancel.clone(),
+            progress: offset,
+            offset,
+            scale: self.scale * scale,
+            ..*self
+        }
+    }
+    /// report the step progress (0.0-1.0) if it changed enough since the last report
+    pub fn report(&mut self, progress: f32) {
+        let progress = self.offset + progress * self.scale;
+        if progress - self.progress < self.min_progress_step {
+            return;
+        }
+        self.progress = progress;
+        // the receiver might be gone if the main thread has stopped
+        let _ = self.tx.send(if self.export {
+            ThreadMessage::ExporterStepProgress(progress)
+        } else {
+            ThreadMessage::GeneratorStepProgress(progress)
+        });
+    }
+}
+
+/// how a step output is combined with the incoming heightmap
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum BlendMode {
+    /// the step modifies the incoming heightmap
+    #[default]
+    Replace,
+    Add,
+    Subtract,
+    Multiply,
+    Max,
+    Min,
+    /// inverse of the product of the inverted heights, for heights in the 0.0-1.0 range
+    Screen,
+    Difference,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 8] = [
+        BlendMode::Replace,
+        BlendMode::Add,
+        BlendMode::Subtract,
+        BlendMode::Multiply,
+        BlendMode::Max,
+        BlendMode::Min,
+        BlendMode::Screen,
+        BlendMode::Difference,
+    ];
+    /// combine the incoming height h with the step output value
+    pub fn blend(&self, h: f32, value: f32) -> f32 {
+        match self {
+            Self::Replace => value,
+            Self::Add => h + value,
+            Self::Subtract => h - value,
+            Self::Multiply => h * value,
+            Self::Max => h.max(value),
+            Self::Min => h.min(value),
+            Self::Screen => 1.0 - (1.0 - h) * (1.0 - value),
+            Self::Difference => (h - value).abs(),
+        }
+    }
+}
+
+impl std::fmt::Display for BlendMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Replace => "replace",
+                Self::Add => "add",
+                Self::Subtract => "subtract",
+                Self::Multiply => "multiply",
+                Self::Max => "max",
+                Self::Min => "min",
+                Self::Screen => "screen",
+                Self::Difference => "difference",
+            }
+        )
+    }
+}
+
+/// width in cells of the grid in whose cells the cell based simulations (mud slide, erosions) express their parameters.
+/// They run on the heightmap at its actual resolution and scale their iteration counts and lengths with its cell size
+/// so that they give the same terrain at any world size.
+pub const REFERENCE_SIZE: usize = 256;
+/// maximum width in cells of the reference grid on which the layers are summarized for the streaming exporter
+pub const MAX_REFERENCE_SIZE: usize = 1024;
+
+/// size of the reference grid for a world : as wide as the world within REFERENCE_SIZE..=MAX_REFERENCE_SIZE,
+/// keeping the world's aspect ratio
+pub fn reference_size(size: (usize, usize)) -> (usize, usize) {
+    let width = size.0.clamp(REFERENCE_SIZE, MAX_REFERENCE_SIZE);
+    let height = (width * size.1 + size.0 / 2) / size.0;
+    (width, height.max(2))
+}
+
+/// number of cells of a grid width cells wide per cell of a REFERENCE_SIZE wide grid.
+/// The simulations multiply their lengths in cells by this factor
+pub fn reference_scale(width: usize) -> f32 {
+    width as f32 / REFERENCE_SIZE as f32
+}
+
+/// proportion to apply at each of scale times more iterations to get the same result as applying rate once
+pub fn per_iteration(rate: f32, scale: f32) -> f32 {
+    1.0 - (1.0 - rate).powf(1.0 / scale)
+}
+
+/// locality of a simulation where a cell only depends on the cells at most halo cells away.
+/// The simulation is computed on the whole heightmap when a band with its halo would cover most of the world
+pub fn simulation_locality(size: (usize, usize), halo: usize) -> Locality {
+    if halo * 4 <= size.1 {
+        Locality::Local(halo)
+    } else {
+        Locality::Global
+    }
+}
+
+/// resize a heightmap. Uses the average of the source cells when shrinking, bilinear interpolation when enlarging
+pub fn resample(v: &[f32], size: (usize, usize), new_size: (usize, usize)) -> Vec<f32> {
+    let mut res = vec![0.0; new_size.0 * new_size.1];
+    for y in 0..new_size.1 {
+        for x in 0..new_size.0 {
+            res[x + y * new_size.0] = resampled_value(v, size, new_size, x, y);
+        }
+    }
+    res
+}
+
+/// value of the (x,y) cell of the heightmap v once resized to new_size. See [`resample`]
+pub fn resampled_value(
+    v: &[f32],
+    size: (usize, usize),
+    new_size: (usize, usize),
+    x: usize,
+    y: usize,
+) -> f32 {
+    if new_size.0 <= size.0 && new_size.1 <= size.1 {
+        let xs = shrunk_cells(x, size.0, new_size.0);
+        let ys = shrunk_cells(y, size.1, new_size.1);
+        let count = (xs.len() * ys.len()) as f32;
+        let mut sum = 0.0;
+        for sy in ys {
+            sum += v[xs.start + sy * size.0..xs.end + sy * size.0]
+                .iter()
+                .sum::<f32>();
+        }
+        sum / count
+    } else {
+        let sx = ((x as f32 + 0.5) * size.0 as f32 / new_size.0 as f32 - 0.5)
+            .clamp(0.0, (size.0 - 1) as f32);
+        let sy = ((y as f32 + 0.5) * size.1 as f32 / new_size.1 as f32 - 0.5)
+            .clamp(0.0, (size.1 - 1) as f32);
+        interpolate(v, sx, sy, size)
+    }
+}
+
+/// cells of an axis of size cells averaged into the cell i when shrinking the axis to new_size cells
+pub fn shrunk_cells(i: usize, size: usize, new_size: usize) -> Range<usize> {
+    let start = i * size / new_size;
+    start..((i + 1) * size / new_size).max(start + 1)
+}
+
+/// compute all the cells of a width cells wide grid, splitting the rows between the cpus
+pub fn compute_rows<T: Send>(width: usize, res: &mut [T], cell: impl Fn(usize, usize) -> T + Sync) {
+    rows_job(width, res, None, cell);
+}
+
+/// same as compute_rows, reporting the progress and stopping before the next row when the computation is cancelled.
+/// The remaining rows are left unchanged
+pub fn compute_rows_with_progress<T: Send>(
+    width: usize,
+    res: &mut [T],
+    progress: &Progress,
+    cell: impl Fn(usize, usize) -> T + Sync,
+) {
+    rows_job(width, res, Some(progress), cell);
+}
+
+fn rows_job<T: Send>(
+    width: usize,
+    res: &mut [T],
+    progress: Option<&Progress>,
+    cell: impl Fn(usize, usize) -> T + Sync,
+) {
+    let num_threads = num_cpus::get();
+    let rows = res.len() / width;
+    std::thread::scope(|s| {
+        let size_per_job = rows.div_ceil(num_threads);
+        for (i, chunk) in res.chunks_mut(size_per_job * width).enumerate() {
+            let cell = &cell;
+            let mut progress = progress.cloned();
+            s.spawn(move || {
+                let yoffset = i * size_per_job;
+                for (y, row) in chunk.chunks_mut(width).enumerate() {
+                    if let Some(ref mut progress) = progress {
+                        if progress.cancelled() {
+                            return;
+                        }
+                        // the first job gives the progress of all the jobs
+                        if i == 0 {
+                            progress.report(y as f32 / size_per_job as f32);
+                        }
+                    }
+                    for (x, value) in row.iter_mut().enumerate() {
+                        *value = cell(x, y + yoffset);
+                    }
+                }
+            });
+        }
+    });
+}
 
 const DIRX: [i32; 9] = [0, -1, 0, 1, -1, 1, -1, 0, 1];
 const DIRY: [i32; 9] = [0, -1, -1, -1, 0, 0, 1, 1, 1];
 
-pub fn vec_get_safe<T>(v: &Vec<T>, off: usize) -> T
+pub fn vec_get_safe<T>(v: &[T], off: usize) -> T
 where
     T: Default + Copy,
 {
@@ -58,31 +709,8 @@ pub fn normalize(v: &mut [f32], target_min: f32, target_max: f32) {
     }
 }
 
-pub fn _blur(v: &mut [f32], size: (usize, usize)) {
-    const FACTOR: usize = 8;
-    let small_size: (usize, usize) = (
-        (size.0 + FACTOR - 1) / FACTOR,
-        (size.1 + FACTOR - 1) / FACTOR,
-    );
-    let mut low_res = vec![0.0; small_size.0 * small_size.1];
-    for x in 0..size.0 {
-        for y in 0..size.1 {
-            let value = v[x + y * size.0];
-            let ix = x / FACTOR;
-            let iy = y / FACTOR;
-            low_res[ix + iy * small_size.0] += value;
-        }
-    }
-    let coef = 1.0 / FACTOR as f32;
-    for x in 0..size.0 {
-        for y in 0..size.1 {
-            v[x + y * size.0] =
-                _interpolate(&low_res, x as f32 * coef, y as f32 * coef, small_size);
-        }
-    }
-}
-
-pub fn _interpolate(v: &[f32], x: f32, y: f32, size: (usize, usize)) -> f32 {
+/// bilinear interpolation of the heightmap v at the position x,y (in cells)
+pub fn interpolate(v: &[f32], x: f32, y: f32, size: (usize, usize)) -> f32 {
     let ix = x as usize;
     let iy = y as usize;
     let dx = x.fract();
@@ -108,13 +736,3 @@ pub fn _interpolate(v: &[f32], x: f32, y: f32, size: (usize, usize)) -> f32 {
     let val_s = (1.0 - dx) * val_sw + dx * val_se;
     (1.0 - dy) * val_n + dy * val_s
 }
-
-fn report_progress(progress: f32, export: bool, tx: Sender<ThreadMessage>) {
-    if export {
-        tx.send(ThreadMessage::ExporterStepProgress(progress))
-            .unwrap();
-    } else {
-        tx.send(ThreadMessage::GeneratorStepProgress(progress))
-            .unwrap();
-    }
-}
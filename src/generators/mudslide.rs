@@ -1,13 +1,67 @@
-use std::sync::mpsc::Sender;
-
+#[cfg(feature = "gui")]
 use eframe::egui;
 use serde::{Deserialize, Serialize};
 
-use crate::ThreadMessage;
+use super::{
+    reference_scale, simulation_locality, vec_get_safe, Chunk, Generator, Locality, Progress,
+    StepContext, DIRX, DIRY,
+};
 
-use super::{report_progress, vec_get_safe, DIRX, DIRY};
+pub struct MudSlide;
+
+impl Generator for MudSlide {
+    type Conf = MudSlideConf;
+    fn name(&self) -> &'static str {
+        "MudSlide"
+    }
+    fn description(&self) -> &'static str {
+        "Simulate mud sliding and smoothing the terrain"
+    }
+    fn run(
+        &self,
+        _seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &MudSlideConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_mudslide(size, hmap, conf, progress);
+    }
+    fn extra_memory(&self, size: (usize, usize), _conf: &MudSlideConf) -> usize {
+        // the heightmap of the next iteration
+        size.0 * size.1 * std::mem::size_of::<f32>()
+    }
+    fn locality(&self, size: (usize, usize), conf: &MudSlideConf) -> Locality {
+        // the mud moves by one cell at each iteration
+        simulation_locality(size, iterations(size.0, conf).ceil() as usize)
+    }
+    fn run_chunk(
+        &self,
+        _seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &MudSlideConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        mudslide(
+            chunk.size,
+            hmap,
+            iterations(chunk.world_size.0, conf),
+            conf,
+            progress,
+        );
+        Ok(())
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut MudSlideConf) {
+        render_mudslide(ui, conf);
+    }
+}
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MudSlideConf {
     iterations: f32,
     max_erosion_alt: f32,
@@ -26,7 +80,8 @@ impl Default for MudSlideConf {
     }
 }
 
-pub fn render_mudslide(ui: &mut egui::Ui, conf: &mut MudSlideConf) {
+#[cfg(feature = "gui")]
+fn render_mudslide(ui: &mut egui::Ui, conf: &mut MudSlideConf) {
     ui.horizontal(|ui| {
         ui.label("iterations");
         ui.add(
@@ -59,29 +114,59 @@ pub fn render_mudslide(ui: &mut egui::Ui, conf: &mut MudSlideConf) {
 
 pub fn gen_mudslide(
     size: (usize, usize),
-    hmap: &mut Vec<f32>,
+    hmap: &mut [f32],
     conf: &MudSlideConf,
-    export: bool,
-    tx: Sender<ThreadMessage>,
-    min_progress_step: f32,
+    progress: &mut Progress,
 ) {
-    for i in 0..conf.iterations as usize {
-        mudslide(size, hmap, i, conf, export, tx.clone(), min_progress_step);
-    }
+    mudslide(size, hmap, iterations(size.0, conf), conf, progress);
+}
+
+/// iteration count in a world width cells wide : the mud moves by one cell at each iteration.
+/// The fractional part is a last iteration with a reduced strength
+fn iterations(width: usize, conf: &MudSlideConf) -> f32 {
+    conf.iterations.trunc() * reference_scale(width)
 }
 
 fn mudslide(
     size: (usize, usize),
-    hmap: &mut Vec<f32>,
+    hmap: &mut [f32],
+    iterations: f32,
+    conf: &MudSlideConf,
+    progress: &mut Progress,
+) {
+    let mut new_hmap = vec![0.0; size.0 * size.1];
+    let count = iterations.ceil() as usize;
+    for i in 0..count {
+        let strength = conf.strength * (iterations - i as f32).min(1.0);
+        mudslide_iteration(
+            size,
+            hmap,
+            &mut new_hmap,
+            i,
+            count,
+            strength,
+            conf,
+            progress,
+        );
+        if progress.cancelled() {
+            return;
+        }
+        hmap.copy_from_slice(&new_hmap);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn mudslide_iteration(
+    size: (usize, usize),
+    hmap: &[f32],
+    new_hmap: &mut [f32],
     iteration: usize,
+    iterations: usize,
+    strength: f32,
     conf: &MudSlideConf,
-    export: bool,
-    tx: Sender<ThreadMessage>,
-    min_progress_step: f32,
+    progress: &mut Progress,
 ) {
     let sand_coef = 1.0 / (1.0 - conf.water_level);
-    let mut new_hmap = vec![0.0; size.0 * size.1];
-    let mut progress = 0.0;
     for y in 0..size.1 {
         let yoff = y * size.0;
         for x in 0..size.0 {
@@ -114,17 +199,14 @@ fn mudslide(
             }
             // average height difference with lower neighbours
             let mut dh = sum_delta1 / nb1 + sum_delta2 / nb2;
-            dh *= conf.strength;
+            dh *= strength;
             let hcoef = (h - conf.water_level) * sand_coef;
             dh *= 1.0 - hcoef * hcoef * hcoef; // less smoothing at high altitudes
             new_hmap[x + y * size.0] = h + dh;
         }
-        let new_progress = iteration as f32 / conf.iterations as f32
-            + (y as f32 / size.1 as f32) / conf.iterations as f32;
-        if new_progress - progress >= min_progress_step {
-            progress = new_progress;
-            report_progress(progress, export, tx.clone());
+        progress.report((iteration as f32 + y as f32 / size.1 as f32) / iterations as f32);
+        if progress.cancelled() {
+            return;
         }
     }
-    *hmap = new_hmap;
 }
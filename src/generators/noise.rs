@@ -0,0 +1,356 @@
+#[cfg(feature = "gui")]
+use eframe::egui;
+use noise::{
+    Billow, Fbm, HybridMulti, MultiFractal, NoiseFn, OpenSimplex, Perlin, RidgedMulti, Seedable,
+    SuperSimplex, Worley,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{Chunk, Generator, Locality, Progress, StepContext};
+
+pub struct Noise;
+
+impl Generator for Noise {
+    type Conf = NoiseConf;
+    fn name(&self) -> &'static str {
+        "Noise"
+    }
+    fn description(&self) -> &'static str {
+        "Add fractal noise to generate a mountainous land, ridged mountain chains or billowy hills"
+    }
+    fn run(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &NoiseConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_noise(seed, size, hmap, conf, progress);
+    }
+    fn is_source(&self, _conf: &NoiseConf) -> bool {
+        true
+    }
+    fn locality(&self, _size: (usize, usize), _conf: &NoiseConf) -> Locality {
+        Locality::Local(0)
+    }
+    fn run_chunk(
+        &self,
+        seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &NoiseConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        noise(
+            seed,
+            chunk.world_size,
+            chunk.pos,
+            chunk.size,
+            hmap,
+            conf,
+            progress,
+        );
+        Ok(())
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut NoiseConf) {
+        render_noise(ui, conf);
+    }
+}
+
+/// how the octaves of the base noise are combined
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum FractalType {
+    /// fractional brownian motion : a mountainous land
+    #[default]
+    Fbm,
+    /// sharp ridges : mountain chains
+    RidgedMulti,
+    /// rounded bumps : billowy hills
+    Billow,
+    /// rough peaks and smooth valleys
+    HybridMulti,
+}
+
+impl FractalType {
+    pub const ALL: [FractalType; 4] = [
+        FractalType::Fbm,
+        FractalType::RidgedMulti,
+        FractalType::Billow,
+        FractalType::HybridMulti,
+    ];
+}
+
+impl std::fmt::Display for FractalType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Fbm => "fbm",
+                Self::RidgedMulti => "ridged multi",
+                Self::Billow => "billow",
+                Self::HybridMulti => "hybrid multi",
+            }
+        )
+    }
+}
+
+/// the noise function combined at each octave
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum NoiseSource {
+    #[default]
+    Perlin,
+    /// cellular noise
+    Worley,
+    OpenSimplex,
+    SuperSimplex,
+}
+
+impl NoiseSource {
+    pub const ALL: [NoiseSource; 4] = [
+        NoiseSource::Perlin,
+        NoiseSource::Worley,
+        NoiseSource::OpenSimplex,
+        NoiseSource::SuperSimplex,
+    ];
+}
+
+impl std::fmt::Display for NoiseSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Perlin => "perlin",
+                Self::Worley => "worley",
+                Self::OpenSimplex => "open simplex",
+                Self::SuperSimplex => "super simplex",
+            }
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseConf {
+    pub mulx: f32,
+    pub muly: f32,
+    pub addx: f32,
+    pub addy: f32,
+    pub octaves: f32,
+    pub delta: f32,
+    pub scale: f32,
+    pub fractal: FractalType,
+    pub source: NoiseSource,
+    /// frequency multiplier between two octaves
+    pub lacunarity: f32,
+    /// amplitude multiplier between two octaves
+    pub persistence: f32,
+}
+
+impl Default for NoiseConf {
+    fn default() -> Self {
+        Self {
+            mulx: 2.20,
+            muly: 2.20,
+            addx: 0.0,
+            addy: 0.0,
+            octaves: 6.0,
+            delta: 0.0,
+            scale: 2.05,
+            fractal: FractalType::Fbm,
+            source: NoiseSource::Perlin,
+            // the noise crate defaults, used by the Fbm step of older versions
+            lacunarity: Fbm::<Perlin>::DEFAULT_LACUNARITY as f32,
+            persistence: Fbm::<Perlin>::DEFAULT_PERSISTENCE as f32,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+pub(super) fn render_noise(ui: &mut egui::Ui, conf: &mut NoiseConf) {
+    ui.horizontal(|ui| {
+        ui.label("scale x");
+        ui.add(
+            egui::DragValue::new(&mut conf.mulx)
+                .speed(0.1)
+                .range(0.0..=100.0),
+        );
+        ui.label("y");
+        ui.add(
+            egui::DragValue::new(&mut conf.muly)
+                .speed(0.1)
+                .range(0.0..=100.0),
+        );
+        ui.label("octaves");
+        ui.add(
+            egui::DragValue::new(&mut conf.octaves)
+                .speed(0.5)
+                .range(1.0..=Fbm::<Perlin>::MAX_OCTAVES as f32),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("offset x");
+        ui.add(
+            egui::DragValue::new(&mut conf.addx)
+                .speed(0.1)
+                .range(0.0..=200.0),
+        );
+        ui.label("y");
+        ui.add(
+            egui::DragValue::new(&mut conf.addy)
+                .speed(0.1)
+                .range(0.0..=200.0),
+        );
+        ui.label("scale");
+        ui.add(
+            egui::DragValue::new(&mut conf.scale)
+                .speed(0.01)
+                .range(0.01..=10.0),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("lacunarity")
+            .on_hover_text("frequency multiplier between two octaves");
+        ui.add(
+            egui::DragValue::new(&mut conf.lacunarity)
+                .speed(0.01)
+                .range(1.0..=4.0),
+        );
+        ui.label("persistence")
+            .on_hover_text("amplitude multiplier between two octaves");
+        ui.add(
+            egui::DragValue::new(&mut conf.persistence)
+                .speed(0.01)
+                .range(0.0..=1.0),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("fractal");
+        egui::ComboBox::from_id_salt("noise fractal")
+            .selected_text(conf.fractal.to_string())
+            .show_ui(ui, |ui| {
+                for fractal in FractalType::ALL {
+                    ui.selectable_value(&mut conf.fractal, fractal, fractal.to_string());
+                }
+            });
+        ui.label("source");
+        egui::ComboBox::from_id_salt("noise source")
+            .selected_text(conf.source.to_string())
+            .show_ui(ui, |ui| {
+                for source in NoiseSource::ALL {
+                    ui.selectable_value(&mut conf.source, source, source.to_string());
+                }
+            });
+    });
+}
+
+pub fn gen_noise(
+    seed: u64,
+    size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &NoiseConf,
+    progress: &mut Progress,
+) {
+    noise(seed, size, (0, 0), size, hmap, conf, progress);
+}
+
+/// the configured fractal noise. Worley isn't Send : each thread builds its own noise function
+pub(super) fn noise_fn(seed: u64, conf: &NoiseConf) -> Box<dyn NoiseFn<f64, 2>> {
+    match conf.source {
+        NoiseSource::Perlin => fractal::<Perlin>(seed, conf),
+        NoiseSource::Worley => fractal::<Worley>(seed, conf),
+        NoiseSource::OpenSimplex => fractal::<OpenSimplex>(seed, conf),
+        NoiseSource::SuperSimplex => fractal::<SuperSimplex>(seed, conf),
+    }
+}
+
+fn fractal<T>(seed: u64, conf: &NoiseConf) -> Box<dyn NoiseFn<f64, 2>>
+where
+    T: Default + Seedable + NoiseFn<f64, 2> + 'static,
+{
+    let seed = seed as u32;
+    let octaves = conf.octaves as usize;
+    let lacunarity = conf.lacunarity as f64;
+    let persistence = conf.persistence as f64;
+    match conf.fractal {
+        FractalType::Fbm => Box::new(
+            Fbm::<T>::new(seed)
+                .set_octaves(octaves)
+                .set_lacunarity(lacunarity)
+                .set_persistence(persistence),
+        ),
+        FractalType::RidgedMulti => Box::new(
+            RidgedMulti::<T>::new(seed)
+                .set_octaves(octaves)
+                .set_lacunarity(lacunarity)
+                .set_persistence(persistence),
+        ),
+        FractalType::Billow => Box::new(
+            Billow::<T>::new(seed)
+                .set_octaves(octaves)
+                .set_lacunarity(lacunarity)
+                .set_persistence(persistence),
+        ),
+        FractalType::HybridMulti => Box::new(
+            HybridMulti::<T>::new(seed)
+                .set_octaves(octaves)
+                .set_lacunarity(lacunarity)
+                .set_persistence(persistence),
+        ),
+    }
+}
+
+/// value of the noise at the world position (x,y) in cells, in a world width cells wide
+pub(super) fn noise_value(
+    noise: &dyn NoiseFn<f64, 2>,
+    conf: &NoiseConf,
+    width: usize,
+    x: f32,
+    y: f32,
+) -> f32 {
+    // the world is 512 noise units wide. Same unit on both axes so that rectangular worlds aren't stretched
+    let unit = 512.0 / width as f32;
+    let f0 = (x * unit + conf.addx) * (conf.mulx / 400.0);
+    let f1 = (y * unit + conf.addy) * (conf.muly / 400.0);
+    conf.delta + noise.get([f0 as f64, f1 as f64]) as f32 * conf.scale
+}
+
+/// add the noise of a world of the given size to the chunk at pos of size chunk_size
+fn noise(
+    seed: u64,
+    size: (usize, usize),
+    pos: (usize, usize),
+    chunk_size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &NoiseConf,
+    progress: &mut Progress,
+) {
+    let num_threads = num_cpus::get();
+    std::thread::scope(|s| {
+        let size_per_job = chunk_size.1.div_ceil(num_threads);
+        for (i, chunk) in hmap.chunks_mut(size_per_job * chunk_size.0).enumerate() {
+            let mut progress = progress.clone();
+            s.spawn(move || {
+                let noise = noise_fn(seed, conf);
+                let yoffset = pos.1 + i * size_per_job;
+                for (y, row) in chunk.chunks_mut(chunk_size.0).enumerate() {
+                    if progress.cancelled() {
+                        return;
+                    }
+                    let wy = (y + yoffset) as f32;
+                    for (x, cell) in row.iter_mut().enumerate() {
+                        *cell += noise_value(&*noise, conf, size.0, (x + pos.0) as f32, wy);
+                    }
+                    if i == 0 {
+                        progress.report((y + 1) as f32 / size_per_job as f32);
+                    }
+                }
+            });
+        }
+    });
+}
@@ -1,8 +1,54 @@
 use serde::{Deserialize, Serialize};
 
-use super::normalize;
+use super::{normalize, Chunk, Generator, Locality, Progress, StepContext};
+
+pub struct Normalize;
+
+impl Generator for Normalize {
+    type Conf = NormalizeConf;
+    fn name(&self) -> &'static str {
+        "Normalize"
+    }
+    fn description(&self) -> &'static str {
+        "Scale the terrain back to the 0.0-1.0 range"
+    }
+    fn run(
+        &self,
+        _seed: u64,
+        _size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &NormalizeConf,
+        _ctx: &StepContext,
+        _progress: &mut Progress,
+    ) {
+        gen_normalize(hmap, conf);
+    }
+    fn locality(&self, _size: (usize, usize), _conf: &NormalizeConf) -> Locality {
+        Locality::Local(0)
+    }
+    fn run_chunk(
+        &self,
+        _seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &NormalizeConf,
+        _ctx: &StepContext,
+        _progress: &mut Progress,
+    ) -> Result<(), String> {
+        let invmax = if chunk.layer.min == chunk.layer.max {
+            0.0
+        } else {
+            (conf.max - conf.min) / (chunk.layer.max - chunk.layer.min)
+        };
+        for val in hmap {
+            *val = conf.min + (*val - chunk.layer.min) * invmax;
+        }
+        Ok(())
+    }
+}
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct NormalizeConf {
     pub min: f32,
     pub max: f32,
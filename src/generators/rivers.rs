@@ -0,0 +1,374 @@
+use std::collections::BinaryHeap;
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+
+#[cfg(feature = "gui")]
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use super::fill_depressions::{neighbour, priority_flood, FloodCell};
+use super::{ExtraLayer, Generator, Progress, StepContext};
+
+/// height added to each cell of a filled depression so that the water can flow out of it
+const FILL_EPSILON: f32 = 1e-6;
+/// drainage area in % of the world area of the rivers whose width and depth are the configured ones
+const REFERENCE_AREA: f32 = 10.0;
+
+pub struct Rivers;
+
+impl Generator for Rivers {
+    type Conf = RiversConf;
+    fn name(&self) -> &'static str {
+        "Rivers"
+    }
+    fn description(&self) -> &'static str {
+        "Carve river channels following the water flow, wider and deeper as the drained area grows. The river network is exported as an additional layer"
+    }
+    fn run(
+        &self,
+        _seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &RiversConf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_rivers(size, hmap, conf, ctx.water_level, progress);
+    }
+    fn run_layers(
+        &self,
+        _seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &RiversConf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Vec<ExtraLayer> {
+        vec![ExtraLayer {
+            name: "rivers",
+            values: gen_rivers(size, hmap, conf, ctx.water_level, progress),
+        }]
+    }
+    fn extra_memory(&self, size: (usize, usize), _conf: &RiversConf) -> usize {
+        // filled heights, drainage area, river network, distances to the channels, nearest river cells and queue
+        size.0 * size.1 * 40
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut RiversConf) {
+        render_rivers(ui, conf);
+    }
+}
+
+/// how the water of a cell is distributed to its neighbours
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum FlowModel {
+    /// all the water goes to the steepest neighbour. Thin, well defined rivers
+    #[default]
+    D8,
+    /// the water is split between the two neighbours around the steepest direction. Smoother, braided rivers
+    DInfinity,
+}
+
+impl FlowModel {
+    pub const ALL: [FlowModel; 2] = [FlowModel::D8, FlowModel::DInfinity];
+}
+
+impl std::fmt::Display for FlowModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::D8 => "D8",
+                Self::DInfinity => "D-infinity",
+            }
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiversConf {
+    pub flow: FlowModel,
+    /// minimum drainage area of a river in % of the world area
+    pub threshold: f32,
+    /// width in % of the world width of a river draining 10% of the world. It grows with the square root of the drainage area
+    pub width: f32,
+    /// depth of a river draining 10% of the world. It grows with the square root of the drainage area
+    pub depth: f32,
+}
+
+impl Default for RiversConf {
+    fn default() -> Self {
+        Self {
+            flow: FlowModel::D8,
+            threshold: 0.1,
+            width: 1.0,
+            depth: 0.02,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+fn render_rivers(ui: &mut egui::Ui, conf: &mut RiversConf) {
+    ui.horizontal(|ui| {
+        ui.label("flow");
+        egui::ComboBox::from_id_salt("rivers flow")
+            .selected_text(conf.flow.to_string())
+            .show_ui(ui, |ui| {
+                for flow in FlowModel::ALL {
+                    ui.selectable_value(&mut conf.flow, flow, flow.to_string());
+                }
+            });
+        ui.label("threshold %")
+            .on_hover_text("minimum drained area of a river in percent of the terrain area");
+        ui.add(
+            egui::DragValue::new(&mut conf.threshold)
+                .speed(0.01)
+                .range(0.01..=50.0),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("width %").on_hover_text(
+            "width in percent of the terrain width of a river draining 10% of the terrain",
+        );
+        ui.add(
+            egui::DragValue::new(&mut conf.width)
+                .speed(0.01)
+                .range(0.0..=10.0),
+        );
+        ui.label("depth")
+            .on_hover_text("depth of a river draining 10% of the terrain");
+        ui.add(
+            egui::DragValue::new(&mut conf.depth)
+                .speed(0.001)
+                .range(0.0..=0.5),
+        );
+    });
+}
+
+/// carve the rivers and return the river network, 1.0 in the middle of a river and 0.0 outside.
+/// The rivers end in the sea, below water_level, and aren't carved there. Without water level, they end on the world border
+pub fn gen_rivers(
+    size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &RiversConf,
+    water_level: Option<f32>,
+    progress: &mut Progress,
+) -> Vec<f32> {
+    let water_level = water_level.unwrap_or(f32::MIN);
+    let mut network = vec![0.0; size.0 * size.1];
+    // the water of every land cell can reach the world border or the sea
+    let filled = priority_flood(
+        size,
+        hmap,
+        FILL_EPSILON,
+        |off| hmap[off] < water_level,
+        &mut progress.part(0.0, 0.3),
+    );
+    if progress.cancelled() {
+        return network;
+    }
+    let area = flow_accumulation(size, &filled, conf, water_level, progress);
+    if progress.cancelled() {
+        return network;
+    }
+    carve(
+        size,
+        hmap,
+        &filled,
+        &area,
+        &mut network,
+        conf,
+        water_level,
+        progress,
+    );
+    network
+}
+
+/// directions of the cardinal and diagonal edges of a D-infinity triangular facet
+fn facet_edges(facet: usize) -> (usize, usize) {
+    if facet.is_multiple_of(2) {
+        (facet, (facet + 1) % 8)
+    } else {
+        ((facet + 1) % 8, facet)
+    }
+}
+
+/// receivers of the water of the cell x,y with the proportion of water they get
+fn receivers(
+    size: (usize, usize),
+    filled: &[f32],
+    x: usize,
+    y: usize,
+    flow: FlowModel,
+) -> [(usize, f32); 2] {
+    let h = filled[x + y * size.0];
+    // slope towards a neighbour, or None if it's not lower
+    let slope = |dir: usize| {
+        neighbour(size, x, y, dir).and_then(|off| {
+            let dist = if dir.is_multiple_of(2) { 1.0 } else { SQRT_2 };
+            (filled[off] < h).then(|| (off, (h - filled[off]) / dist))
+        })
+    };
+    // D8 : steepest neighbour. The filled surface guarantees that there's one
+    let steepest = (0..8)
+        .filter_map(slope)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or((usize::MAX, 1.0), |(off, _)| (off, 1.0));
+    if flow == FlowModel::D8 {
+        return [steepest, (usize::MAX, 0.0)];
+    }
+    // D-infinity (Tarboton 1997) : steepest direction on the 8 triangular facets around the cell
+    let height = |dir: usize| neighbour(size, x, y, dir).map(|off| filled[off]);
+    let mut best: Option<(f32, usize, f32)> = None;
+    for facet in 0..8 {
+        let (cardinal, diagonal) = facet_edges(facet);
+        let (Some(h1), Some(h2)) = (height(cardinal), height(diagonal)) else {
+            continue;
+        };
+        let s1 = h - h1;
+        let s2 = h1 - h2;
+        let mut r = s2.atan2(s1);
+        let s = if r < 0.0 {
+            r = 0.0;
+            s1
+        } else if r > FRAC_PI_4 {
+            r = FRAC_PI_4;
+            (h - h2) / SQRT_2
+        } else {
+            (s1 * s1 + s2 * s2).sqrt()
+        };
+        if s > 0.0 && best.is_none_or(|(best_s, _, _)| s > best_s) {
+            best = Some((s, facet, r));
+        }
+    }
+    let Some((_, facet, r)) = best else {
+        return [steepest, (usize::MAX, 0.0)];
+    };
+    let (cardinal, diagonal) = facet_edges(facet);
+    let diagonal_part = r / FRAC_PI_4;
+    match (slope(cardinal), slope(diagonal)) {
+        (Some((c, _)), Some((d, _))) => [(c, 1.0 - diagonal_part), (d, diagonal_part)],
+        (Some((c, _)), None) => [(c, 1.0), (usize::MAX, 0.0)],
+        (None, Some((d, _))) => [(d, 1.0), (usize::MAX, 0.0)],
+        (None, None) => [steepest, (usize::MAX, 0.0)],
+    }
+}
+
+/// drainage area of each cell in % of the world area
+fn flow_accumulation(
+    size: (usize, usize),
+    filled: &[f32],
+    conf: &RiversConf,
+    water_level: f32,
+    progress: &mut Progress,
+) -> Vec<f32> {
+    let cells = size.0 * size.1;
+    let mut area = vec![100.0 / cells as f32; cells];
+    // the water flows from the highest to the lowest cells
+    let mut order: Vec<usize> = (0..cells).collect();
+    order.sort_unstable_by(|a, b| filled[*b].total_cmp(&filled[*a]));
+    progress.report(0.4);
+    for (i, off) in order.iter().enumerate() {
+        // the sea is a sink
+        if filled[*off] >= water_level {
+            let (x, y) = (off % size.0, off / size.0);
+            for (receiver, part) in receivers(size, filled, x, y, conf.flow) {
+                if receiver != usize::MAX {
+                    area[receiver] += area[*off] * part;
+                }
+            }
+        }
+        if i % size.0 == 0 {
+            progress.report(0.4 + 0.2 * i as f32 / cells as f32);
+            if progress.cancelled() {
+                break;
+            }
+        }
+    }
+    area
+}
+
+/// carve a channel along the cells whose drainage area exceeds the threshold.
+/// The channels are grown from the river cells, nearest first relative to the channel radius,
+/// so that each cell is carved once with the cross section of its nearest river cell
+#[allow(clippy::too_many_arguments)]
+fn carve(
+    size: (usize, usize),
+    hmap: &mut [f32],
+    filled: &[f32],
+    area: &[f32],
+    network: &mut [f32],
+    conf: &RiversConf,
+    water_level: f32,
+    progress: &mut Progress,
+) {
+    let cells = size.0 * size.1;
+    let threshold = conf.threshold.max(f32::EPSILON);
+    // radius, bed height and depth of the channel centered on a river cell
+    let channel = |off: usize| {
+        if area[off] < threshold || filled[off] < water_level {
+            return None;
+        }
+        let scale = (area[off] / REFERENCE_AREA).sqrt();
+        // at least the river cell
+        let radius = (conf.width * 0.005 * size.0 as f32 * scale).max(0.5);
+        let depth = conf.depth * scale;
+        // following the filled surface, the river bed always goes down, even through depressions
+        let bed = (filled[off] - depth).max(water_level);
+        Some((radius, bed, depth))
+    };
+    // squared distance to the nearest river cell relative to its channel radius, and this river cell
+    let mut dist = vec![f32::INFINITY; cells];
+    let mut source = vec![usize::MAX; cells];
+    let mut queue = BinaryHeap::new();
+    for off in 0..cells {
+        if channel(off).is_some() {
+            dist[off] = 0.0;
+            source[off] = off;
+            queue.push(FloodCell { h: 0.0, off });
+        }
+    }
+    progress.report(0.7);
+    let mut done = 0;
+    while let Some(FloodCell { h, off }) = queue.pop() {
+        // the cell has been reached by a nearer channel since it was queued
+        if h > dist[off] {
+            continue;
+        }
+        let river = source[off];
+        let Some((radius, _, _)) = channel(river) else {
+            continue;
+        };
+        let (rx, ry) = ((river % size.0) as i32, (river / size.0) as i32);
+        let (x, y) = (off % size.0, off / size.0);
+        for dir in 0..8 {
+            let Some(noff) = neighbour(size, x, y, dir) else {
+                continue;
+            };
+            let dx = (noff % size.0) as i32 - rx;
+            let dy = (noff / size.0) as i32 - ry;
+            let t2 = (dx * dx + dy * dy) as f32 / (radius * radius);
+            if t2 < 1.0 && t2 < dist[noff] {
+                dist[noff] = t2;
+                source[noff] = river;
+                queue.push(FloodCell { h: t2, off: noff });
+            }
+        }
+        done += 1;
+        if done % size.0 == 0 {
+            progress.report(0.7 + 0.3 * (done as f32 / cells as f32).min(1.0));
+            if progress.cancelled() {
+                return;
+            }
+        }
+    }
+    for (off, river) in source.iter().enumerate() {
+        if let Some((_, bed, depth)) = (*river != usize::MAX).then(|| channel(*river)).flatten() {
+            let t2 = dist[off];
+            // parabolic cross section
+            hmap[off] = hmap[off].min(bed + t2 * depth);
+            network[off] = 1.0 - t2;
+        }
+    }
+}
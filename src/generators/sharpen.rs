@@ -0,0 +1,128 @@
+#[cfg(feature = "gui")]
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use super::blur::{blur, blur_sigma, kernel_radius};
+use super::{Chunk, Generator, Locality, Progress, StepContext};
+
+pub struct Sharpen;
+
+impl Generator for Sharpen {
+    type Conf = SharpenConf;
+    fn name(&self) -> &'static str {
+        "Sharpen"
+    }
+    fn description(&self) -> &'static str {
+        "Enhance the terrain details by amplifying its difference with a blurred copy (unsharp mask)"
+    }
+    fn run(
+        &self,
+        _seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &SharpenConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_sharpen(size, hmap, conf, progress);
+    }
+    fn extra_memory(&self, size: (usize, usize), _conf: &SharpenConf) -> usize {
+        // the blurred copy and its horizontally blurred version
+        2 * size.0 * size.1 * std::mem::size_of::<f32>()
+    }
+    fn locality(&self, size: (usize, usize), conf: &SharpenConf) -> Locality {
+        Locality::Local(kernel_radius(blur_sigma(size, conf.radius)))
+    }
+    fn run_chunk(
+        &self,
+        _seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &SharpenConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        sharpen(
+            chunk.size,
+            hmap,
+            blur_sigma(chunk.world_size, conf.radius),
+            conf,
+            progress,
+        );
+        Ok(())
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut SharpenConf) {
+        render_sharpen(ui, conf);
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SharpenConf {
+    /// radius of the blur in % of the world width. Details smaller than the radius are enhanced
+    pub radius: f32,
+    /// multiplier of the difference between the terrain and its blurred copy added to the terrain
+    pub amount: f32,
+}
+
+impl Default for SharpenConf {
+    fn default() -> Self {
+        Self {
+            radius: 1.0,
+            amount: 0.5,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+fn render_sharpen(ui: &mut egui::Ui, conf: &mut SharpenConf) {
+    ui.horizontal(|ui| {
+        ui.label("radius %")
+            .on_hover_text("size of the enhanced details in percent of the terrain width");
+        ui.add(
+            egui::DragValue::new(&mut conf.radius)
+                .speed(0.01)
+                .range(0.0..=10.0),
+        );
+        ui.label("amount");
+        ui.add(
+            egui::DragValue::new(&mut conf.amount)
+                .speed(0.01)
+                .range(0.0..=5.0),
+        );
+    });
+}
+
+pub fn gen_sharpen(
+    size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &SharpenConf,
+    progress: &mut Progress,
+) {
+    sharpen(size, hmap, blur_sigma(size, conf.radius), conf, progress);
+}
+
+fn sharpen(
+    size: (usize, usize),
+    hmap: &mut [f32],
+    sigma: f32,
+    conf: &SharpenConf,
+    progress: &mut Progress,
+) {
+    let mut blurred = hmap.to_vec();
+    blur(
+        size,
+        &mut blurred,
+        sigma,
+        None,
+        &mut progress.part(0.0, 0.9),
+    );
+    if progress.cancelled() {
+        return;
+    }
+    for (h, b) in hmap.iter_mut().zip(blurred.iter()) {
+        *h += (*h - *b) * conf.amount;
+    }
+    progress.report(1.0);
+}
@@ -0,0 +1,213 @@
+#[cfg(feature = "gui")]
+use eframe::egui;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+
+use super::{get_min_max, Chunk, Generator, Locality, Progress, StepContext};
+
+/// octaves of the level jitter noise
+const JITTER_OCTAVES: usize = 4;
+
+pub struct Terrace;
+
+impl Generator for Terrace {
+    type Conf = TerraceConf;
+    fn name(&self) -> &'static str {
+        "Terrace"
+    }
+    fn description(&self) -> &'static str {
+        "Quantize the heights into levels to create rice paddies, mesas and stepped landscapes"
+    }
+    fn run(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &TerraceConf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_terrace(seed, size, hmap, conf, ctx.water_level, progress);
+    }
+    fn locality(&self, _size: (usize, usize), _conf: &TerraceConf) -> Locality {
+        Locality::Local(0)
+    }
+    fn run_chunk(
+        &self,
+        seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &TerraceConf,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        terrace(
+            seed,
+            chunk.world_size,
+            chunk.pos,
+            chunk.size,
+            hmap,
+            (chunk.layer.min, chunk.layer.max),
+            conf,
+            ctx.water_level,
+            progress,
+        );
+        Ok(())
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut TerraceConf) {
+        render_terrace(ui, conf);
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerraceConf {
+    pub levels: f32,
+    /// 0.0 : unchanged terrain, 1.0 : flat levels separated by vertical cliffs
+    pub sharpness: f32,
+    /// random displacement of the level borders, in levels 0.0-1.0
+    pub jitter: f32,
+    /// number of jitter noise features across the world width
+    pub jitter_frequency: f32,
+    /// leave the terrain under the water level of the previous LandMass step untouched
+    pub keep_underwater: bool,
+}
+
+impl Default for TerraceConf {
+    fn default() -> Self {
+        Self {
+            levels: 8.0,
+            sharpness: 0.7,
+            jitter: 0.0,
+            jitter_frequency: 8.0,
+            keep_underwater: true,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+fn render_terrace(ui: &mut egui::Ui, conf: &mut TerraceConf) {
+    ui.horizontal(|ui| {
+        ui.label("levels");
+        ui.add(
+            egui::DragValue::new(&mut conf.levels)
+                .speed(0.5)
+                .range(1.0..=100.0),
+        );
+        ui.label("sharpness")
+            .on_hover_text("0 : unchanged terrain, 1 : flat levels separated by cliffs");
+        ui.add(
+            egui::DragValue::new(&mut conf.sharpness)
+                .speed(0.01)
+                .range(0.0..=1.0),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("jitter")
+            .on_hover_text("random displacement of the levels borders");
+        ui.add(
+            egui::DragValue::new(&mut conf.jitter)
+                .speed(0.01)
+                .range(0.0..=1.0),
+        );
+        ui.label("frequency")
+            .on_hover_text("number of jitter noise features across the terrain width");
+        ui.add(
+            egui::DragValue::new(&mut conf.jitter_frequency)
+                .speed(0.1)
+                .range(0.1..=100.0),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut conf.keep_underwater, "keep underwater terrain")
+            .on_hover_text("below the water level of the previous LandMass step");
+    });
+}
+
+pub fn gen_terrace(
+    seed: u64,
+    size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &TerraceConf,
+    water_level: Option<f32>,
+    progress: &mut Progress,
+) {
+    let min_max = get_min_max(hmap);
+    terrace(
+        seed,
+        size,
+        (0, 0),
+        size,
+        hmap,
+        min_max,
+        conf,
+        water_level,
+        progress,
+    );
+}
+
+/// terrace the chunk at pos of size chunk_size of a world of the given size whose heights are in the min_max range.
+/// water_level is the height of the water plane, if any
+#[allow(clippy::too_many_arguments)]
+fn terrace(
+    seed: u64,
+    size: (usize, usize),
+    pos: (usize, usize),
+    chunk_size: (usize, usize),
+    hmap: &mut [f32],
+    (min, max): (f32, f32),
+    conf: &TerraceConf,
+    water_level: Option<f32>,
+    progress: &mut Progress,
+) {
+    let bottom = match water_level {
+        Some(water_level) if conf.keep_underwater => water_level.max(min),
+        _ => min,
+    };
+    if max <= bottom {
+        return;
+    }
+    let levels = conf.levels.max(1.0);
+    let level_height = (max - bottom) / levels;
+    // flat part of each level followed by a steeper riser
+    let exponent = 1.0 / (1.0 - conf.sharpness.min(0.99));
+    // same unit on both axes so that rectangular worlds aren't stretched
+    let coef = conf.jitter_frequency / size.0 as f32;
+    let num_threads = num_cpus::get();
+    std::thread::scope(|s| {
+        let size_per_job = chunk_size.1.div_ceil(num_threads);
+        for (i, chunk) in hmap.chunks_mut(size_per_job * chunk_size.0).enumerate() {
+            let mut progress = progress.clone();
+            s.spawn(move || {
+                let noise = Fbm::<Perlin>::new(seed as u32).set_octaves(JITTER_OCTAVES);
+                let yoffset = pos.1 + i * size_per_job;
+                for (y, row) in chunk.chunks_mut(chunk_size.0).enumerate() {
+                    if progress.cancelled() {
+                        return;
+                    }
+                    let wy = ((y + yoffset) as f32 * coef) as f64;
+                    for (x, h) in row.iter_mut().enumerate() {
+                        if *h < bottom {
+                            continue;
+                        }
+                        let jitter = if conf.jitter > 0.0 {
+                            let wx = ((x + pos.0) as f32 * coef) as f64;
+                            noise.get([wx, wy]) as f32 * conf.jitter
+                        } else {
+                            0.0
+                        };
+                        // position in levels, shifted by the jitter so that the levels borders move
+                        let t = (*h - bottom) / level_height + jitter;
+                        let level = t.floor();
+                        let terraced = level + (t - level).powf(exponent) - jitter;
+                        *h = (bottom + terraced * level_height).max(bottom);
+                    }
+                    if i == 0 {
+                        progress.report((y + 1) as f32 / size_per_job as f32);
+                    }
+                }
+            });
+        }
+    });
+}
@@ -0,0 +1,192 @@
+#[cfg(feature = "gui")]
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    compute_rows, reference_scale, simulation_locality, Chunk, Generator, Locality, Progress,
+    StepContext, DIRX, DIRY,
+};
+
+/// width of the world in height units : a 0.0-1.0 terrain is as high as a tenth of its width
+pub const HORIZONTAL_SCALE: f32 = 10.0;
+
+pub struct ThermalErosion;
+
+impl Generator for ThermalErosion {
+    type Conf = ThermalErosionConf;
+    fn name(&self) -> &'static str {
+        "ThermalErosion"
+    }
+    fn description(&self) -> &'static str {
+        "Simulate rocks falling from the slopes steeper than the talus angle, forming scree slopes at the feet of cliffs"
+    }
+    fn run(
+        &self,
+        _seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &ThermalErosionConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_thermal_erosion(size, hmap, conf, progress);
+    }
+    fn extra_memory(&self, size: (usize, usize), _conf: &ThermalErosionConf) -> usize {
+        // the outflow is two values per cell, plus the heightmap of the next iteration
+        size.0 * size.1 * std::mem::size_of::<f32>() * 3
+    }
+    fn locality(&self, size: (usize, usize), conf: &ThermalErosionConf) -> Locality {
+        // the outflow of a cell depends on its neighbours and its new height on their outflow
+        simulation_locality(size, 2 * iterations(size.0, conf))
+    }
+    fn run_chunk(
+        &self,
+        _seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &ThermalErosionConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        thermal_erosion(chunk.size, chunk.world_size.0, hmap, conf, progress);
+        Ok(())
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut ThermalErosionConf) {
+        render_thermal_erosion(ui, conf);
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThermalErosionConf {
+    /// maximum slope angle in degrees before material starts falling (see [`HORIZONTAL_SCALE`])
+    pub talus_angle: f32,
+    pub iterations: f32,
+    /// proportion of the material above the talus angle moved at each iteration 0.0-1.0
+    pub amount: f32,
+}
+
+impl Default for ThermalErosionConf {
+    fn default() -> Self {
+        Self {
+            talus_angle: 35.0,
+            iterations: 50.0,
+            amount: 0.5,
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+fn render_thermal_erosion(ui: &mut egui::Ui, conf: &mut ThermalErosionConf) {
+    ui.horizontal(|ui| {
+        ui.label("talus angle")
+            .on_hover_text("slopes steeper than this angle (in degrees) collapse");
+        ui.add(
+            egui::DragValue::new(&mut conf.talus_angle)
+                .speed(0.5)
+                .range(0.0..=89.0),
+        );
+        ui.label("iterations");
+        ui.add(
+            egui::DragValue::new(&mut conf.iterations)
+                .speed(1.0)
+                .range(1.0..=500.0),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("amount")
+            .on_hover_text("proportion of the unstable material moved at each iteration");
+        ui.add(
+            egui::DragValue::new(&mut conf.amount)
+                .speed(0.01)
+                .range(0.0..=1.0),
+        );
+    });
+}
+
+pub fn gen_thermal_erosion(
+    size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &ThermalErosionConf,
+    progress: &mut Progress,
+) {
+    thermal_erosion(size, size.0, hmap, conf, progress);
+}
+
+/// iteration count in a world width cells wide. The material moves by one cell at each iteration and spreads
+/// like a diffusion : twice finer grids need four times more iterations
+fn iterations(width: usize, conf: &ThermalErosionConf) -> usize {
+    let scale = reference_scale(width);
+    (conf.iterations.trunc() * scale * scale).round() as usize
+}
+
+/// height difference above which material falls between a cell and its neighbour in direction dir
+fn neighbour_talus(talus: f32, dir: usize) -> f32 {
+    if dir == 1 || dir == 3 || dir == 6 || dir == 8 {
+        // diagonal neighbour
+        talus * std::f32::consts::SQRT_2
+    } else {
+        talus
+    }
+}
+
+/// the neighbours of (x,y) with their direction index in DIRX/DIRY
+fn neighbours(size: (usize, usize), x: usize, y: usize) -> impl Iterator<Item = (usize, usize)> {
+    (1..9).filter_map(move |i| {
+        let ix = (x as i32 + DIRX[i]) as usize;
+        let iy = (y as i32 + DIRY[i]) as usize;
+        (ix < size.0 && iy < size.1).then(|| (i, ix + iy * size.0))
+    })
+}
+
+/// erode a part of a world world_width cells wide
+fn thermal_erosion(
+    size: (usize, usize),
+    world_width: usize,
+    hmap: &mut [f32],
+    conf: &ThermalErosionConf,
+    progress: &mut Progress,
+) {
+    let cell_width = HORIZONTAL_SCALE / world_width as f32;
+    let talus = conf.talus_angle.to_radians().tan() * cell_width;
+    let iterations = iterations(world_width, conf);
+    // material leaving each cell and the sum of the height differences above the talus with its lower neighbours
+    let mut outflow = vec![(0.0, 0.0); size.0 * size.1];
+    let mut new_hmap = vec![0.0; size.0 * size.1];
+    for iteration in 0..iterations {
+        if progress.cancelled() {
+            return;
+        }
+        let h: &[f32] = hmap;
+        compute_rows(size.0, &mut outflow, |x, y| {
+            let off = x + y * size.0;
+            let mut max_excess: f32 = 0.0;
+            let mut sum_excess = 0.0;
+            for (dir, n) in neighbours(size, x, y) {
+                let excess = h[off] - h[n] - neighbour_talus(talus, dir);
+                if excess > 0.0 {
+                    max_excess = max_excess.max(excess);
+                    sum_excess += excess;
+                }
+            }
+            // moving half the largest excess at most so that the cell doesn't become lower than its neighbours
+            (conf.amount * max_excess * 0.5, sum_excess)
+        });
+        let outflow: &[(f32, f32)] = &outflow;
+        compute_rows(size.0, &mut new_hmap, |x, y| {
+            let off = x + y * size.0;
+            let mut new_h = h[off] - outflow[off].0;
+            for (dir, n) in neighbours(size, x, y) {
+                let excess = h[n] - h[off] - neighbour_talus(talus, dir);
+                if excess > 0.0 {
+                    // share of the higher neighbour's material falling on this cell
+                    new_h += outflow[n].0 * excess / outflow[n].1;
+                }
+            }
+            new_h
+        });
+        hmap.copy_from_slice(&new_hmap);
+        progress.report((iteration + 1) as f32 / iterations as f32);
+    }
+}
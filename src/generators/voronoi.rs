@@ -0,0 +1,320 @@
+#[cfg(feature = "gui")]
+use eframe::egui;
+use rand::{prelude::*, rngs::StdRng};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::{
+    compute_rows_with_progress, BlendMode, Chunk, Generator, Locality, Progress, StepContext,
+};
+
+pub struct Voronoi;
+
+impl Generator for Voronoi {
+    type Conf = VoronoiConf;
+    fn name(&self) -> &'static str {
+        "Voronoi"
+    }
+    fn description(&self) -> &'static str {
+        "Add cellular features (mesas, cracked plateaus, ridge networks) computed from the distances to random points"
+    }
+    fn run(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &VoronoiConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_voronoi(seed, size, hmap, conf, progress);
+    }
+    fn is_source(&self, _conf: &VoronoiConf) -> bool {
+        true
+    }
+    fn locality(&self, _size: (usize, usize), _conf: &VoronoiConf) -> Locality {
+        Locality::Local(0)
+    }
+    fn run_chunk(
+        &self,
+        seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        conf: &VoronoiConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        voronoi(
+            seed,
+            chunk.world_size,
+            chunk.pos,
+            chunk.size,
+            hmap,
+            conf,
+            progress,
+        );
+        Ok(())
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut VoronoiConf) {
+        render_voronoi(ui, conf);
+    }
+}
+
+/// how the distance between a cell and a feature point is measured
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum DistanceMetric {
+    /// round cells
+    #[default]
+    Euclidean,
+    /// diamond shaped cells
+    Manhattan,
+    /// square cells
+    Chebyshev,
+}
+
+impl DistanceMetric {
+    pub const ALL: [DistanceMetric; 3] = [
+        DistanceMetric::Euclidean,
+        DistanceMetric::Manhattan,
+        DistanceMetric::Chebyshev,
+    ];
+    fn distance(&self, dx: f32, dy: f32) -> f32 {
+        match self {
+            Self::Euclidean => (dx * dx + dy * dy).sqrt(),
+            Self::Manhattan => dx.abs() + dy.abs(),
+            Self::Chebyshev => dx.abs().max(dy.abs()),
+        }
+    }
+}
+
+impl std::fmt::Display for DistanceMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Euclidean => "euclidean",
+                Self::Manhattan => "manhattan",
+                Self::Chebyshev => "chebyshev",
+            }
+        )
+    }
+}
+
+/// the value computed from F1 and F2, the distances to the nearest and second nearest feature points
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum VoronoiCombination {
+    /// cones around the feature points
+    #[default]
+    F1,
+    F2,
+    /// null on the cells borders : ridge networks or cracks
+    F2MinusF1,
+    F1TimesF2,
+    /// a random height per cell : mesas and plateaus
+    Cell,
+}
+
+impl VoronoiCombination {
+    pub const ALL: [VoronoiCombination; 5] = [
+        VoronoiCombination::F1,
+        VoronoiCombination::F2,
+        VoronoiCombination::F2MinusF1,
+        VoronoiCombination::F1TimesF2,
+        VoronoiCombination::Cell,
+    ];
+}
+
+impl std::fmt::Display for VoronoiCombination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::F1 => "F1",
+                Self::F2 => "F2",
+                Self::F2MinusF1 => "F2 - F1",
+                Self::F1TimesF2 => "F1 * F2",
+                Self::Cell => "cell",
+            }
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoronoiConf {
+    /// number of cells across the world width
+    pub cells: f32,
+    /// random displacement of the feature points from the center of their cell 0.0-1.0
+    pub jitter: f32,
+    pub metric: DistanceMetric,
+    pub combination: VoronoiCombination,
+    /// blend mode of the projects saved by the 0.4.0 development versions,
+    /// moved to the step blend mode when the project is loaded
+    #[doc(hidden)]
+    #[serde(
+        rename = "blend",
+        skip_serializing,
+        deserialize_with = "deserialize_legacy_blend"
+    )]
+    pub legacy_blend: Option<BlendMode>,
+    /// multiplier of the voronoi values, about 0.0-1.0 before scaling
+    pub height: f32,
+}
+
+impl Default for VoronoiConf {
+    fn default() -> Self {
+        Self {
+            cells: 16.0,
+            jitter: 1.0,
+            metric: DistanceMetric::Euclidean,
+            combination: VoronoiCombination::F1,
+            legacy_blend: None,
+            height: 0.3,
+        }
+    }
+}
+
+fn deserialize_legacy_blend<'de, D: Deserializer<'de>>(
+    de: D,
+) -> Result<Option<BlendMode>, D::Error> {
+    BlendMode::deserialize(de).map(Some)
+}
+
+#[cfg(feature = "gui")]
+fn render_voronoi(ui: &mut egui::Ui, conf: &mut VoronoiConf) {
+    ui.horizontal(|ui| {
+        ui.label("cells")
+            .on_hover_text("number of cells across the terrain width");
+        ui.add(
+            egui::DragValue::new(&mut conf.cells)
+                .speed(0.5)
+                .range(1.0..=256.0),
+        );
+        ui.label("jitter")
+            .on_hover_text("0 : regular grid, 1 : random points");
+        ui.add(
+            egui::DragValue::new(&mut conf.jitter)
+                .speed(0.01)
+                .range(0.0..=1.0),
+        );
+        ui.label("height");
+        ui.add(
+            egui::DragValue::new(&mut conf.height)
+                .speed(0.01)
+                .range(-2.0..=2.0),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("value");
+        egui::ComboBox::from_id_salt("voronoi combination")
+            .selected_text(conf.combination.to_string())
+            .show_ui(ui, |ui| {
+                for combination in VoronoiCombination::ALL {
+                    ui.selectable_value(
+                        &mut conf.combination,
+                        combination,
+                        combination.to_string(),
+                    );
+                }
+            });
+        ui.label("metric");
+        egui::ComboBox::from_id_salt("voronoi metric")
+            .selected_text(conf.metric.to_string())
+            .show_ui(ui, |ui| {
+                for metric in DistanceMetric::ALL {
+                    ui.selectable_value(&mut conf.metric, metric, metric.to_string());
+                }
+            });
+    });
+}
+
+pub fn gen_voronoi(
+    seed: u64,
+    size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &VoronoiConf,
+    progress: &mut Progress,
+) {
+    voronoi(seed, size, (0, 0), size, hmap, conf, progress);
+}
+
+/// feature point of a grid cell in grid units, with the random height of its voronoi cell
+struct FeaturePoint {
+    x: f32,
+    y: f32,
+    value: f32,
+}
+
+/// one feature point per cell of a grid_size grid
+fn feature_points(seed: u64, grid_size: (usize, usize), jitter: f32) -> Vec<FeaturePoint> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut points = Vec::with_capacity(grid_size.0 * grid_size.1);
+    for gy in 0..grid_size.1 {
+        for gx in 0..grid_size.0 {
+            let dx: f32 = rng.random_range(-0.5..0.5);
+            let dy: f32 = rng.random_range(-0.5..0.5);
+            points.push(FeaturePoint {
+                x: gx as f32 + 0.5 + dx * jitter,
+                y: gy as f32 + 0.5 + dy * jitter,
+                value: rng.random_range(0.0..1.0),
+            });
+        }
+    }
+    points
+}
+
+/// compute the voronoi values of a world of the given size in the chunk at pos of size chunk_size.
+/// They replace the heights, the step blend mode combines them with the terrain
+fn voronoi(
+    seed: u64,
+    size: (usize, usize),
+    pos: (usize, usize),
+    chunk_size: (usize, usize),
+    hmap: &mut [f32],
+    conf: &VoronoiConf,
+    progress: &mut Progress,
+) {
+    // square grid cells, the same whatever the heightmap resolution
+    let cell_size = size.0 as f32 / conf.cells.max(1.0);
+    let grid_size = (
+        conf.cells.max(1.0).ceil() as usize,
+        (size.1 as f32 / cell_size).ceil().max(1.0) as usize,
+    );
+    let points = feature_points(seed, grid_size, conf.jitter);
+    compute_rows_with_progress(chunk_size.0, hmap, progress, |x, y| {
+        let u = ((x + pos.0) as f32 + 0.5) / cell_size;
+        let v = ((y + pos.1) as f32 + 0.5) / cell_size;
+        let (gx, gy) = (u as i32, v as i32);
+        let mut f1 = f32::MAX;
+        let mut f2 = f32::MAX;
+        let mut f1_value = 0.0;
+        // with a full jitter, the second nearest point can be two cells away
+        for py in (gy - 2).max(0)..(gy + 3).min(grid_size.1 as i32) {
+            for px in (gx - 2).max(0)..(gx + 3).min(grid_size.0 as i32) {
+                let p = &points[px as usize + py as usize * grid_size.0];
+                let d = conf.metric.distance(p.x - u, p.y - v);
+                if d < f1 {
+                    f2 = f1;
+                    f1 = d;
+                    f1_value = p.value;
+                } else if d < f2 {
+                    f2 = d;
+                }
+            }
+        }
+        if f2 == f32::MAX {
+            // single cell grid
+            f2 = f1;
+        }
+        let value = match conf.combination {
+            VoronoiCombination::F1 => f1,
+            VoronoiCombination::F2 => f2,
+            VoronoiCombination::F2MinusF1 => f2 - f1,
+            VoronoiCombination::F1TimesF2 => f1 * f2,
+            VoronoiCombination::Cell => f1_value,
+        };
+        value * conf.height
+    });
+}
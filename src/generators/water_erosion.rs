@@ -1,14 +1,12 @@
-use std::sync::mpsc::Sender;
-
+#[cfg(feature = "gui")]
 use eframe::egui;
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
-use crate::ThreadMessage;
-
-use super::report_progress;
+use super::{per_iteration, reference_scale, Generator, Progress, StepContext};
 
 // water erosion algorithm adapted from https://www.firespark.de/resources/downloads/implementation%20of%20a%20methode%20for%20hydraulic%20erosion.pdf
+// lengths and radius are expressed in cells of a REFERENCE_SIZE wide grid and scaled with the heightmap cells
 const MAX_PATH_LENGTH: usize = 40;
 const DEFAULT_EVAPORATION: f32 = 0.05;
 const DEFAULT_CAPACITY: f32 = 8.0;
@@ -18,6 +16,8 @@ const DEFAULT_INERTIA: f32 = 0.4;
 const DEFAULT_DROP_AMOUNT: f32 = 0.5;
 const DEFAULT_EROSION_STRENGTH: f32 = 0.1;
 const DEFAULT_RADIUS: f32 = 4.0;
+/// part of the drop kinetic energy lost at each step
+const FRICTION: f32 = 0.3;
 
 /// a drop of water
 struct Drop {
@@ -36,12 +36,41 @@ struct Drop {
 }
 
 impl Drop {
+    /// offset of the top left cell of the four cells surrounding the drop
     pub fn grid_offset(&self, grid_width: usize) -> usize {
-        self.pos.0.round() as usize + self.pos.1.round() as usize * grid_width
+        self.pos.0 as usize + self.pos.1 as usize * grid_width
+    }
+}
+
+pub struct WaterErosion;
+
+impl Generator for WaterErosion {
+    type Conf = WaterErosionConf;
+    fn name(&self) -> &'static str {
+        "WaterErosion"
+    }
+    fn description(&self) -> &'static str {
+        "Simulate rain falling and carving rivers"
+    }
+    fn run(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        conf: &WaterErosionConf,
+        _ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        gen_water_erosion(seed, size, hmap, conf, progress);
+    }
+    #[cfg(feature = "gui")]
+    fn render(&self, ui: &mut egui::Ui, conf: &mut WaterErosionConf) {
+        render_water_erosion(ui, conf);
     }
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct WaterErosionConf {
     drop_amount: f32,
     erosion_strength: f32,
@@ -68,7 +97,8 @@ impl Default for WaterErosionConf {
     }
 }
 
-pub fn render_water_erosion(ui: &mut egui::Ui, conf: &mut WaterErosionConf) {
+#[cfg(feature = "gui")]
+fn render_water_erosion(ui: &mut egui::Ui, conf: &mut WaterErosionConf) {
     ui.horizontal(|ui| {
         ui.label("drop amount")
             .on_hover_text("Amount of drops simulated");
@@ -139,24 +169,29 @@ pub fn gen_water_erosion(
     size: (usize, usize),
     hmap: &mut [f32],
     conf: &WaterErosionConf,
-    export: bool,
-    tx: Sender<ThreadMessage>,
-    min_progress_step: f32,
+    progress: &mut Progress,
 ) {
-    let mut progress = 0.0;
     let mut rng = StdRng::seed_from_u64(seed);
-    // maximum drop count is 2 per cell
-    let drop_count = ((size.1 * 2) as f32 * conf.drop_amount) as usize;
-    // compute erosion weight depending on radius
-    let mut erosion_weight = 0.0;
-    for y in (-conf.radius).round() as i32..conf.radius.round() as i32 {
-        for x in (-conf.radius).round() as i32..conf.radius.round() as i32 {
-            let dist = ((x * x + y * y) as f32).sqrt();
-            if dist < conf.radius {
-                erosion_weight += conf.radius - dist;
-            }
-        }
-    }
+    let scale = reference_scale(size.0);
+    // the drops move by one cell at each step, but not more than a cell of the REFERENCE_SIZE wide grid :
+    // on a finer grid, they make more, smaller steps
+    let step = scale.min(1.0);
+    // steps per cell of the REFERENCE_SIZE wide grid
+    let steps = scale / step;
+    // at least the cells around the drop are eroded
+    let radius = (conf.radius * scale).max(1.0);
+    let max_path_length = (MAX_PATH_LENGTH as f32 * steps).round() as usize;
+    let erosion_strength = per_iteration(conf.erosion_strength, steps);
+    let deposition = per_iteration(conf.deposition, steps);
+    let evaporation = per_iteration(conf.evaporation, steps);
+    let inertia = conf.inertia.powf(1.0 / steps);
+    let friction = per_iteration(FRICTION, steps);
+    // on a grid coarser than the REFERENCE_SIZE wide grid, the drops are as dense as on this grid and
+    // carry proportionally less sediment, so that a few drops don't shape the whole terrain
+    let density = (1.0 / (scale * scale)).max(1.0);
+    let capacity = conf.capacity / density;
+    // maximum drop count is 2 per cell of the finer of both grids
+    let drop_count = ((size.1 * 2) as f32 * conf.drop_amount * density) as usize;
     // use a double loop to check progress every size.0 drops
     for y in 0..drop_count {
         for _ in 0..size.0 {
@@ -168,12 +203,12 @@ pub fn gen_water_erosion(
                 dir: (0.0, 0.0),
                 sediment: 0.0,
                 water: 1.0,
-                capacity: conf.capacity,
+                capacity,
                 speed: 0.0,
             };
             let mut off = drop.grid_offset(size.0);
             let mut count = 0;
-            while count < MAX_PATH_LENGTH {
+            while count < max_path_length {
                 let oldh = hmap[off];
                 let old_off = off;
                 // interpolate slope at old position
@@ -193,17 +228,19 @@ pub fn gen_water_erosion(
                 let mut gy = (h00 - h01) * (1.0 - old_u) + (h10 - h11) * old_u;
                 (gx, gy) = normalize_dir(gx, gy, &mut rng);
                 // interpolate between old direction and new one to account for inertia
-                gx = (drop.dir.0 - gx) * conf.inertia + gx;
-                gy = (drop.dir.1 - gy) * conf.inertia + gy;
+                gx = (drop.dir.0 - gx) * inertia + gx;
+                gy = (drop.dir.1 - gy) * inertia + gy;
                 (drop.dir.0, drop.dir.1) = normalize_dir(gx, gy, &mut rng);
                 let old_x = drop.pos.0;
                 let old_y = drop.pos.1;
                 // compute the droplet new position
-                drop.pos.0 += drop.dir.0;
-                drop.pos.1 += drop.dir.1;
-                let ix = drop.pos.0.round() as usize;
-                let iy = drop.pos.1.round() as usize;
-                if ix >= size.0 - 1 || iy >= size.1 - 1 {
+                drop.pos.0 += drop.dir.0 * step;
+                drop.pos.1 += drop.dir.1 * step;
+                if drop.pos.0 < 0.0
+                    || drop.pos.1 < 0.0
+                    || drop.pos.0 >= (size.0 - 1) as f32
+                    || drop.pos.1 >= (size.1 - 1) as f32
+                {
                     // out of the map
                     break;
                 }
@@ -226,17 +263,17 @@ pub fn gen_water_erosion(
                     hmap[old_off + size.0] += deposit * w01;
                     hmap[old_off + 1 + size.0] += deposit * w11;
                     drop.sediment -= deposit;
-                    drop.speed = 0.0;
                     if drop.sediment <= 0.0 {
                         // no more sediment. stop the path
                         break;
                     }
                 } else {
+                    // slope in height units per cell of the REFERENCE_SIZE wide grid
                     drop.capacity =
-                        conf.min_slope.max(-hdif) * drop.water * conf.capacity * drop.speed;
+                        conf.min_slope.max(-hdif * steps) * drop.water * capacity * drop.speed;
                     if drop.sediment > drop.capacity {
                         // too much sediment in the drop. deposit
-                        let deposit = (drop.sediment - drop.capacity) * conf.deposition;
+                        let deposit = (drop.sediment - drop.capacity) * deposition;
                         hmap[old_off] += deposit * w00;
                         hmap[old_off + 1] += deposit * w10;
                         hmap[old_off + size.0] += deposit * w01;
@@ -245,47 +282,53 @@ pub fn gen_water_erosion(
                     } else {
                         // erode
                         let amount =
-                            ((drop.capacity - drop.sediment) * conf.erosion_strength).min(-hdif);
-                        for y in (old_y - conf.radius).round() as i32
-                            ..(old_y + conf.radius).round() as i32
-                        {
-                            if y < 0 || y >= size.1 as i32 {
-                                continue;
-                            }
-                            let dy = old_y - y as f32;
-                            for x in (old_x - conf.radius).round() as i32
-                                ..(old_x + conf.radius).round() as i32
-                            {
-                                if x < 0 || x >= size.0 as i32 {
-                                    continue;
-                                }
-                                let dx = old_x - x as f32;
-                                let dist = (dx * dx + dy * dy).sqrt();
-                                if dist < conf.radius {
-                                    let off = x as usize + y as usize * size.0;
-                                    hmap[off] -= amount * (conf.radius - dist) / erosion_weight;
-                                }
-                            }
+                            ((drop.capacity - drop.sediment) * erosion_strength).min(-hdif);
+                        let cells = || erosion_cells(size, (old_x, old_y), radius);
+                        let weight: f32 = cells().map(|(_, w)| w).sum();
+                        for (off, w) in cells() {
+                            hmap[off] -= amount * w / weight;
                         }
                         drop.sediment += amount;
                     }
                 }
-                drop.speed = (drop.speed * drop.speed + hdif.abs()).sqrt();
-                drop.water *= 1.0 - conf.evaporation;
+                // the drop gains the height it loses as kinetic energy, whatever the steps it takes
+                drop.speed = ((drop.speed * drop.speed - hdif) * (1.0 - friction))
+                    .max(0.0)
+                    .sqrt();
+                drop.water *= 1.0 - evaporation;
                 count += 1;
             }
         }
-        let new_progress = y as f32 / drop_count as f32;
-        if new_progress - progress >= min_progress_step {
-            progress = new_progress;
-            report_progress(progress, export, tx.clone());
+        progress.report(y as f32 / drop_count as f32);
+        if progress.cancelled() {
+            return;
         }
     }
 }
 
+/// offsets of the cells within radius of pos with their erosion weight, decreasing with the distance
+fn erosion_cells(
+    size: (usize, usize),
+    pos: (f32, f32),
+    radius: f32,
+) -> impl Iterator<Item = (usize, f32)> {
+    let x0 = (pos.0 - radius).floor().max(0.0) as usize;
+    let x1 = ((pos.0 + radius).ceil() as usize).min(size.0 - 1);
+    let y0 = (pos.1 - radius).floor().max(0.0) as usize;
+    let y1 = ((pos.1 + radius).ceil() as usize).min(size.1 - 1);
+    (y0..=y1).flat_map(move |y| {
+        (x0..=x1).filter_map(move |x| {
+            let dx = pos.0 - x as f32;
+            let dy = pos.1 - y as f32;
+            let dist = (dx * dx + dy * dy).sqrt();
+            (dist < radius).then(|| (x + y * size.0, radius - dist))
+        })
+    })
+}
+
 fn normalize_dir(dx: f32, dy: f32, rng: &mut StdRng) -> (f32, f32) {
     let len = (dx * dx + dy * dy).sqrt();
-    if len < std::f32::EPSILON {
+    if len < f32::EPSILON {
         // random direction
         let angle = rng.random_range(0.0..std::f32::consts::PI * 2.0);
         (angle.cos(), angle.sin())
@@ -0,0 +1,157 @@
+use worldgen::Step;
+
+/// maximum number of changes that can be undone
+const MAX_HISTORY: usize = 100;
+
+/// the part of the project that can be undone
+#[derive(Clone, PartialEq)]
+pub struct Snapshot {
+    pub steps: Vec<Step>,
+    pub seed: u64,
+}
+
+/// undo/redo stacks of the generator steps and seed
+pub struct History {
+    /// state after the last recorded change
+    current: Snapshot,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+impl History {
+    pub fn new(current: Snapshot) -> Self {
+        Self {
+            current,
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+    /// record the state if it changed since the last recorded change.
+    /// Called every frame : the steps are only cloned when they changed
+    pub fn record(&mut self, steps: &[Step], seed: u64) {
+        if seed == self.current.seed && steps == self.current.steps.as_slice() {
+            return;
+        }
+        let state = Snapshot {
+            steps: steps.to_vec(),
+            seed,
+        };
+        let previous = std::mem::replace(&mut self.current, state);
+        self.undo.push(previous);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+    /// the state before the last change
+    pub fn undo(&mut self) -> Option<Snapshot> {
+        let previous = self.undo.pop()?;
+        let current = std::mem::replace(&mut self.current, previous.clone());
+        self.redo.push(current);
+        Some(previous)
+    }
+    /// the state before the last undo
+    pub fn redo(&mut self) -> Option<Snapshot> {
+        let next = self.redo.pop()?;
+        let current = std::mem::replace(&mut self.current, next.clone());
+        self.undo.push(current);
+        Some(next)
+    }
+}
+
+/// index of the first step that differs between two states. All steps are affected if the seed changed
+pub fn first_changed_step(a: &Snapshot, b: &Snapshot) -> usize {
+    if a.seed != b.seed {
+        return 0;
+    }
+    a.steps
+        .iter()
+        .zip(b.steps.iter())
+        .position(|(sa, sb)| sa != sb)
+        .unwrap_or(a.steps.len().min(b.steps.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(count: usize) -> Vec<Step> {
+        vec![Step::default(); count]
+    }
+
+    fn history() -> History {
+        History::new(Snapshot {
+            steps: steps(1),
+            seed: 0,
+        })
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut history = history();
+        assert!(!history.can_undo());
+        history.record(&steps(2), 0);
+        history.record(&steps(2), 1);
+        assert_eq!(history.undo().map(|s| s.seed), Some(0));
+        let previous = history.undo().unwrap();
+        assert_eq!(previous.steps.len(), 1);
+        assert!(!history.can_undo());
+        assert!(history.undo().is_none());
+        assert_eq!(history.redo().map(|s| s.steps.len()), Some(2));
+        assert_eq!(history.redo().map(|s| s.seed), Some(1));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn unchanged_state_is_not_recorded() {
+        let mut history = history();
+        history.record(&steps(1), 0);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn new_change_clears_redo() {
+        let mut history = history();
+        history.record(&steps(2), 0);
+        history.undo();
+        assert!(history.can_redo());
+        history.record(&steps(3), 0);
+        assert!(!history.can_redo());
+        assert_eq!(history.undo().map(|s| s.steps.len()), Some(1));
+    }
+
+    #[test]
+    fn oldest_changes_are_forgotten() {
+        let mut history = history();
+        for seed in 1..=MAX_HISTORY as u64 + 10 {
+            history.record(&steps(1), seed);
+        }
+        let mut undone = 0;
+        while let Some(state) = history.undo() {
+            undone += 1;
+            assert_eq!(state.seed, MAX_HISTORY as u64 + 10 - undone);
+        }
+        assert_eq!(undone, MAX_HISTORY as u64);
+    }
+
+    #[test]
+    fn first_changed_step_ignores_the_unchanged_prefix() {
+        let a = Snapshot {
+            steps: steps(3),
+            seed: 0,
+        };
+        let mut b = a.clone();
+        b.steps[2].disabled = true;
+        assert_eq!(first_changed_step(&a, &b), 2);
+        b.steps.truncate(1);
+        assert_eq!(first_changed_step(&a, &b), 1);
+        b.seed = 1;
+        assert_eq!(first_changed_step(&a, &b), 0);
+    }
+}
@@ -0,0 +1,66 @@
+//! wgen terrain generation core : generators, world generator and heightmap exporter.
+//! The graphical interface is only built with the `gui` feature.
+extern crate exr;
+extern crate image;
+extern crate noise;
+extern crate rand;
+
+pub mod cache;
+pub mod exporter;
+pub mod generators;
+pub mod project;
+mod streaming;
+pub mod worldgen;
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+pub use cache::{cache_dir, StepCache};
+pub use exporter::{estimate_export_memory, export_heightmap, ExportFileType, ExportSettings};
+pub use generators::BlendMode;
+pub use project::{
+    check_project_version, migrate_project, project_version, Project, View3dSettings,
+};
+pub use worldgen::{
+    assign_new_step_ids, assign_step_ids, generator_thread, ExportMap, Step, StepType,
+    WorldGenCommand, WorldGenerator,
+};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const MASK_SIZE: usize = 64;
+
+/// messages sent to the main thread by either world generator or exporter threads
+pub enum ThreadMessage {
+    /// from world generator : all steps have been computed => update 2D/3D previews
+    GeneratorDone(ExportMap),
+    /// from world generator : update progress bar
+    GeneratorStepProgress(f32),
+    /// from world generator : one step has been computed => update 2D preview if live preview enabled
+    GeneratorStepDone(usize, Option<ExportMap>),
+    /// from world generator : return the heightmap for a specific step
+    GeneratorStepMap(usize, ExportMap),
+    /// from world generator : histogram of the input heightmap of a specific step
+    GeneratorStepHistogram(usize, Box<[usize; 256]>),
+    /// from exporter : one step has been computed
+    ExporterStepDone(usize),
+    /// from exporter : export is finished
+    ExporterDone(Result<(), String>),
+    /// from exporter : update progress bar
+    ExporterStepProgress(f32),
+}
+
+/// cooperative cancellation of a running computation, shared between the main thread and a worker thread.
+/// The generators check it regularly and stop as soon as possible, leaving an incomplete heightmap
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,36 @@
+//! prints the library log messages on the standard output, with the time elapsed since the program start.
+//! The messages of the dependencies are ignored
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+struct StdoutLogger;
+
+static LOGGER: StdoutLogger = StdoutLogger;
+static START: OnceLock<Instant> = OnceLock::new();
+
+impl Log for StdoutLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target().starts_with("worldgen")
+    }
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let start = START.get_or_init(Instant::now);
+            println!(
+                "{:03.3} {}",
+                start.elapsed().as_millis() as f32 / 1000.0,
+                record.args()
+            );
+        }
+    }
+    fn flush(&self) {}
+}
+
+/// print the messages up to level
+pub fn init(level: LevelFilter) {
+    START.get_or_init(Instant::now);
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
@@ -1,11 +1,12 @@
-use eframe::egui;
-use egui_extras::RetainedImage;
+use eframe::egui::{self, TextureHandle, TextureOptions};
 use epaint::{Color32, ColorImage};
 
-use crate::{fps::FpsCounter, panel_maskedit::PanelMaskEdit, worldgen::ExportMap};
+use worldgen::ExportMap;
+
+use crate::{fps::FpsCounter, panel_maskedit::PanelMaskEdit};
 
 pub enum Panel2dAction {
-    /// inform the main program that the preview size has changed. terrain/3d view must be recomputed
+    /// inform the main program that the preview size (longest side) has changed. terrain/3d view must be recomputed
     ResizePreview(usize),
     /// inform the main program that mask must be copied to the generator panel
     MaskUpdated,
@@ -21,33 +22,46 @@ pub struct Panel2dView {
     max: f32,
     /// are we displaying the mask editor ?
     mask_mode: bool,
-    /// size of the preview canvas in pixels
+    /// size of the preview canvas longest side in pixels
     image_size: usize,
-    /// size of the heightmap
+    /// size of the heightmap longest side
     preview_size: usize,
+    /// size of the heightmap
+    world_size: (usize, usize),
     /// should we update the preview every time a step is computed ?
     pub live_preview: bool,
     /// utility to display FPS
     fps_counter: FpsCounter,
-    /// egui renderable image
-    ui_img: Option<RetainedImage>,
+    /// egui texture, rebuilt from img when the heightmap changes
+    ui_img: Option<TextureHandle>,
     /// mask editor subpanel
     mask_editor: PanelMaskEdit,
 }
 
+/// size in pixels of a canvas displaying a world, image_size pixels along its longest side
+pub fn canvas_size(image_size: usize, world_size: (usize, usize)) -> (usize, usize) {
+    let scale = image_size as f32 / world_size.0.max(world_size.1) as f32;
+    (
+        ((world_size.0 as f32 * scale).round() as usize).max(1),
+        ((world_size.1 as f32 * scale).round() as usize).max(1),
+    )
+}
+
 impl Panel2dView {
-    pub fn new(image_size: usize, preview_size: u32, hmap: &ExportMap) -> Self {
+    pub fn new(image_size: usize, preview_size: (usize, usize), hmap: &ExportMap) -> Self {
+        let canvas = canvas_size(image_size, preview_size);
         let mut panel = Panel2dView {
-            img: ColorImage::new([image_size, image_size], Color32::BLACK),
+            img: ColorImage::new([canvas.0, canvas.1], Color32::BLACK),
             min: 0.0,
             max: 0.0,
             image_size,
             mask_mode: false,
             live_preview: true,
-            preview_size: preview_size as usize,
+            preview_size: preview_size.0.max(preview_size.1),
+            world_size: preview_size,
             fps_counter: FpsCounter::default(),
             ui_img: None,
-            mask_editor: PanelMaskEdit::new(image_size),
+            mask_editor: PanelMaskEdit::new(canvas),
         };
         panel.refresh(image_size, preview_size, Some(hmap));
         panel
@@ -55,22 +69,36 @@ impl Panel2dView {
     pub fn get_current_mask(&self) -> Option<Vec<f32>> {
         self.mask_editor.get_mask()
     }
-    pub fn display_mask(&mut self, image_size: usize, preview_size: u32, mask: Option<Vec<f32>>) {
+    pub fn display_mask(
+        &mut self,
+        image_size: usize,
+        preview_size: (usize, usize),
+        mask: Option<Vec<f32>>,
+    ) {
         self.image_size = image_size;
-        self.preview_size = preview_size as usize;
-        self.mask_editor.display_mask(image_size, mask);
+        self.preview_size = preview_size.0.max(preview_size.1);
+        self.world_size = preview_size;
+        self.mask_editor
+            .display_mask(canvas_size(image_size, preview_size), mask);
         self.mask_mode = true;
     }
-    pub fn refresh(&mut self, image_size: usize, preview_size: u32, hmap: Option<&ExportMap>) {
+    pub fn refresh(
+        &mut self,
+        image_size: usize,
+        preview_size: (usize, usize),
+        hmap: Option<&ExportMap>,
+    ) {
         self.image_size = image_size;
         self.mask_mode = false;
-        self.preview_size = preview_size as usize;
-        if self.img.width() != image_size {
-            self.img = ColorImage::new([self.image_size, self.image_size], Color32::BLACK);
+        self.preview_size = preview_size.0.max(preview_size.1);
+        self.world_size = preview_size;
+        let (width, height) = canvas_size(image_size, preview_size);
+        if self.img.size != [width, height] {
+            self.img = ColorImage::new([width, height], Color32::BLACK);
         }
         if let Some(hmap) = hmap {
             let (min, max) = hmap.get_min_max();
-            let coef = if max - min > std::f32::EPSILON {
+            let coef = if max - min > f32::EPSILON {
                 1.0 / (max - min)
             } else {
                 1.0
@@ -78,18 +106,18 @@ impl Panel2dView {
             self.min = min;
             self.max = max;
             let mut idx = 0;
-            for y in 0..image_size {
-                let py = ((y * preview_size as usize) as f32 / image_size as f32) as usize;
-                for x in 0..image_size {
-                    let px = ((x * preview_size as usize) as f32 / image_size as f32) as usize;
-                    let mut h = hmap.height(px as usize, py as usize);
+            for y in 0..height {
+                let py = ((y * preview_size.1) as f32 / height as f32) as usize;
+                for x in 0..width {
+                    let px = ((x * preview_size.0) as f32 / width as f32) as usize;
+                    let mut h = hmap.height(px, py);
                     h = (h - min) * coef;
                     self.img.pixels[idx] = Color32::from_gray((h * 255.0).clamp(0.0, 255.0) as u8);
                     idx += 1;
                 }
             }
         };
-        self.ui_img = Some(RetainedImage::from_color_image("hmap", self.img.clone()));
+        self.ui_img = None;
     }
     pub fn render(&mut self, ui: &mut egui::Ui) -> Option<Panel2dAction> {
         let mut action = None;
@@ -99,8 +127,15 @@ impl Panel2dView {
             action = self.mask_editor.render(ui, &self.img);
         } else {
             ui.vertical(|ui| {
-                if let Some(ref img) = self.ui_img {
-                    img.show(ui);
+                if self.ui_img.is_none() {
+                    self.ui_img = Some(ui.ctx().load_texture(
+                        "hmap",
+                        self.img.clone(),
+                        TextureOptions::LINEAR,
+                    ));
+                }
+                if let Some(ref texture) = self.ui_img {
+                    ui.image((texture.id(), texture.size_vec2()));
                 }
                 ui.horizontal(|ui| {
                     ui.label(format!("Height range : {} - {}", self.min, self.max));
@@ -111,12 +146,14 @@ impl Panel2dView {
         ui.horizontal(|ui| {
             ui.label("Preview size");
             egui::ComboBox::from_label("")
-                .selected_text(format!("{}x{}", self.preview_size, self.preview_size))
+                .selected_text(format!("{}x{}", self.world_size.0, self.world_size.1))
                 .show_ui(ui, |ui| {
-                    ui.selectable_value(&mut self.preview_size, 64, "64x64");
-                    ui.selectable_value(&mut self.preview_size, 128, "128x128");
-                    ui.selectable_value(&mut self.preview_size, 256, "256x256");
-                    ui.selectable_value(&mut self.preview_size, 512, "512x512");
+                    for size in [64, 128, 256, 512] {
+                        ui.selectable_value(&mut self.preview_size, size, size.to_string())
+                            .on_hover_text(
+                                "size of the longest side. The preview has the same proportions as the exported terrain",
+                            );
+                    }
                 });
             ui.label("Live preview");
             ui.checkbox(&mut self.live_preview, "");
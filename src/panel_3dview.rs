@@ -6,7 +6,7 @@ use three_d::{
     Mesh, PhysicalMaterial, Positions, TextureData, Vec3,
 };
 
-use crate::worldgen::ExportMap;
+use worldgen::{ExportMap, View3dSettings};
 
 const ZSCALE: f32 = 200.0;
 const XY_SCALE: f32 = 500.0;
@@ -22,27 +22,10 @@ pub struct MeshData {
     uv: Vec<three_d::Vec2>,
 }
 
-#[derive(Clone, Copy)]
-pub struct Panel3dViewConf {
-    /// camera x and y orbit angles
-    pub orbit: three_d::Vec2,
-    /// camera x and y pan distances
-    pub pan: three_d::Vec2,
-    /// camera zoom in degrees (y field of view is 90 - zoom)
-    pub zoom: f32,
-    /// vertical scale to apply to the heightmap
-    pub hscale: f32,
-    /// water plane z position
-    pub water_level: f32,
-    /// do we display the water plane ?
-    pub show_water: bool,
-    /// do we display the skybox ?
-    pub show_skybox: bool,
-}
-
 pub struct Panel3dView {
     size: f32,
-    conf: Panel3dViewConf,
+    /// camera and display options, saved in the project
+    conf: View3dSettings,
     mesh_data: MeshData,
     mesh_updated: bool,
 }
@@ -51,15 +34,7 @@ impl Default for Panel3dView {
     fn default() -> Self {
         Self {
             size: PANEL3D_SIZE,
-            conf: Panel3dViewConf {
-                pan: three_d::Vec2::new(0.0, 0.0),
-                orbit: three_d::Vec2::new(std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_4),
-                zoom: 60.0,
-                hscale: 100.0,
-                water_level: 40.0,
-                show_water: true,
-                show_skybox: true,
-            },
+            conf: View3dSettings::default(),
             mesh_data: Default::default(),
             mesh_updated: false,
         }
@@ -67,12 +42,25 @@ impl Default for Panel3dView {
 }
 
 impl Panel3dView {
-    pub fn new(size: f32) -> Self {
+    pub fn new(size: f32, conf: View3dSettings) -> Self {
         Self {
             size,
+            conf,
             ..Default::default()
         }
     }
+    pub fn settings(&self) -> View3dSettings {
+        self.conf
+    }
+    pub fn set_settings(&mut self, conf: View3dSettings) {
+        if self.conf.show_water {
+            self.update_water_level(false, self.conf.water_level);
+        }
+        self.conf = conf;
+        if self.conf.show_water {
+            self.update_water_level(true, self.conf.water_level);
+        }
+    }
     pub fn render(&mut self, ui: &mut egui::Ui) {
         ui.vertical(|ui| {
             egui::Frame::dark_canvas(ui.style()).show(ui, |ui| {
@@ -128,12 +116,14 @@ impl Panel3dView {
         let size = hmap.get_size();
         self.mesh_data.size = size;
         self.mesh_data.vertices = Vec::with_capacity(size.0 * size.1);
-        let grid_size = (XY_SCALE / size.0 as f32, XY_SCALE / size.1 as f32);
+        // same scale on both axes so that rectangular worlds keep their proportions
+        let cell_size = XY_SCALE / size.0.max(size.1) as f32;
+        let grid_size = (cell_size, cell_size);
         let off_x = -0.5 * grid_size.0 * size.0 as f32;
         let off_y = -0.5 * grid_size.1 * size.1 as f32;
         let (min, max) = hmap.get_min_max();
         let coef = ZSCALE
-            * if max - min > std::f32::EPSILON {
+            * if max - min > f32::EPSILON {
                 1.0 / (max - min)
             } else {
                 1.0
@@ -216,7 +206,7 @@ impl Panel3dView {
                         three_d,
                         &info,
                         conf,
-                        FrameInput::new(&three_d, &info, painter),
+                        FrameInput::new(three_d, &info, painter),
                     );
                 });
             })),
@@ -300,7 +290,7 @@ fn with_three_d_context<R>(
 ) -> R {
     use std::cell::RefCell;
     thread_local! {
-        pub static THREE_D: RefCell<Option<(three_d::Context,Renderer)>> = RefCell::new(None);
+        pub static THREE_D: RefCell<Option<(three_d::Context,Renderer)>> = const { RefCell::new(None) };
     }
     #[allow(unsafe_code)]
     unsafe {
@@ -361,7 +351,7 @@ impl Renderer {
                 Srgba::new_opaque(255, 222, 180),
                 vec3(-0.5, 0.5, -0.5).normalize(),
             ),
-            ambient: AmbientLight::new(&three_d, 0.5, Srgba::WHITE),
+            ambient: AmbientLight::new(three_d, 0.5, Srgba::WHITE),
         }
     }
     pub fn update_model(&mut self, three_d: &three_d::Context, mesh_data: &Option<MeshData>) {
@@ -387,7 +377,7 @@ impl Renderer {
         &mut self,
         _three_d: &three_d::Context,
         _info: &egui::PaintCallbackInfo,
-        conf: Panel3dViewConf,
+        conf: View3dSettings,
         frame_input: FrameInput<'_>,
     ) {
         // Set where to paint
@@ -432,7 +422,7 @@ impl Renderer {
         frame_input.screen.render_partially(
             frame_input.scissor_box,
             &camera,
-            &[&self.terrain_model],
+            [&self.terrain_model],
             &[&self.ambient, &self.directional],
         );
 
@@ -446,19 +436,16 @@ impl Renderer {
             frame_input.screen.render_partially(
                 frame_input.scissor_box,
                 &camera,
-                &[&self.water_model],
+                [&self.water_model],
                 &[&self.ambient, &self.directional],
             );
         }
         if conf.show_skybox {
             let transfo = Mat4::from_angle_z(radians(conf.orbit[0] * 2.0));
             self.sky.set_transformation(transfo);
-            frame_input.screen.render_partially(
-                frame_input.scissor_box,
-                &camera,
-                &[&self.sky],
-                &[],
-            );
+            frame_input
+                .screen
+                .render_partially(frame_input.scissor_box, &camera, [&self.sky], &[]);
         }
 
         frame_input.screen.into_framebuffer(); // Take back the screen fbo, we will continue to use it.
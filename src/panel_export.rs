@@ -1,93 +1,89 @@
 use std::path::PathBuf;
 
 use eframe::egui;
+use worldgen::{estimate_export_memory, ExportFileType, ExportSettings, Step};
 
 pub const TEXTEDIT_WIDTH: f32 = 240.0;
 
-#[derive(Clone)]
-pub enum ExportFileType {
-    PNG,
-    EXR,
-}
-
-impl std::fmt::Display for ExportFileType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::PNG => "png",
-                Self::EXR => "exr",
-            }
-        )
-    }
-}
-
 #[derive(Clone)]
 pub struct PanelExport {
-    /// width of each image in pixels
-    pub export_width: f32,
-    /// height of each image in pixels
-    pub export_height: f32,
-    /// number of horizontal tiles
-    pub tiles_h: f32,
-    /// number of vertical tiles
-    pub tiles_v: f32,
-    /// image filename prefix
-    pub file_path: String,
-    /// should we repeat the same pixel row on two adjacent tiles ?
-    /// not needed for unreal engine which handles multi-textures heightmaps
-    /// might be needed for other engines (for example godot heightmap terrain plugin)
-    pub seamless: bool,
-    /// format to export, either png or exr
-    pub file_type: ExportFileType,
+    /// size, number and format of the files to export
+    pub settings: ExportSettings,
     /// to disable the exporter ui during export
     pub enabled: bool,
     /// program's current directory
     cur_dir: PathBuf,
 }
 
+pub enum ExportAction {
+    Export,
+    Cancel,
+}
+
 impl Default for PanelExport {
     fn default() -> Self {
-        let cur_dir = std::env::current_dir().unwrap();
-        let file_path = format!("{}/wgen", cur_dir.display());
         Self {
-            export_width: 1024.0,
-            export_height: 1024.0,
-            tiles_h: 1.0,
-            tiles_v: 1.0,
-            file_path,
-            seamless: false,
-            file_type: ExportFileType::PNG,
+            settings: ExportSettings::default(),
             enabled: true,
-            cur_dir,
+            cur_dir: std::env::current_dir().unwrap(),
         }
     }
 }
 
 impl PanelExport {
-    pub fn render(&mut self, ui: &mut egui::Ui, progress: f32, progress_text: &str) -> bool {
-        let mut export = false;
+    pub fn render(
+        &mut self,
+        ui: &mut egui::Ui,
+        progress: f32,
+        progress_text: &str,
+        steps: &[Step],
+    ) -> Option<ExportAction> {
+        let mut action = None;
         ui.horizontal(|ui| {
             ui.heading("Export heightmaps");
             if !self.enabled {
                 ui.spinner();
+                if ui
+                    .button("Cancel")
+                    .on_hover_text("stop the export. Already saved files are kept")
+                    .clicked()
+                {
+                    action = Some(ExportAction::Cancel);
+                }
             }
         });
         ui.add(egui::ProgressBar::new(progress).text(progress_text));
         ui.add_enabled_ui(self.enabled, |ui| {
             ui.horizontal(|ui| {
                 ui.label("Tile size");
-                ui.add(egui::DragValue::new(&mut self.export_width).speed(1.0));
+                ui.add(
+                    egui::DragValue::new(&mut self.settings.export_width)
+                        .speed(1.0)
+                        .range(2.0..=f32::MAX),
+                );
                 ui.label(" x ");
-                ui.add(egui::DragValue::new(&mut self.export_height).speed(1.0));
+                ui.add(
+                    egui::DragValue::new(&mut self.settings.export_height)
+                        .speed(1.0)
+                        .range(2.0..=f32::MAX),
+                );
             });
             ui.horizontal(|ui| {
                 ui.label("Tiles");
-                ui.add(egui::DragValue::new(&mut self.tiles_h).speed(1.0));
+                ui.add(
+                    egui::DragValue::new(&mut self.settings.tiles_h)
+                        .speed(1.0)
+                        .range(1.0..=f32::MAX),
+                );
                 ui.label(" x ");
-                ui.add(egui::DragValue::new(&mut self.tiles_v).speed(1.0));
+                ui.add(
+                    egui::DragValue::new(&mut self.settings.tiles_v)
+                        .speed(1.0)
+                        .range(1.0..=f32::MAX),
+                );
             });
+            let (width, height) = self.settings.world_size();
+            ui.label(format!("Terrain size : {} x {}", width, height));
             ui.horizontal(|ui| {
                 ui.label("Export file path");
                 if ui.button("Pick...").clicked() {
@@ -95,14 +91,12 @@ impl PanelExport {
                         .set_directory(&self.cur_dir)
                         .pick_file()
                     {
-                        self.file_path = path.display().to_string();
-                        if self.file_path.ends_with(".png") {
-                            self.file_path =
-                                self.file_path.strip_suffix(".png").unwrap().to_owned();
-                        } else if self.file_path.ends_with(".exr") {
-                            self.file_path =
-                                self.file_path.strip_suffix(".exr").unwrap().to_owned();
-                        }
+                        let file_path = path.display().to_string();
+                        self.settings.file_path = file_path
+                            .strip_suffix(".png")
+                            .or_else(|| file_path.strip_suffix(".exr"))
+                            .unwrap_or(&file_path)
+                            .to_owned();
                         self.cur_dir = if path.is_file() {
                             path.parent().unwrap().to_path_buf()
                         } else {
@@ -113,27 +107,47 @@ impl PanelExport {
             });
             ui.horizontal(|ui| {
                 ui.add(
-                    egui::TextEdit::singleline(&mut self.file_path)
+                    egui::TextEdit::singleline(&mut self.settings.file_path)
                         .desired_width(TEXTEDIT_WIDTH - 80.0),
                 );
                 ui.label("_x*_y*.");
                 if ui
-                    .button(&self.file_type.to_string())
+                    .button(self.settings.file_type.to_string())
                     .on_hover_text("change the exported file format")
                     .clicked()
                 {
-                    match self.file_type {
-                        ExportFileType::PNG => self.file_type = ExportFileType::EXR,
-                        ExportFileType::EXR => self.file_type = ExportFileType::PNG,
+                    match self.settings.file_type {
+                        ExportFileType::Png => self.settings.file_type = ExportFileType::Exr,
+                        ExportFileType::Exr => self.settings.file_type = ExportFileType::Png,
                     }
                 }
             });
             ui.horizontal(|ui| {
-                ui.checkbox(&mut self.seamless, "seamless")
+                ui.checkbox(&mut self.settings.seamless, "seamless")
                     .on_hover_text("whether pixel values are repeated on two adjacent tiles");
-                export = ui.button("Export!").clicked();
+                ui.checkbox(&mut self.settings.streaming, "low memory")
+                    .on_hover_text(
+                        "compute the heightmap by parts stored in temporary files next to the exported files. Slower but needs much less memory for huge terrains",
+                    );
+                if ui.button("Export!").clicked() {
+                    action = Some(ExportAction::Export);
+                }
             });
+            ui.label(format!(
+                "Estimated peak memory : {}",
+                format_bytes(estimate_export_memory(steps, &self.settings))
+            ));
         });
-        export
+        action
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    const MB: f32 = 1024.0 * 1024.0;
+    let mb = bytes as f32 / MB;
+    if mb >= 1024.0 {
+        format!("{:.1} GB", mb / 1024.0)
+    } else {
+        format!("{:.0} MB", mb.ceil())
     }
 }
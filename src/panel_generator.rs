@@ -1,21 +1,22 @@
-use eframe::egui::{self, CursorIcon, Id, LayerId, Order, Sense};
-use epaint::Color32;
-use serde::{Deserialize, Serialize};
-use std::{
-    fs::File,
-    io::{Read, Write},
+use eframe::egui::{Key, KeyboardShortcut, Modifiers};
+use eframe::{
+    egui::{self, CursorIcon, Id, LayerId, Order, Sense},
+    emath::TSTransform,
 };
+use epaint::Color32;
 
-use crate::{
-    generators::{
-        render_fbm, render_hills, render_island, render_landmass, render_mid_point,
-        render_mudslide, render_water_erosion, FbmConf, HillsConf, IslandConf, LandMassConf,
-        MidPointConf, MudSlideConf, NormalizeConf, WaterErosionConf,
-    },
-    worldgen::{Step, StepType},
-    VERSION,
+use worldgen::{
+    assign_new_step_ids,
+    generators::{generators, input_histogram_id, Hills},
+    BlendMode, Project, Step, StepType,
 };
 
+use crate::history::{first_changed_step, History, Snapshot};
+
+const UNDO_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+const REDO_SHORTCUT: KeyboardShortcut =
+    KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::Z);
+
 /// actions to do by the main program
 pub enum GeneratorAction {
     /// recompute heightmap from a specific step (deleteStep, stepIndex)
@@ -32,20 +33,19 @@ pub enum GeneratorAction {
     SetSeed(u64),
     /// remove all steps
     Clear,
+    /// steps restored by undo/redo : recompute heightmap from a specific step (stepIndex, number of removed steps)
+    Restore(usize, usize),
 }
 
-#[derive(Serialize, Deserialize)]
 pub struct PanelGenerator {
-    /// to avoid loading a save from another version
-    version: String,
-    #[serde(skip)]
     /// is the world generator currently computing the heightmap?
     pub is_running: bool,
-    #[serde(skip)]
     /// are we currently displaying a mask or a heightmap ?
     pub mask_selected: bool,
     /// generator steps with their configuration and masks
     pub steps: Vec<Step>,
+    /// last id given to a step, never reused even after the step was deleted
+    pub last_step_id: u64,
     /// current selected step. used for combo box. must be outside of steps in case steps is empty
     cur_step: Step,
     /// current selected step index
@@ -56,23 +56,32 @@ pub struct PanelGenerator {
     hovered: bool,
     /// random number generator's seed
     pub seed: u64,
+    /// undo/redo history of the steps and seed
+    history: History,
+    /// histogram of the selected step input heightmap, displayed by some steps parameters UI
+    pub input_histogram: Option<[usize; 256]>,
 }
 
 impl Default for PanelGenerator {
     fn default() -> Self {
         Self {
-            version: VERSION.to_owned(),
             is_running: false,
             mask_selected: false,
             steps: Vec::new(),
+            last_step_id: 0,
             cur_step: Step {
-                typ: StepType::Hills(HillsConf::default()),
+                typ: StepType::new(&Hills),
                 ..Default::default()
             },
             selected_step: 0,
             move_to_pos: 0,
             hovered: false,
             seed: 0xdeadbeef,
+            history: History::new(Snapshot {
+                steps: Vec::new(),
+                seed: 0xdeadbeef,
+            }),
+            input_histogram: None,
         }
     }
 }
@@ -88,7 +97,8 @@ fn render_step_gui(ui: &mut egui::Ui, id: Id, body: impl FnOnce(&mut egui::Ui))
         if let Some(pointer_pos) = ui.ctx().pointer_interact_pos() {
             let mut delta = pointer_pos - response.rect.center();
             delta.x += 60.0;
-            ui.ctx().translate_layer(layer_id, delta);
+            ui.ctx()
+                .transform_layer_shapes(layer_id, TSTransform::from_translation(delta));
             return Some(delta.y);
         }
     }
@@ -113,6 +123,26 @@ impl PanelGenerator {
                 self.steps.clear();
                 action = Some(GeneratorAction::Clear)
             }
+            if ui
+                .add_enabled(self.history.can_undo(), egui::Button::new("⟲"))
+                .on_hover_text(format!(
+                    "Undo ({})",
+                    ui.ctx().format_shortcut(&UNDO_SHORTCUT)
+                ))
+                .clicked()
+            {
+                action = self.undo();
+            }
+            if ui
+                .add_enabled(self.history.can_redo(), egui::Button::new("⟳"))
+                .on_hover_text(format!(
+                    "Redo ({})",
+                    ui.ctx().format_shortcut(&REDO_SHORTCUT)
+                ))
+                .clicked()
+            {
+                action = self.redo();
+            }
             ui.label("Seed");
             let old_seed = self.seed;
             let old_size = ui.spacing().interact_size.x;
@@ -131,86 +161,24 @@ impl PanelGenerator {
         ui.horizontal(|ui| {
             if ui.button("New step").clicked() {
                 self.steps.push(self.cur_step.clone());
+                assign_new_step_ids(&mut self.steps, &mut self.last_step_id);
                 self.selected_step = self.steps.len() - 1;
                 action = Some(GeneratorAction::Regen(false, self.selected_step))
             }
             egui::ComboBox::from_label("")
                 .selected_text(format!("{}", self.cur_step))
                 .show_ui(ui, |ui| {
-                    ui.selectable_value(
-                        &mut self.cur_step,
-                        Step {
-                            typ: StepType::Hills(HillsConf::default()),
-                            ..Default::default()
-                        },
-                        "Hills",
-                    )
-                    .on_hover_text("Add round hills to generate a smooth land");
-                    ui.selectable_value(
-                        &mut self.cur_step,
-                        Step {
-                            typ: StepType::Fbm(FbmConf::default()),
-                            ..Default::default()
-                        },
-                        "Fbm",
-                    )
-                    .on_hover_text("Add fractional brownian motion to generate a mountainous land");
-                    ui.selectable_value(
-                        &mut self.cur_step,
-                        Step {
-                            typ: StepType::MidPoint(MidPointConf::default()),
-                            ..Default::default()
-                        },
-                        "MidPoint",
-                    )
-                    .on_hover_text("Use mid point deplacement to generate a mountainous land");
-                    ui.selectable_value(
-                        &mut self.cur_step,
-                        Step {
-                            typ: StepType::Normalize(NormalizeConf::default()),
-                            ..Default::default()
-                        },
-                        "Normalize",
-                    )
-                    .on_hover_text("Scale the terrain back to the 0.0-1.0 range");
-                    ui.selectable_value(
-                        &mut self.cur_step,
-                        Step {
-                            typ: StepType::LandMass(LandMassConf::default()),
-                            ..Default::default()
-                        },
-                        "LandMass",
-                    )
-                    .on_hover_text(
-                        "Scale the terrain so that only a proportion of land is above water level",
-                    );
-                    ui.selectable_value(
-                        &mut self.cur_step,
-                        Step {
-                            typ: StepType::MudSlide(MudSlideConf::default()),
-                            ..Default::default()
-                        },
-                        "MudSlide",
-                    )
-                    .on_hover_text("Simulate mud sliding and smoothing the terrain");
-                    ui.selectable_value(
-                        &mut self.cur_step,
-                        Step {
-                            typ: StepType::WaterErosion(WaterErosionConf::default()),
-                            ..Default::default()
-                        },
-                        "WaterErosion",
-                    )
-                    .on_hover_text("Simulate rain falling and carving rivers");
-                    ui.selectable_value(
-                        &mut self.cur_step,
-                        Step {
-                            typ: StepType::Island(IslandConf::default()),
-                            ..Default::default()
-                        },
-                        "Island",
-                    )
-                    .on_hover_text("Lower height on the map borders");
+                    for generator in generators() {
+                        ui.selectable_value(
+                            &mut self.cur_step,
+                            Step {
+                                typ: StepType::new(generator),
+                                ..Default::default()
+                            },
+                            generator.name(),
+                        )
+                        .on_hover_text(generator.description());
+                    }
                 });
         });
         action
@@ -224,8 +192,7 @@ impl PanelGenerator {
     ) -> Option<GeneratorAction> {
         let mut action = None;
         let len = self.steps.len();
-        // let dragging = ui.ctx().dragged_id.is_some()
-        let dragging = ui.memory(|m| m.is_anything_being_dragged()) && self.hovered;
+        let dragging = ui.ctx().dragged_id().is_some() && self.hovered;
         let response = ui
             .scope(|ui| {
                 for (i, step) in self.steps.iter_mut().enumerate() {
@@ -298,47 +265,102 @@ impl PanelGenerator {
     /// render the configuration UI for currently selected step
     fn render_curstep_conf(&mut self, ui: &mut egui::Ui) -> Option<GeneratorAction> {
         let mut action = None;
-        match &mut self.steps[self.selected_step] {
-            Step {
-                typ: StepType::Hills(conf),
-                ..
-            } => render_hills(ui, conf),
-            Step {
-                typ: StepType::LandMass(conf),
-                ..
-            } => render_landmass(ui, conf),
-            Step {
-                typ: StepType::MudSlide(conf),
-                ..
-            } => render_mudslide(ui, conf),
-            Step {
-                typ: StepType::Fbm(conf),
-                ..
-            } => render_fbm(ui, conf),
-            Step {
-                typ: StepType::WaterErosion(conf),
-                ..
-            } => render_water_erosion(ui, conf),
-            Step {
-                typ: StepType::Island(conf),
-                ..
-            } => render_island(ui, conf),
-            Step {
-                typ: StepType::MidPoint(conf),
-                ..
-            } => render_mid_point(ui, conf),
-            Step {
-                typ: StepType::Normalize(_),
-                ..
-            } => (),
+        let step = &mut self.steps[self.selected_step];
+        match self.input_histogram {
+            Some(histogram) => ui.data_mut(|d| d.insert_temp(input_histogram_id(), histogram)),
+            None => ui.data_mut(|d| d.remove::<[usize; 256]>(input_histogram_id())),
         }
+        step.typ.render(ui);
+        ui.horizontal(|ui| {
+            let mut override_seed = step.seed.is_some();
+            if ui
+                .checkbox(&mut override_seed, "step seed")
+                .on_hover_text(
+                    "use a specific seed instead of the one derived from the project seed",
+                )
+                .changed()
+            {
+                // start from the current seed so that the terrain doesn't change
+                step.seed = if override_seed {
+                    Some(step.seed(self.seed))
+                } else {
+                    None
+                };
+            }
+            if let Some(ref mut seed) = step.seed {
+                ui.add(egui::DragValue::new(seed).speed(1.0));
+            }
+            if ui
+                .button("Reroll")
+                .on_hover_text("use a new random seed for this step")
+                .clicked()
+            {
+                step.seed = Some(rand::random());
+                action = Some(GeneratorAction::Regen(false, self.selected_step));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("blend").on_hover_text(
+                "how this step output is combined with the previous steps.\nExcept with replace, the steps generating a terrain start from a flat terrain",
+            );
+            egui::ComboBox::from_id_salt("step blend")
+                .selected_text(step.blend.to_string())
+                .show_ui(ui, |ui| {
+                    for blend in BlendMode::ALL {
+                        ui.selectable_value(&mut step.blend, blend, blend.to_string());
+                    }
+                });
+            ui.label("opacity")
+                .on_hover_text("0 : this step has no effect, 1 : full effect");
+            ui.add(
+                egui::DragValue::new(&mut step.opacity)
+                    .speed(0.01)
+                    .range(0.0..=1.0),
+            );
+        });
         if ui.button("Refresh").clicked() {
             action = Some(GeneratorAction::Regen(false, self.selected_step));
             self.mask_selected = false;
         }
         action
     }
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            steps: self.steps.clone(),
+            seed: self.seed,
+        }
+    }
+    /// restore a state from the history
+    fn restore(&mut self, state: Snapshot) -> GeneratorAction {
+        let current = self.snapshot();
+        let from_idx = first_changed_step(&current, &state);
+        let removed = self.steps.len().saturating_sub(state.steps.len());
+        self.steps = state.steps;
+        self.seed = state.seed;
+        self.selected_step = self.selected_step.min(self.steps.len().saturating_sub(1));
+        self.mask_selected = false;
+        GeneratorAction::Restore(from_idx, removed)
+    }
+    pub fn undo(&mut self) -> Option<GeneratorAction> {
+        // record pending changes so that they can be redone
+        self.history.record(&self.steps, self.seed);
+        let state = self.history.undo()?;
+        Some(self.restore(state))
+    }
+    pub fn redo(&mut self) -> Option<GeneratorAction> {
+        let state = self.history.redo()?;
+        Some(self.restore(state))
+    }
     pub fn render(&mut self, ui: &mut egui::Ui, progress: f32) -> Option<GeneratorAction> {
+        if !ui.ctx().wants_keyboard_input() {
+            // check redo first as the undo shortcut also matches with shift pressed
+            if ui.input_mut(|i| i.consume_shortcut(&REDO_SHORTCUT)) {
+                return self.redo();
+            }
+            if ui.input_mut(|i| i.consume_shortcut(&UNDO_SHORTCUT)) {
+                return self.undo();
+            }
+        }
         let previous_selected_step = self.selected_step;
         let previous_mask_selected = self.mask_selected;
         let mut action = self.render_header(ui, progress);
@@ -388,28 +410,23 @@ impl PanelGenerator {
                 }
             }
         }
-        action
-    }
-    pub fn load(&mut self, file_path: &str) -> Result<(), String> {
-        let mut file = File::open(file_path).map_err(|_| "Unable to open the file")?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .map_err(|_| "Unable to read the file")?;
-        let gen_data: PanelGenerator =
-            ron::from_str(&contents).map_err(|e| format!("Cannot parse the file : {}", e))?;
-        if gen_data.version != VERSION {
-            return Err(format!(
-                "Bad file version. Expected {}, found {}",
-                VERSION, gen_data.version
-            ));
+        if !ui.input(|i| i.pointer.any_down()) {
+            // a drag on a parameter or in the mask editor is recorded as a single change once finished
+            self.history.record(&self.steps, self.seed);
         }
-        *self = gen_data;
-        Ok(())
+        action
     }
-    pub fn save(&self, file_path: &str) -> Result<(), String> {
-        let data = ron::to_string(self).unwrap();
-        let mut buffer = File::create(file_path).map_err(|_| "Unable to create the file")?;
-        write!(buffer, "{}", data).map_err(|_| "Unable to write to the file")?;
-        Ok(())
+    /// display the steps of a loaded project, resetting the UI state
+    pub fn set_project(&mut self, project: &Project) {
+        *self = Self {
+            steps: project.steps.clone(),
+            last_step_id: project.last_step_id,
+            seed: project.seed,
+            history: History::new(Snapshot {
+                steps: project.steps.clone(),
+                seed: project.seed,
+            }),
+            ..Default::default()
+        };
     }
 }
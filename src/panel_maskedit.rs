@@ -2,13 +2,15 @@ use eframe::{
     egui::{self, PointerButton},
     emath,
 };
-use epaint::{Color32, ColorImage, Pos2, Rect};
+use epaint::{Color32, ColorImage, Pos2, Rect, Vec2};
 use three_d::{
     vec3, Blend, Camera, ColorMaterial, CpuMaterial, CpuMesh, CpuTexture, Cull, DepthTest, Gm,
     Indices, Mat4, Mesh, Object, Positions, Srgba, TextureData, Viewport,
 };
 
-use crate::{panel_2dview::Panel2dAction, MASK_SIZE};
+use worldgen::MASK_SIZE;
+
+use crate::panel_2dview::Panel2dAction;
 
 /// maximum size of the brush relative to the canvas
 const MAX_BRUSH_SIZE: f32 = 0.25;
@@ -25,8 +27,8 @@ pub struct BrushConfig {
     pub opacity: f32,
 }
 pub struct PanelMaskEdit {
-    /// preview canvas size in pixels
-    image_size: usize,
+    /// preview canvas size in pixels (width, height)
+    image_size: (usize, usize),
     /// the mask as a MASK_SIZE x MASK_SIZE f32 matrix
     mask: Option<Vec<f32>>,
     /// the brush parameters
@@ -46,7 +48,7 @@ pub struct PanelMaskEdit {
 }
 
 impl PanelMaskEdit {
-    pub fn new(image_size: usize) -> Self {
+    pub fn new(image_size: (usize, usize)) -> Self {
         PanelMaskEdit {
             image_size,
             mask: None,
@@ -67,7 +69,7 @@ impl PanelMaskEdit {
     pub fn get_mask(&self) -> Option<Vec<f32>> {
         self.mask.clone()
     }
-    pub fn display_mask(&mut self, image_size: usize, mask: Option<Vec<f32>>) {
+    pub fn display_mask(&mut self, image_size: (usize, usize), mask: Option<Vec<f32>>) {
         self.image_size = image_size;
         self.mesh_updated = true;
         self.new_mask = true;
@@ -81,7 +83,7 @@ impl PanelMaskEdit {
         let mut action = None;
         ui.vertical(|ui| {
             egui::Frame::dark_canvas(ui.style()).show(ui, |ui| {
-                self.render_3dview(ui, heightmap_img, self.image_size as u32);
+                self.render_3dview(ui, heightmap_img);
             });
             if self.is_painting {
                 action = Some(Panel2dAction::MaskUpdated);
@@ -141,17 +143,19 @@ impl PanelMaskEdit {
         });
         action
     }
-    fn render_3dview(&mut self, ui: &mut egui::Ui, heightmap_img: &ColorImage, image_size: u32) {
+    fn render_3dview(&mut self, ui: &mut egui::Ui, heightmap_img: &ColorImage) {
         let (rect, response) = ui.allocate_exact_size(
-            egui::Vec2::splat(self.image_size as f32),
+            Vec2::new(self.image_size.0 as f32, self.image_size.1 as f32),
             egui::Sense::drag(),
         );
+        // the mask covers the whole canvas, whatever its proportions
+        let aspect = self.image_size.0 as f32 / self.image_size.1 as f32;
         let lbutton = ui.input(|i| i.pointer.button_down(PointerButton::Primary));
         let rbutton = ui.input(|i| i.pointer.button_down(PointerButton::Secondary));
         let mbutton = ui.input(|i| i.pointer.button_down(PointerButton::Middle));
         let mut mouse_pos = ui.input(|i| i.pointer.hover_pos());
         let to_screen = emath::RectTransform::from_to(
-            Rect::from_min_size(Pos2::ZERO, response.rect.square_proportions()),
+            Rect::from_min_size(Pos2::ZERO, Vec2::splat(1.0)),
             response.rect,
         );
         let from_screen = to_screen.inverse();
@@ -180,7 +184,14 @@ impl PanelMaskEdit {
             mouse_pos = Some(canvas_pos);
             self.is_painting = (lbutton || rbutton || mbutton) && in_canvas(canvas_pos);
             if self.is_painting && time > 0.0 {
-                self.update_mask(canvas_pos, lbutton, rbutton, brush_config, time as f32);
+                self.update_mask(
+                    canvas_pos,
+                    lbutton,
+                    rbutton,
+                    brush_config,
+                    aspect,
+                    time as f32,
+                );
                 mesh_updated = true;
             }
         }
@@ -195,7 +206,7 @@ impl PanelMaskEdit {
                 with_three_d_context(painter.gl(), |three_d, renderer| {
                     if new_mask {
                         if let Some(ref heightmap_img) = heightmap_img {
-                            renderer.set_heightmap(three_d, heightmap_img, image_size);
+                            renderer.set_heightmap(three_d, heightmap_img);
                         }
                     }
                     if brush_updated {
@@ -204,7 +215,7 @@ impl PanelMaskEdit {
                     if mesh_updated {
                         renderer.update_model(three_d, &mask);
                     }
-                    renderer.render(three_d, &info, mouse_pos, brush_config, hmap_transp);
+                    renderer.render(three_d, &info, mouse_pos, brush_config, hmap_transp, aspect);
                 });
             })),
         };
@@ -219,15 +230,17 @@ impl PanelMaskEdit {
         lbutton: bool,
         rbutton: bool,
         brush_config: BrushConfig,
+        aspect: f32,
         time: f32,
     ) {
         if let Some(ref mut mask) = self.mask {
             let mx = canvas_pos.x * MASK_SIZE as f32;
             let my = canvas_pos.y * MASK_SIZE as f32;
+            // radius in mask rows. Mask columns are aspect times wider than rows on the canvas
             let brush_radius = brush_config.size * MASK_SIZE as f32 * MAX_BRUSH_SIZE;
             let falloff_dist = (1.0 - brush_config.falloff) * brush_radius;
-            let minx = (mx - brush_radius).max(0.0) as usize;
-            let maxx = ((mx + brush_radius) as usize).min(MASK_SIZE);
+            let minx = (mx - brush_radius / aspect).max(0.0) as usize;
+            let maxx = ((mx + brush_radius / aspect) as usize).min(MASK_SIZE);
             let miny = (my - brush_radius).max(0.0) as usize;
             let maxy = ((my + brush_radius) as usize).min(MASK_SIZE);
             let opacity_factor = 0.5 + brush_config.opacity;
@@ -246,7 +259,7 @@ impl PanelMaskEdit {
                 let dy = y as f32 - my;
                 let yoff = y * MASK_SIZE;
                 for x in minx..maxx {
-                    let dx = x as f32 - mx;
+                    let dx = (x as f32 - mx) * aspect;
                     // distance from brush center
                     let dist = (dx * dx + dy * dy).sqrt();
                     if dist >= brush_radius {
@@ -276,7 +289,7 @@ fn with_three_d_context<R>(
 ) -> R {
     use std::cell::RefCell;
     thread_local! {
-        pub static THREE_D: RefCell<Option<(three_d::Context,Renderer)>> = RefCell::new(None);
+        pub static THREE_D: RefCell<Option<(three_d::Context,Renderer)>> = const { RefCell::new(None) };
     }
     #[allow(unsafe_code)]
     unsafe {
@@ -371,6 +384,7 @@ impl Renderer {
         mouse_pos: Option<Pos2>,
         brush_conf: BrushConfig,
         hmap_transp: f32,
+        aspect: f32,
     ) {
         // Set where to paint
         let viewport = info.viewport_in_pixels();
@@ -394,10 +408,13 @@ impl Renderer {
             1000.0,
         );
 
+        // the camera shows a 10 units high view. Stretch the models to the view width
+        self.mask_model
+            .set_transformation(Mat4::from_nonuniform_scale(aspect, 1.0, 1.0));
         self.mask_model.render(&camera, &[]);
         if let Some(mouse_pos) = mouse_pos {
             let transfo = Mat4::from_translation(vec3(
-                mouse_pos.x * 10.0 - 5.0,
+                (mouse_pos.x * 10.0 - 5.0) * aspect,
                 5.0 - mouse_pos.y * 10.0,
                 0.1,
             ));
@@ -405,19 +422,14 @@ impl Renderer {
             self.brush_model.set_transformation(transfo * scale);
             self.brush_model.render(&camera, &[]);
         }
-        let transfo = Mat4::from_scale(5.0);
+        let transfo = Mat4::from_nonuniform_scale(5.0 * aspect, 5.0, 1.0);
         self.heightmap_model.set_transformation(transfo);
         self.heightmap_model.material.color.a = (hmap_transp * 255.0) as u8;
         self.heightmap_model.render(&camera, &[]);
     }
 
-    fn set_heightmap(
-        &mut self,
-        three_d: &three_d::Context,
-        heightmap_img: &ColorImage,
-        image_size: u32,
-    ) {
-        self.heightmap_model = build_heightmap(three_d, heightmap_img, image_size);
+    fn set_heightmap(&mut self, three_d: &three_d::Context, heightmap_img: &ColorImage) {
+        self.heightmap_model = build_heightmap(three_d, heightmap_img);
     }
 }
 
@@ -510,7 +522,6 @@ fn build_mask() -> CpuMesh {
 fn build_heightmap(
     three_d: &three_d::Context,
     heightmap_img: &ColorImage,
-    image_size: u32,
 ) -> Gm<Mesh, ColorMaterial> {
     let mesh = CpuMesh::square();
     let mut material = ColorMaterial::new(
@@ -520,8 +531,8 @@ fn build_heightmap(
             metallic: 0.0,
             albedo: Srgba::new(255, 255, 255, 128),
             albedo_texture: Some(CpuTexture {
-                width: image_size,
-                height: image_size,
+                width: heightmap_img.width() as u32,
+                height: heightmap_img.height() as u32,
                 data: TextureData::RgbaU8(
                     heightmap_img.pixels.iter().map(Color32::to_array).collect(),
                 ),
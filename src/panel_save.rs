@@ -8,18 +8,26 @@ pub struct PanelSaveLoad {
     pub file_path: String,
     /// the program's current directory
     cur_dir: PathBuf,
+    /// should the steps heightmaps be saved next to the project file ?
+    pub disk_cache: bool,
 }
 
 pub enum SaveLoadAction {
     Save,
     Load,
+    /// the disk cache was enabled or disabled
+    DiskCache,
 }
 
 impl Default for PanelSaveLoad {
     fn default() -> Self {
         let cur_dir = std::env::current_dir().unwrap();
         let file_path = format!("{}/my_terrain.wgen", cur_dir.display());
-        Self { file_path, cur_dir }
+        Self {
+            file_path,
+            cur_dir,
+            disk_cache: false,
+        }
     }
 }
 
@@ -54,6 +62,15 @@ impl PanelSaveLoad {
             if ui.button("Save!").clicked() {
                 action = Some(SaveLoadAction::Save);
             }
+            if ui
+                .checkbox(&mut self.disk_cache, "disk cache")
+                .on_hover_text(
+                    "save the steps heightmaps of big previews next to the project file so that they are not recomputed when the project is loaded again",
+                )
+                .changed()
+            {
+                action = Some(SaveLoadAction::DiskCache);
+            }
         });
         action
     }
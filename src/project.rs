@@ -0,0 +1,231 @@
+//! project files : the data saved in .wgen files and its versioning.
+//! Projects saved by older versions are converted to the current version by a chain of migration functions.
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use crate::generators::VoronoiConf;
+use crate::{assign_new_step_ids, BlendMode, ExportSettings, Step, VERSION};
+
+/// everything saved in a project file. The UI state (selected step, ...) is not saved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Project {
+    /// version of wgen that saved the project
+    pub version: String,
+    /// random number generator's seed
+    pub seed: u64,
+    /// size of the preview heightmap longest side
+    pub preview_size: usize,
+    /// generator steps with their configuration and masks
+    pub steps: Vec<Step>,
+    /// last id given to a step. The ids of the deleted steps are not reused so that new steps don't get their seed
+    pub last_step_id: u64,
+    /// size, number and format of the files to export
+    pub export: ExportSettings,
+    /// 3D preview camera and display options
+    pub view_3d: View3dSettings,
+}
+
+impl Default for Project {
+    fn default() -> Self {
+        Self {
+            version: VERSION.to_owned(),
+            seed: 0xdeadbeef,
+            preview_size: 128,
+            steps: Vec::new(),
+            last_step_id: 0,
+            export: ExportSettings::default(),
+            view_3d: View3dSettings::default(),
+        }
+    }
+}
+
+/// 3D preview settings
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct View3dSettings {
+    /// camera x and y orbit angles
+    pub orbit: [f32; 2],
+    /// camera x and y pan distances
+    pub pan: [f32; 2],
+    /// camera zoom in degrees (y field of view is 90 - zoom)
+    pub zoom: f32,
+    /// vertical scale to apply to the heightmap
+    pub hscale: f32,
+    /// water plane z position
+    pub water_level: f32,
+    /// do we display the water plane ?
+    pub show_water: bool,
+    /// do we display the skybox ?
+    pub show_skybox: bool,
+}
+
+impl Default for View3dSettings {
+    fn default() -> Self {
+        Self {
+            orbit: [std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_4],
+            pan: [0.0, 0.0],
+            zoom: 60.0,
+            hscale: 100.0,
+            water_level: 40.0,
+            show_water: true,
+            show_skybox: true,
+        }
+    }
+}
+
+impl Project {
+    /// load a project file. Returns the project and a warning if it was saved by an older version
+    pub fn load(file_path: &str) -> Result<(Self, Option<String>), String> {
+        let contents = fs::read_to_string(file_path).map_err(|_| "Unable to read the file")?;
+        Self::from_ron(&contents)
+    }
+    /// parse the content of a project file, converting it to the current version
+    pub fn from_ron(contents: &str) -> Result<(Self, Option<String>), String> {
+        let version = project_version(contents)?;
+        check_project_version(&version)?;
+        let mut project: Project = ron::from_str(contents)
+            .map_err(|e| format!("Cannot parse the file (version {}) : {}", version, e))?;
+        let warning = migrate_project(&mut project)?;
+        assign_new_step_ids(&mut project.steps, &mut project.last_step_id);
+        Ok((project, warning))
+    }
+    /// save the project. One value per line so that project files can be compared in a version control system
+    pub fn save(&self, file_path: &str) -> Result<(), String> {
+        let project = Self {
+            version: VERSION.to_owned(),
+            ..self.clone()
+        };
+        let pretty = ron::ser::PrettyConfig::new().compact_arrays(true);
+        let data = ron::ser::to_string_pretty(&project, pretty)
+            .map_err(|e| format!("Unable to serialize the project : {}", e))?;
+        fs::write(file_path, data).map_err(|_| "Unable to write to the file".to_owned())
+    }
+    pub fn enabled_steps(&self) -> usize {
+        self.steps.iter().filter(|s| !s.disabled).count()
+    }
+}
+
+/// a project file migration : target version, function updating the project
+/// and message explaining what changed
+type Migration = (&'static str, fn(&mut Project), &'static str);
+
+/// migrations, oldest first. Fields added to the project without any other change only need a serde default.
+/// Project files saved before 0.4.0 contain the generator panel UI state, which is ignored
+const MIGRATIONS: &[Migration] = &[
+    (
+        "0.4.0",
+        migrate_0_4_0,
+        "steps now have their own seed. The project seed is used as seed override to keep the same random values",
+    ),
+    // the renamed steps are converted when the project is read, see find_generator
+    ("0.4.0", |_| (), "the Fbm step is renamed Noise"),
+];
+
+/// oldest project version that can be loaded
+const OLDEST_VERSION: &str = "0.3.1";
+
+#[derive(Deserialize)]
+struct Header {
+    version: String,
+}
+
+/// the version that saved a project file
+pub fn project_version(contents: &str) -> Result<String, String> {
+    let header: Header =
+        ron::from_str(contents).map_err(|e| format!("Cannot read the project version : {}", e))?;
+    Ok(header.version)
+}
+
+/// check that a project saved by the given version can be loaded
+pub fn check_project_version(version: &str) -> Result<(), String> {
+    let parsed = parse_version(version)?;
+    if parsed > parse_version(VERSION)? {
+        return Err(format!(
+            "This project was saved by wgen {}. Update wgen to load it",
+            version
+        ));
+    }
+    if parsed < parse_version(OLDEST_VERSION)? {
+        return Err(format!(
+            "This project was saved by wgen {}. Only projects from wgen {} or newer can be loaded",
+            version, OLDEST_VERSION
+        ));
+    }
+    Ok(())
+}
+
+/// convert a project saved by an older version to the current version.
+/// Returns a warning describing the changes if the project was migrated
+pub fn migrate_project(project: &mut Project) -> Result<Option<String>, String> {
+    let version = std::mem::replace(&mut project.version, VERSION.to_owned());
+    check_project_version(&version)?;
+    let parsed = parse_version(&version)?;
+    let mut changes = Vec::new();
+    for (target, migrate, msg) in MIGRATIONS {
+        if parsed < parse_version(target)? {
+            migrate(project);
+            changes.push(format!("- {} : {}", target, msg));
+        }
+    }
+    // development versions saved projects with the current version number
+    if migrate_voronoi_blend(project) {
+        changes
+            .push("- 0.4.0 : the Voronoi blend mode is replaced by the step blend mode".to_owned());
+    }
+    if parsed == parse_version(VERSION)? && changes.is_empty() {
+        return Ok(None);
+    }
+    let mut warning = format!(
+        "This project was saved by wgen {} and converted to wgen {}. Save it to update the file.",
+        version, VERSION
+    );
+    for change in changes {
+        warning.push('\n');
+        warning.push_str(&change);
+    }
+    Ok(Some(warning))
+}
+
+/// major, minor, patch numbers of a version string
+fn parse_version(version: &str) -> Result<(u32, u32, u32), String> {
+    let err = || format!("Bad project version {}", version);
+    let mut numbers = version
+        .split('.')
+        .map(|n| n.parse::<u32>().map_err(|_| err()));
+    let mut next = || numbers.next().unwrap_or_else(|| Err(err()));
+    let parsed = (next()?, next()?, next()?);
+    if numbers.next().is_some() {
+        return Err(err());
+    }
+    Ok(parsed)
+}
+
+/// the Voronoi steps of the 0.4.0 development versions combined their values with the terrain using their own blend mode.
+/// It becomes the step blend mode, unless the step already has one. Returns true if a step was changed
+fn migrate_voronoi_blend(project: &mut Project) -> bool {
+    let mut changed = false;
+    for step in project.steps.iter_mut() {
+        let Some(blend) = step
+            .typ
+            .conf_mut::<VoronoiConf>()
+            .and_then(|conf| conf.legacy_blend.take())
+        else {
+            continue;
+        };
+        changed = true;
+        // a step blending a flat heightmap cannot keep the same result with most modes
+        if step.blend == BlendMode::Replace {
+            step.blend = blend;
+        }
+    }
+    changed
+}
+
+/// 0.3.1 steps all used the project seed
+fn migrate_0_4_0(project: &mut Project) {
+    for step in project.steps.iter_mut() {
+        step.seed = Some(project.seed);
+    }
+}
@@ -0,0 +1,409 @@
+//! out-of-core heightmap generation for exports that don't fit in memory.
+//! Each layer is stored in a temporary file and computed by bands of rows.
+//! Only the previous and the current layers exist at any time.
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::mpsc::Sender;
+use std::time::Instant;
+
+use crate::generators::{
+    get_min_max, height_histogram, reference_size, shrunk_cells, Chunk, LayerInfo, Locality,
+    Progress, StepContext,
+};
+use crate::worldgen::{apply_blend, apply_mask, MaskBase, Step};
+use crate::{CancelToken, ThreadMessage};
+
+/// error returned when the export is cancelled
+pub(crate) const CANCELLED: &str = "export cancelled";
+
+/// default maximum number of cells in a band (64MB), see [`crate::ExportSettings::band_cells`]
+pub(crate) const BAND_CELLS: usize = 1 << 24;
+
+/// a heightmap stored row by row in a temporary file. The file is deleted when the layer is dropped
+pub(crate) struct LayerFile {
+    path: String,
+    size: (usize, usize),
+    min: f32,
+    max: f32,
+}
+
+impl LayerFile {
+    pub fn get_min_max(&self) -> (f32, f32) {
+        (self.min, self.max)
+    }
+    /// read rows first_row..first_row + rows
+    pub fn read_rows(&self, first_row: usize, rows: usize) -> Result<Vec<f32>, String> {
+        let err = |e: std::io::Error| format!("Error while reading {}: {}", self.path, e);
+        let mut file = File::open(&self.path).map_err(err)?;
+        file.seek(SeekFrom::Start((first_row * self.size.0 * 4) as u64))
+            .map_err(err)?;
+        let mut buf = vec![0u8; rows * self.size.0 * 4];
+        file.read_exact(&mut buf).map_err(err)?;
+        Ok(buf
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect())
+    }
+}
+
+impl Drop for LayerFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// read some rows of the previous layer. No layer means all heights are 0.0
+fn read_rows(
+    layer: Option<&LayerFile>,
+    size: (usize, usize),
+    first_row: usize,
+    rows: usize,
+) -> Result<Vec<f32>, String> {
+    match layer {
+        Some(layer) => layer.read_rows(first_row, rows),
+        None => Ok(vec![0.0; size.0 * rows]),
+    }
+}
+
+/// writes a layer file, from the first to the last row
+struct LayerWriter {
+    file: BufWriter<File>,
+    layer: LayerFile,
+}
+
+impl LayerWriter {
+    fn new(path: String, size: (usize, usize)) -> Result<Self, String> {
+        let file =
+            File::create(&path).map_err(|e| format!("Error while creating {}: {}", path, e))?;
+        Ok(Self {
+            file: BufWriter::new(file),
+            layer: LayerFile {
+                path,
+                size,
+                min: f32::MAX,
+                max: f32::MIN,
+            },
+        })
+    }
+    fn write_rows(&mut self, h: &[f32]) -> Result<(), String> {
+        let (min, max) = get_min_max(h);
+        self.layer.min = self.layer.min.min(min);
+        self.layer.max = self.layer.max.max(max);
+        // convert at most a band at once to limit the memory usage of global steps
+        for rows in h.chunks(BAND_CELLS) {
+            let buf: Vec<u8> = rows.iter().flat_map(|v| v.to_le_bytes()).collect();
+            self.file
+                .write_all(&buf)
+                .map_err(|e| format!("Error while writing {}: {}", self.layer.path, e))?;
+        }
+        Ok(())
+    }
+    fn finish(self) -> Result<LayerFile, String> {
+        let LayerWriter { mut file, layer } = self;
+        file.flush()
+            .map_err(|e| format!("Error while writing {}: {}", layer.path, e))?;
+        Ok(layer)
+    }
+}
+
+/// number of rows in a band of at most band_cells cells and number of bands
+fn bands(size: (usize, usize), band_cells: usize) -> (usize, usize) {
+    let band_rows = (band_cells / size.0).max(1);
+    (band_rows, size.1.div_ceil(band_rows))
+}
+
+/// estimate of the peak memory in bytes used by generate_layers
+pub(crate) fn estimate_memory(size: (usize, usize), steps: &[Step], band_cells: usize) -> usize {
+    let (band_rows, _) = bands(size, band_cells);
+    let band = band_rows.min(size.1) * size.0 * std::mem::size_of::<f32>();
+    let ref_size = reference_size(size);
+    // layer information : reference grid and reference row of each layer row
+    let info = ref_size.0 * ref_size.1 * std::mem::size_of::<f32>()
+        + size.1 * std::mem::size_of::<usize>();
+    let cells = size.0 * size.1 * std::mem::size_of::<f32>();
+    steps
+        .iter()
+        .filter(|s| !s.disabled)
+        .map(|step| {
+            let copies = if step.mask.is_some() || step.is_blended() {
+                2
+            } else {
+                1
+            };
+            let extra = step.typ.extra_memory(size);
+            match step.typ.locality(size) {
+                // band read from the previous layer, mask copy and bytes to write
+                Locality::Local(halo) => {
+                    (band + halo * 2 * size.0 * std::mem::size_of::<f32>()) * (copies + 1)
+                        + info
+                        + extra
+                }
+                Locality::Global => cells * copies + band + extra,
+            }
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// compute the information needed by local steps about the previous layer
+fn layer_info(
+    layer: Option<&LayerFile>,
+    size: (usize, usize),
+    band_cells: usize,
+) -> Result<LayerInfo, String> {
+    let Some(layer) = layer else {
+        return Ok(LayerInfo::zero(size));
+    };
+    let ref_size = reference_size(size);
+    if ref_size.0 > size.0 || ref_size.1 > size.1 {
+        // small world. the reference grid is bigger than the layer
+        return Ok(LayerInfo::new(size, &layer.read_rows(0, size.1)?));
+    }
+    // average of the cells covered by each reference grid cell, as computed by resample
+    let ref_x: Vec<_> = (0..ref_size.0)
+        .map(|x| shrunk_cells(x, size.0, ref_size.0))
+        .collect();
+    let mut ref_y = vec![0; size.1];
+    for y in 0..ref_size.1 {
+        for row in shrunk_cells(y, size.1, ref_size.1) {
+            ref_y[row] = y;
+        }
+    }
+    // sums of the cells, then their average
+    let mut reference = vec![0.0; ref_size.0 * ref_size.1];
+    let mut histogram = [0; 256];
+    let (band_rows, band_count) = bands(size, band_cells);
+    for band in 0..band_count {
+        let first_row = band * band_rows;
+        let rows = band_rows.min(size.1 - first_row);
+        let h = layer.read_rows(first_row, rows)?;
+        height_histogram(&h, layer.min, layer.max, &mut histogram);
+        for (y, row) in h.chunks_exact(size.0).enumerate() {
+            let off = ref_y[first_row + y] * ref_size.0;
+            for (sum, xs) in reference[off..off + ref_size.0]
+                .iter_mut()
+                .zip(ref_x.iter())
+            {
+                *sum += row[xs.clone()].iter().sum::<f32>();
+            }
+        }
+    }
+    for (y, row) in reference.chunks_exact_mut(ref_size.0).enumerate() {
+        let rows = shrunk_cells(y, size.1, ref_size.1).len();
+        for (v, xs) in row.iter_mut().zip(ref_x.iter()) {
+            *v /= (xs.len() * rows) as f32;
+        }
+    }
+    Ok(LayerInfo {
+        size,
+        min: layer.min,
+        max: layer.max,
+        histogram,
+        reference,
+    })
+}
+
+/// the additional layers computed by the steps (see [`crate::generators::ExtraLayer`]) with their name
+pub(crate) type ExtraLayerFiles = Vec<(&'static str, LayerFile)>;
+
+/// compute the steps by bands of at most band_cells cells, storing each layer in a temporary file prefixed by tmp_path.
+/// Returns the last layer or None if all the heights are 0.0, and the additional layers
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn generate_layers(
+    seed: u64,
+    size: (usize, usize),
+    steps: &[Step],
+    tmp_path: &str,
+    band_cells: usize,
+    tx: Sender<ThreadMessage>,
+    min_progress_step: f32,
+    cancel: &CancelToken,
+) -> Result<(Option<LayerFile>, ExtraLayerFiles), String> {
+    let mut prev: Option<LayerFile> = None;
+    let mut extra_layers = ExtraLayerFiles::new();
+    let mut ctx = StepContext::default();
+    for (index, step) in steps.iter().enumerate() {
+        let now = Instant::now();
+        if !step.disabled {
+            let path = format!("{}_layer{}.tmp", tmp_path, index);
+            let mut progress = Progress::new(tx.clone(), true, min_progress_step, cancel.clone());
+            let layer = match step.typ.locality(size) {
+                Locality::Local(halo) => compute_local_step(
+                    seed,
+                    size,
+                    index,
+                    step,
+                    halo,
+                    band_cells,
+                    &ctx,
+                    prev.as_ref(),
+                    path,
+                    &mut progress,
+                )?,
+                Locality::Global => compute_global_step(
+                    seed,
+                    size,
+                    index,
+                    step,
+                    &ctx,
+                    prev.as_ref(),
+                    path,
+                    &mut extra_layers,
+                    &mut progress,
+                )?,
+            };
+            // the previous layer file is deleted here
+            prev = Some(layer);
+        }
+        ctx = step.next_context(ctx);
+        if cancel.is_cancelled() {
+            return Err(CANCELLED.to_owned());
+        }
+        log::info!("Streamed {} in {:.2}s", step, now.elapsed().as_secs_f32());
+        tx.send(ThreadMessage::ExporterStepDone(index)).unwrap();
+    }
+    Ok((prev, extra_layers))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn compute_local_step(
+    seed: u64,
+    size: (usize, usize),
+    index: usize,
+    step: &Step,
+    halo: usize,
+    band_cells: usize,
+    ctx: &StepContext,
+    prev: Option<&LayerFile>,
+    path: String,
+    progress: &mut Progress,
+) -> Result<LayerFile, String> {
+    let step_seed = step.seed(seed);
+    let info = if step.starts_flat() {
+        LayerInfo::zero(size)
+    } else {
+        layer_info(prev, size, band_cells)?
+    };
+    let prepared = step
+        .typ
+        .prepare(step_seed, &info, ctx, &mut progress.part(0.0, 0.5));
+    let (band_rows, band_count) = bands(size, band_cells);
+    let mut writer = LayerWriter::new(path.clone(), size)?;
+    for band in 0..band_count {
+        if progress.cancelled() {
+            return Err(CANCELLED.to_owned());
+        }
+        let first_row = band * band_rows;
+        let rows = band_rows.min(size.1 - first_row);
+        let top = first_row.saturating_sub(halo);
+        let bottom = (first_row + rows + halo).min(size.1);
+        let mut h = read_rows(prev, size, top, bottom - top)?;
+        let start = (first_row - top) * size.0;
+        let end = start + rows * size.0;
+        let prev_rows = if step.is_blended() || (step.mask.is_some() && index > 0) {
+            Some(h[start..end].to_vec())
+        } else {
+            None
+        };
+        if step.starts_flat() {
+            h.fill(0.0);
+        }
+        let chunk = Chunk {
+            world_size: size,
+            pos: (0, top),
+            size: (size.0, bottom - top),
+            layer: &info,
+            prepared: &prepared,
+        };
+        step.typ.run_chunk(
+            step_seed,
+            &chunk,
+            &mut h,
+            ctx,
+            &mut progress.part(
+                0.5 + 0.5 * band as f32 / band_count as f32,
+                0.5 / band_count as f32,
+            ),
+        )?;
+        let h = &mut h[start..end];
+        if let Some(ref prev_rows) = prev_rows {
+            if step.is_blended() {
+                apply_blend(step, prev_rows, h);
+            }
+            match step.mask {
+                Some(ref mask) if index > 0 => {
+                    apply_mask(size, mask, first_row, MaskBase::Previous(prev_rows), h)
+                }
+                _ => (),
+            }
+        }
+        writer.write_rows(h)?;
+    }
+    let layer = writer.finish()?;
+    match step.mask {
+        // the first step mask depends on the step minimum height, only known once the whole layer is computed
+        Some(ref mask) if index == 0 => {
+            let mut writer = LayerWriter::new(format!("{}.mask", path), size)?;
+            for band in 0..band_count {
+                let first_row = band * band_rows;
+                let rows = band_rows.min(size.1 - first_row);
+                let mut h = layer.read_rows(first_row, rows)?;
+                apply_mask(size, mask, first_row, MaskBase::Min(layer.min), &mut h);
+                writer.write_rows(&h)?;
+            }
+            writer.finish()
+        }
+        _ => Ok(layer),
+    }
+}
+
+/// compute a global step on the whole layer in memory. Its additional layers are written next to the layer and replace those with the same name
+#[allow(clippy::too_many_arguments)]
+fn compute_global_step(
+    seed: u64,
+    size: (usize, usize),
+    index: usize,
+    step: &Step,
+    ctx: &StepContext,
+    prev: Option<&LayerFile>,
+    path: String,
+    extra_layers: &mut ExtraLayerFiles,
+    progress: &mut Progress,
+) -> Result<LayerFile, String> {
+    let mut h = read_rows(prev, size, 0, size.1)?;
+    let prev_h = if step.is_blended() || (step.mask.is_some() && index > 0) {
+        Some(h.clone())
+    } else {
+        None
+    };
+    if step.starts_flat() {
+        h.fill(0.0);
+    }
+    let layers = step
+        .typ
+        .run_layers(step.seed(seed), size, &mut h, ctx, progress);
+    match prev_h {
+        Some(ref prev_h) if step.is_blended() => apply_blend(step, prev_h, &mut h),
+        _ => (),
+    }
+    if let Some(ref mask) = step.mask {
+        let base = match prev_h {
+            Some(ref prev_h) if index > 0 => MaskBase::Previous(prev_h),
+            _ => MaskBase::Min(get_min_max(&h).0),
+        };
+        apply_mask(size, mask, 0, base, &mut h);
+    }
+    for mut layer in layers {
+        if let Some(ref mask) = step.mask {
+            apply_mask(size, mask, 0, MaskBase::Min(0.0), &mut layer.values);
+        }
+        let mut writer = LayerWriter::new(format!("{}.{}", path, layer.name), size)?;
+        writer.write_rows(&layer.values)?;
+        // the file of the replaced layer is deleted here
+        extra_layers.retain(|(name, _)| *name != layer.name);
+        extra_layers.push((layer.name, writer.finish()?));
+    }
+    let mut writer = LayerWriter::new(path, size)?;
+    writer.write_rows(&h)?;
+    writer.finish()
+}
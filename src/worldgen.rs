@@ -1,50 +1,220 @@
+use std::borrow::Cow;
+use std::path::PathBuf;
 use std::sync::mpsc::Sender;
 use std::time::Instant;
 use std::{fmt::Display, sync::mpsc::Receiver};
 
-use serde::{Deserialize, Serialize};
+#[cfg(feature = "gui")]
+use eframe::egui;
+use serde::{
+    de::{self, DeserializeSeed, EnumAccess, VariantAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 
+use crate::cache::{step_hash, StepCache};
 use crate::generators::{
-    gen_fbm, gen_hills, gen_island, gen_landmass, gen_mid_point, gen_mudslide, gen_normalize,
-    gen_water_erosion, get_min_max, FbmConf, HillsConf, IslandConf, LandMassConf, MidPointConf,
-    MudSlideConf, NormalizeConf, WaterErosionConf,
+    find_generator, get_min_max, height_histogram, BlendMode, Chunk, DynGenerator, ExtraLayer,
+    LandMassConf, LayerInfo, Locality, Normalize, Progress, StepConf, StepContext,
 };
-use crate::{log, ThreadMessage, MASK_SIZE};
+use crate::{CancelToken, ThreadMessage, MASK_SIZE};
 
 #[derive(Debug)]
 /// commands sent by the main thread to the world generator thread
 pub enum WorldGenCommand {
-    /// recompute a specific step : step index, step conf, live preview, min progress step to report
-    ExecuteStep(usize, Step, bool, f32),
+    /// recompute a specific step : step index, step conf, live preview, min progress step to report,
+    /// token cancelling the computation once it's started
+    ExecuteStep(usize, Step, bool, f32, CancelToken),
     /// remove a step
     DeleteStep(usize),
     /// enable a step
     EnableStep(usize),
     /// disable a step
     DisableStep(usize),
-    /// change the heightmap size
-    SetSize(usize),
+    /// change the heightmap size (width, height)
+    SetSize((usize, usize)),
     /// return the heightmap for a given step
     GetStepMap(usize),
+    /// return the histogram of the input heightmap of a given step
+    GetStepHistogram(usize),
     /// change the random number generator seed
     SetSeed(u64),
     /// remove all steps
     Clear,
     /// cancel previous undone ExecuteStep commands from a specific step
     Abort(usize),
+    /// also save the big steps heightmaps in this directory, or only keep them in memory
+    SetCacheDir(Option<PathBuf>),
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
-/// Each value contains its own configuration
-pub enum StepType {
-    Hills(HillsConf),
-    Fbm(FbmConf),
-    Normalize(NormalizeConf),
-    LandMass(LandMassConf),
-    MudSlide(MudSlideConf),
-    WaterErosion(WaterErosionConf),
-    Island(IslandConf),
-    MidPoint(MidPointConf),
+/// a step generator with its configuration.
+/// Saved in project files as Name(configuration)
+pub struct StepType {
+    generator: &'static dyn DynGenerator,
+    conf: Box<dyn StepConf>,
+}
+
+impl StepType {
+    /// a step using the generator's default configuration
+    pub fn new(generator: &'static dyn DynGenerator) -> Self {
+        Self {
+            generator,
+            conf: generator.default_conf(),
+        }
+    }
+    /// a step using a registered generator's default configuration
+    pub fn from_name(name: &str) -> Option<Self> {
+        find_generator(name).map(Self::new)
+    }
+    pub fn name(&self) -> &'static str {
+        self.generator.name()
+    }
+    pub fn generator(&self) -> &'static dyn DynGenerator {
+        self.generator
+    }
+    /// the step configuration, if it is of type C
+    pub fn conf<C: 'static>(&self) -> Option<&C> {
+        self.conf.as_any().downcast_ref()
+    }
+    pub fn conf_mut<C: 'static>(&mut self) -> Option<&mut C> {
+        self.conf.as_any_mut().downcast_mut()
+    }
+    pub fn run(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) {
+        self.generator
+            .run(seed, size, hmap, self.conf.as_ref(), ctx, progress);
+    }
+    pub fn run_layers(
+        &self,
+        seed: u64,
+        size: (usize, usize),
+        hmap: &mut Vec<f32>,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Vec<ExtraLayer> {
+        self.generator
+            .run_layers(seed, size, hmap, self.conf.as_ref(), ctx, progress)
+    }
+    pub fn extra_memory(&self, size: (usize, usize)) -> usize {
+        self.generator.extra_memory(size, self.conf.as_ref())
+    }
+    pub fn is_source(&self) -> bool {
+        self.generator.is_source(self.conf.as_ref())
+    }
+    pub fn locality(&self, size: (usize, usize)) -> Locality {
+        self.generator.locality(size, self.conf.as_ref())
+    }
+    pub fn prepare(
+        &self,
+        seed: u64,
+        layer: &LayerInfo,
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Vec<f32> {
+        self.generator
+            .prepare(seed, layer, self.conf.as_ref(), ctx, progress)
+    }
+    pub fn run_chunk(
+        &self,
+        seed: u64,
+        chunk: &Chunk,
+        hmap: &mut [f32],
+        ctx: &StepContext,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        self.generator
+            .run_chunk(seed, chunk, hmap, self.conf.as_ref(), ctx, progress)
+    }
+    #[cfg(feature = "gui")]
+    pub fn render(&mut self, ui: &mut egui::Ui) {
+        self.generator.render(ui, self.conf.as_mut());
+    }
+}
+
+impl Clone for StepType {
+    fn clone(&self) -> Self {
+        Self {
+            generator: self.generator,
+            conf: self.conf.clone_box(),
+        }
+    }
+}
+
+impl PartialEq for StepType {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name() && self.conf.eq_box(other.conf.as_ref())
+    }
+}
+
+impl std::fmt::Debug for StepType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({:?})", self.name(), self.conf)
+    }
+}
+
+impl Serialize for StepType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_variant("StepType", 0, self.name(), self.conf.as_ref())
+    }
+}
+
+impl<'de> Deserialize<'de> for StepType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_enum("StepType", &[], StepTypeVisitor)
+    }
+}
+
+/// a registered generator, deserialized from its name
+struct GeneratorName(&'static dyn DynGenerator);
+
+impl<'de> Deserialize<'de> for GeneratorName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NameVisitor;
+        impl Visitor<'_> for NameVisitor {
+            type Value = GeneratorName;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a generator name")
+            }
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<GeneratorName, E> {
+                find_generator(name)
+                    .map(GeneratorName)
+                    .ok_or_else(|| E::custom(format!("unknown step type {}", name)))
+            }
+        }
+        deserializer.deserialize_identifier(NameVisitor)
+    }
+}
+
+/// deserialize a step configuration with the type expected by its generator
+struct ConfSeed(&'static dyn DynGenerator);
+
+impl<'de> DeserializeSeed<'de> for ConfSeed {
+    type Value = Box<dyn StepConf>;
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let mut deserializer = <dyn erased_serde::Deserializer>::erase(deserializer);
+        self.0
+            .deserialize_conf(&mut deserializer)
+            .map_err(de::Error::custom)
+    }
+}
+
+struct StepTypeVisitor;
+
+impl<'de> Visitor<'de> for StepTypeVisitor {
+    type Value = StepType;
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a step type")
+    }
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<StepType, A::Error> {
+        let (GeneratorName(generator), variant) = data.variant()?;
+        let conf = variant.newtype_variant_seed(ConfSeed(generator))?;
+        Ok(StepType { generator, conf })
+    }
 }
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Step {
@@ -54,6 +224,22 @@ pub struct Step {
     pub mask: Option<Vec<f32>>,
     /// step type with its configuration
     pub typ: StepType,
+    /// stable identifier used to derive this step seed from the project seed. 0 if not assigned yet
+    #[serde(default)]
+    pub id: u64,
+    /// use this seed instead of the one derived from the project seed
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// how this step output is combined with the incoming heightmap
+    #[serde(default)]
+    pub blend: BlendMode,
+    /// 0.0 : the incoming heightmap is kept, 1.0 : the blended heightmap replaces it
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+}
+
+fn default_opacity() -> f32 {
+    1.0
 }
 
 impl Default for Step {
@@ -61,16 +247,79 @@ impl Default for Step {
         Self {
             disabled: false,
             mask: None,
-            typ: StepType::Normalize(NormalizeConf::default()),
+            typ: StepType::new(&Normalize),
+            id: 0,
+            seed: None,
+            blend: BlendMode::Replace,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl Step {
+    /// the seed used by this step's random number generator
+    pub fn seed(&self, project_seed: u64) -> u64 {
+        self.seed
+            .unwrap_or_else(|| splitmix64(project_seed ^ splitmix64(self.id)))
+    }
+    /// the generator runs on a flat heightmap instead of the incoming one.
+    /// Its output is then blended with the incoming heightmap. Filters and simulations always run on the incoming heightmap
+    pub fn starts_flat(&self) -> bool {
+        self.blend != BlendMode::Replace && self.typ.is_source()
+    }
+    /// the context of the next step, given the context of this step
+    pub fn next_context(&self, ctx: StepContext) -> StepContext {
+        match self.typ.conf::<LandMassConf>() {
+            Some(conf) if !self.disabled => StepContext {
+                water_level: Some(conf.water_level),
+            },
+            _ => ctx,
         }
     }
+    /// the step output must be combined with the incoming heightmap
+    pub fn is_blended(&self) -> bool {
+        self.blend != BlendMode::Replace || self.opacity < 1.0
+    }
+}
+
+/// give an id to the steps that don't have one yet (new steps or steps from an old project file),
+/// after the largest id of the steps
+pub fn assign_step_ids(steps: &mut [Step]) {
+    assign_new_step_ids(steps, &mut 0);
+}
+
+/// give an id to the steps that don't have one yet. last_id is the last id given to a step of the project,
+/// so that a new step never gets the id, and therefore the seed, of a deleted step
+pub fn assign_new_step_ids(steps: &mut [Step], last_id: &mut u64) {
+    *last_id = steps.iter().map(|s| s.id).fold(*last_id, u64::max);
+    for step in steps.iter_mut().filter(|s| s.id == 0) {
+        *last_id += 1;
+        step.id = *last_id;
+    }
+}
+
+/// the steps with an id for each step, so that steps built without assign_step_ids don't all get the same seed
+pub(crate) fn with_step_ids(steps: &[Step]) -> Cow<'_, [Step]> {
+    if steps.iter().all(|s| s.id != 0) {
+        Cow::Borrowed(steps)
+    } else {
+        let mut steps = steps.to_vec();
+        assign_step_ids(&mut steps);
+        Cow::Owned(steps)
+    }
+}
+
+/// scramble a 64 bits value so that close inputs give unrelated outputs
+fn splitmix64(v: u64) -> u64 {
+    let mut z = v.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
 }
 
 impl Display for Step {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let debug_val = format!("{:?}", self.typ);
-        let val: Vec<&str> = debug_val.split('(').collect();
-        write!(f, "{}", val[0])
+        write!(f, "{}", self.typ.name())
     }
 }
 
@@ -93,7 +342,7 @@ impl ExportMap {
         }
         0.0
     }
-    pub fn borrow(&self) -> &Vec<f32> {
+    pub fn heights(&self) -> &[f32] {
         &self.h
     }
 }
@@ -102,6 +351,10 @@ impl ExportMap {
 struct HMap {
     h: Vec<f32>,
     disabled: bool,
+    /// hash of the step result (see [`step_hash`]). None if the heightmap is not cached or incomplete
+    hash: Option<u64>,
+    /// context of the next step
+    context: StepContext,
 }
 
 #[derive(Clone)]
@@ -109,6 +362,12 @@ pub struct WorldGenerator {
     seed: u64,
     world_size: (usize, usize),
     hmap: Vec<HMap>,
+    /// only keep the last step heightmap, computing the steps in place (see new_lean)
+    lean: bool,
+    /// previously computed steps heightmaps (see new_cached)
+    cache: Option<StepCache>,
+    /// additional layers computed by the steps during an export. A layer replaces the previous one with the same name
+    extra_layers: Vec<ExtraLayer>,
 }
 
 struct InnerStep {
@@ -116,6 +375,7 @@ struct InnerStep {
     step: Step,
     live: bool,
     min_progress_step: f32,
+    cancel: CancelToken,
 }
 
 fn do_command(
@@ -124,7 +384,7 @@ fn do_command(
     steps: &mut Vec<InnerStep>,
     tx: Sender<ThreadMessage>,
 ) {
-    log(&format!("wgen<={:?}", msg));
+    log::debug!("wgen<={:?}", msg);
     match msg {
         WorldGenCommand::Clear => {
             wgen.clear();
@@ -132,16 +392,20 @@ fn do_command(
         WorldGenCommand::SetSeed(new_seed) => {
             wgen.seed = new_seed;
         }
-        WorldGenCommand::ExecuteStep(index, step, live, min_progress_step) => {
+        WorldGenCommand::ExecuteStep(index, step, live, min_progress_step, cancel) => {
             steps.push(InnerStep {
                 index,
                 step,
                 live,
                 min_progress_step,
+                cancel,
             });
         }
         WorldGenCommand::DeleteStep(index) => {
-            wgen.hmap.remove(index);
+            // the step may not have been computed yet
+            if index < wgen.hmap.len() {
+                wgen.hmap.remove(index);
+            }
         }
         WorldGenCommand::DisableStep(index) => {
             wgen.hmap[index].disabled = true;
@@ -155,6 +419,12 @@ fn do_command(
                 wgen.get_step_export_map(index),
             ))
             .unwrap(),
+        WorldGenCommand::GetStepHistogram(index) => tx
+            .send(ThreadMessage::GeneratorStepHistogram(
+                index,
+                Box::new(wgen.get_step_input_histogram(index)),
+            ))
+            .unwrap(),
         WorldGenCommand::Abort(from_idx) => {
             let mut i = 0;
             while i < steps.len() {
@@ -166,18 +436,26 @@ fn do_command(
             }
         }
         WorldGenCommand::SetSize(size) => {
-            *wgen = WorldGenerator::new(wgen.seed, (size, size));
+            // keep the cache so that going back to a previous size doesn't recompute anything
+            let cache = wgen.cache.take();
+            *wgen = WorldGenerator::new(wgen.seed, size);
+            wgen.cache = cache;
+        }
+        WorldGenCommand::SetCacheDir(dir) => {
+            if let Some(ref mut cache) = wgen.cache {
+                cache.set_dir(dir);
+            }
         }
     }
 }
 
 pub fn generator_thread(
     seed: u64,
-    size: usize,
+    size: (usize, usize),
     rx: Receiver<WorldGenCommand>,
     tx: Sender<ThreadMessage>,
 ) {
-    let mut wgen = WorldGenerator::new(seed, (size, size));
+    let mut wgen = WorldGenerator::new_cached(seed, size, StepCache::default());
     let mut steps = Vec::new();
     loop {
         if steps.is_empty() {
@@ -197,15 +475,19 @@ pub fn generator_thread(
                 step,
                 live,
                 min_progress_step,
+                cancel,
             } = steps.remove(0);
             let tx2 = tx.clone();
-            wgen.execute_step(index, &step, false, tx2, min_progress_step);
-            if steps.is_empty() {
-                log("wgen=>Done");
+            wgen.execute_step(index, &step, false, tx2, min_progress_step, &cancel);
+            if cancel.is_cancelled() {
+                // the heightmap is incomplete. The main thread already sent the commands to recompute it
+                log::debug!("wgen=>Cancelled({})", index);
+            } else if steps.is_empty() {
+                log::debug!("wgen=>Done");
                 tx.send(ThreadMessage::GeneratorDone(wgen.get_export_map()))
                     .unwrap();
             } else {
-                log(&format!("wgen=>GeneratorStepDone({})", index));
+                log::debug!("wgen=>GeneratorStepDone({})", index);
                 tx.send(ThreadMessage::GeneratorStepDone(
                     index,
                     if live {
@@ -225,8 +507,30 @@ impl WorldGenerator {
             seed,
             world_size,
             hmap: Vec::new(),
+            lean: false,
+            cache: None,
+            extra_layers: Vec::new(),
         }
     }
+    /// a generator that doesn't keep the intermediate steps heightmaps.
+    /// generate uses a single working buffer plus one copy for masked steps.
+    /// Only the final heightmap is available
+    pub fn new_lean(seed: u64, world_size: (usize, usize)) -> Self {
+        Self {
+            lean: true,
+            ..Self::new(seed, world_size)
+        }
+    }
+    /// a generator that only recomputes the steps whose configuration, mask, seed or previous step changed
+    pub fn new_cached(seed: u64, world_size: (usize, usize), cache: StepCache) -> Self {
+        Self {
+            cache: Some(cache),
+            ..Self::new(seed, world_size)
+        }
+    }
+    pub fn cache(&self) -> Option<&StepCache> {
+        self.cache.as_ref()
+    }
     pub fn get_export_map(&self) -> ExportMap {
         self.get_step_export_map(if self.hmap.is_empty() {
             0
@@ -245,6 +549,17 @@ impl WorldGenerator {
         }
     }
 
+    /// histogram of the heightmap computed by the steps before step, between its min and max heights
+    pub fn get_step_input_histogram(&self, step: usize) -> [usize; 256] {
+        let mut histogram = [0; 256];
+        if step > 0 && step <= self.hmap.len() {
+            let h = &self.hmap[step - 1].h;
+            let (min, max) = get_min_max(h);
+            height_histogram(h, min, max, &mut histogram);
+        }
+        histogram
+    }
+
     pub fn combined_height(&self, x: usize, y: usize) -> f32 {
         let off = x + y * self.world_size.0;
         if !self.hmap.is_empty() && off < self.world_size.0 * self.world_size.1 {
@@ -253,7 +568,38 @@ impl WorldGenerator {
         0.0
     }
     pub fn clear(&mut self) {
-        *self = WorldGenerator::new(self.seed, self.world_size);
+        self.hmap.clear();
+        self.extra_layers.clear();
+    }
+    /// the additional layers computed by the steps, only available after generate
+    pub fn extra_layers(&self) -> &[ExtraLayer] {
+        &self.extra_layers
+    }
+    /// keep the additional layers computed by a step, applying the step mask
+    fn add_extra_layers(&mut self, step: &Step, layers: Vec<ExtraLayer>) {
+        for mut layer in layers {
+            if let Some(ref mask) = step.mask {
+                apply_mask(
+                    self.world_size,
+                    mask,
+                    0,
+                    MaskBase::Min(0.0),
+                    &mut layer.values,
+                );
+            }
+            self.extra_layers.retain(|l| l.name != layer.name);
+            self.extra_layers.push(layer);
+        }
+    }
+    /// hash of a step result, if the generator has a cache and the previous step result is known
+    fn step_hash(&self, index: usize, step: &Step) -> Option<u64> {
+        self.cache.as_ref()?;
+        let upstream = if index == 0 {
+            0
+        } else {
+            self.hmap.get(index - 1)?.hash?
+        };
+        Some(step_hash(upstream, step, self.seed, self.world_size))
     }
 
     fn execute_step(
@@ -263,8 +609,14 @@ impl WorldGenerator {
         export: bool,
         tx: Sender<ThreadMessage>,
         min_progress_step: f32,
+        cancel: &CancelToken,
     ) {
         let now = Instant::now();
+        let hash = self.step_hash(index, step);
+        if hash.is_some() && self.hmap.get(index).is_some_and(|m| m.hash == hash) {
+            log::info!("{} is up to date", step);
+            return;
+        }
         let len = self.hmap.len();
         if index >= len {
             let vecsize = self.world_size.0 * self.world_size.1;
@@ -272,11 +624,15 @@ impl WorldGenerator {
                 HMap {
                     h: vec![0.0; vecsize],
                     disabled: false,
+                    hash: None,
+                    context: StepContext::default(),
                 }
             } else {
                 HMap {
                     h: self.hmap[len - 1].h.clone(),
                     disabled: false,
+                    hash: None,
+                    context: StepContext::default(),
                 }
             });
         } else if index > 0 {
@@ -284,157 +640,164 @@ impl WorldGenerator {
         } else {
             self.hmap[index].h.fill(0.0);
         }
-        {
-            let hmap = &mut self.hmap[index];
-            match step {
-                Step {
-                    typ: StepType::Hills(conf),
-                    disabled,
-                    ..
-                } => {
-                    if !*disabled {
-                        gen_hills(
-                            self.seed,
-                            self.world_size,
-                            &mut hmap.h,
-                            conf,
-                            export,
-                            tx,
-                            min_progress_step,
-                        );
-                    }
-                }
-                Step {
-                    typ: StepType::Fbm(conf),
-                    disabled,
-                    ..
-                } => {
-                    if !*disabled {
-                        gen_fbm(
-                            self.seed,
-                            self.world_size,
-                            &mut hmap.h,
-                            conf,
-                            export,
-                            tx,
-                            min_progress_step,
-                        );
-                    }
-                }
-                Step {
-                    typ: StepType::MidPoint(conf),
-                    disabled,
-                    ..
-                } => {
-                    if !*disabled {
-                        gen_mid_point(
-                            self.seed,
-                            self.world_size,
-                            &mut hmap.h,
-                            conf,
-                            export,
-                            tx,
-                            min_progress_step,
-                        );
-                    }
-                }
-                Step {
-                    typ: StepType::Normalize(conf),
-                    disabled,
-                    ..
-                } => {
-                    if !*disabled {
-                        gen_normalize(&mut hmap.h, conf);
-                    }
-                }
-                Step {
-                    typ: StepType::LandMass(conf),
-                    disabled,
-                    ..
-                } => {
-                    if !*disabled {
-                        gen_landmass(
-                            self.world_size,
-                            &mut hmap.h,
-                            conf,
-                            export,
-                            tx,
-                            min_progress_step,
-                        );
-                    }
-                }
-                Step {
-                    typ: StepType::MudSlide(conf),
-                    disabled,
-                    ..
-                } => {
-                    if !*disabled {
-                        gen_mudslide(
-                            self.world_size,
-                            &mut hmap.h,
-                            conf,
-                            export,
-                            tx,
-                            min_progress_step,
-                        );
-                    }
-                }
-                Step {
-                    typ: StepType::WaterErosion(conf),
-                    disabled,
-                    ..
-                } => {
-                    if !*disabled {
-                        gen_water_erosion(
-                            self.seed,
-                            self.world_size,
-                            &mut hmap.h,
-                            conf,
-                            export,
-                            tx,
-                            min_progress_step,
-                        );
-                    }
-                }
-                Step {
-                    typ: StepType::Island(conf),
-                    disabled,
-                    ..
-                } => {
-                    if !*disabled {
-                        gen_island(
-                            self.world_size,
-                            &mut hmap.h,
-                            conf,
-                            export,
-                            tx,
-                            min_progress_step,
-                        );
-                    }
+        self.hmap[index].hash = None;
+        let ctx = if index > 0 {
+            self.hmap[index - 1].context
+        } else {
+            StepContext::default()
+        };
+        self.hmap[index].context = step.next_context(ctx);
+        if let (Some(hash), Some(cache)) = (hash, self.cache.as_mut()) {
+            if let Some(cached) = cache.get(hash, self.hmap[index].h.len()) {
+                self.hmap[index].h.copy_from_slice(cached);
+                self.hmap[index].hash = Some(hash);
+                log::info!("Loaded {} from cache", step);
+                return;
+            }
+        }
+        if !step.disabled {
+            if step.starts_flat() {
+                self.hmap[index].h.fill(0.0);
+            }
+            let mut progress = Progress::new(tx, export, min_progress_step, cancel.clone());
+            if export {
+                let layers = step.typ.run_layers(
+                    step.seed(self.seed),
+                    self.world_size,
+                    &mut self.hmap[index].h,
+                    &ctx,
+                    &mut progress,
+                );
+                self.add_extra_layers(step, layers);
+            } else {
+                step.typ.run(
+                    step.seed(self.seed),
+                    self.world_size,
+                    &mut self.hmap[index].h,
+                    &ctx,
+                    &mut progress,
+                );
+            }
+            if step.is_blended() {
+                if index > 0 {
+                    let (prev, current) = self.hmap.split_at_mut(index);
+                    apply_blend(step, &prev[index - 1].h, &mut current[0].h);
+                } else {
+                    // the first step is blended with a flat heightmap
+                    let flat = vec![0.0; self.hmap[index].h.len()];
+                    apply_blend(step, &flat, &mut self.hmap[index].h);
                 }
             }
         }
         if let Some(ref mask) = step.mask {
             if index > 0 {
                 let prev = self.hmap[index - 1].h.clone();
-                apply_mask(self.world_size, mask, Some(&prev), &mut self.hmap[index].h);
+                apply_mask(
+                    self.world_size,
+                    mask,
+                    0,
+                    MaskBase::Previous(&prev),
+                    &mut self.hmap[index].h,
+                );
             } else {
-                apply_mask(self.world_size, mask, None, &mut self.hmap[index].h);
+                let (min, _) = get_min_max(&self.hmap[index].h);
+                apply_mask(
+                    self.world_size,
+                    mask,
+                    0,
+                    MaskBase::Min(min),
+                    &mut self.hmap[index].h,
+                );
+            }
+        }
+        if let (Some(hash), Some(cache)) = (hash, self.cache.as_mut()) {
+            // an incomplete heightmap must not be reused
+            if !cancel.is_cancelled() {
+                cache.insert(hash, &self.hmap[index].h);
+                self.hmap[index].hash = Some(hash);
             }
         }
 
-        log(&format!(
-            "Executed {} in {:.2}s",
-            step,
-            now.elapsed().as_secs_f32()
-        ));
+        log::info!("Executed {} in {:.2}s", step, now.elapsed().as_secs_f32());
+    }
+
+    /// compute a step on the only heightmap of a lean generator
+    fn execute_step_in_place(
+        &mut self,
+        index: usize,
+        step: &Step,
+        tx: Sender<ThreadMessage>,
+        min_progress_step: f32,
+        cancel: &CancelToken,
+    ) {
+        let now = Instant::now();
+        if self.hmap.is_empty() {
+            self.hmap.push(HMap {
+                h: vec![0.0; self.world_size.0 * self.world_size.1],
+                disabled: false,
+                hash: None,
+                context: StepContext::default(),
+            });
+        }
+        let ctx = self.hmap[0].context;
+        self.hmap[0].context = step.next_context(ctx);
+        if step.disabled {
+            return;
+        }
+        let h = &mut self.hmap[0].h;
+        // the only copy, needed to blend the step output or its mask with the previous step
+        let prev = if step.is_blended() || (step.mask.is_some() && index > 0) {
+            Some(h.clone())
+        } else {
+            None
+        };
+        if step.starts_flat() {
+            h.fill(0.0);
+        }
+        let mut progress = Progress::new(tx, true, min_progress_step, cancel.clone());
+        let layers = step.typ.run_layers(
+            step.seed(self.seed),
+            self.world_size,
+            h,
+            &ctx,
+            &mut progress,
+        );
+        match prev {
+            Some(ref prev) if step.is_blended() => apply_blend(step, prev, h),
+            _ => (),
+        }
+        if let Some(ref mask) = step.mask {
+            let base = match prev {
+                Some(ref prev) if index > 0 => MaskBase::Previous(prev),
+                _ => MaskBase::Min(get_min_max(h).0),
+            };
+            apply_mask(self.world_size, mask, 0, base, h);
+        }
+        self.add_extra_layers(step, layers);
+        log::info!("Executed {} in {:.2}s", step, now.elapsed().as_secs_f32());
     }
 
-    pub fn generate(&mut self, steps: &[Step], tx: Sender<ThreadMessage>, min_progress_step: f32) {
+    /// compute all the steps. Stops after the current step if cancel is triggered,
+    /// leaving an incomplete heightmap. The steps without an id are numbered as by assign_step_ids
+    pub fn generate(
+        &mut self,
+        steps: &[Step],
+        tx: Sender<ThreadMessage>,
+        min_progress_step: f32,
+        cancel: &CancelToken,
+    ) {
         self.clear();
+        let steps = with_step_ids(steps);
         for (i, step) in steps.iter().enumerate() {
             let tx2 = tx.clone();
-            self.execute_step(i, step, true, tx2, min_progress_step);
+            if self.lean {
+                self.execute_step_in_place(i, step, tx2, min_progress_step, cancel);
+            } else {
+                self.execute_step(i, step, true, tx2, min_progress_step, cancel);
+            }
+            if cancel.is_cancelled() {
+                return;
+            }
             tx.send(ThreadMessage::ExporterStepDone(i)).unwrap();
         }
     }
@@ -448,15 +811,33 @@ impl WorldGenerator {
     }
 }
 
-fn apply_mask(world_size: (usize, usize), mask: &[f32], prev: Option<&[f32]>, h: &mut [f32]) {
+/// combine the output h of a step with the incoming heightmap prev (same rows as h)
+pub(crate) fn apply_blend(step: &Step, prev: &[f32], h: &mut [f32]) {
+    for (h, prev) in h.iter_mut().zip(prev.iter()) {
+        let blended = step.blend.blend(*prev, *h);
+        *h = (1.0 - step.opacity) * prev + step.opacity * blended;
+    }
+}
+
+/// what the masked out parts of a step are blended with
+pub(crate) enum MaskBase<'a> {
+    /// the previous step heightmap (same rows as the masked heightmap)
+    Previous(&'a [f32]),
+    /// the step minimum height, for the first step
+    Min(f32),
+}
+
+/// apply a step mask to some rows of the world, starting at first_row
+pub(crate) fn apply_mask(
+    world_size: (usize, usize),
+    mask: &[f32],
+    first_row: usize,
+    base: MaskBase,
+    h: &mut [f32],
+) {
     let mut off = 0;
-    let (min, _) = if prev.is_none() {
-        get_min_max(h)
-    } else {
-        (0.0, 0.0)
-    };
-    for y in 0..world_size.1 {
-        let myf = (y * MASK_SIZE) as f32 / world_size.0 as f32;
+    for y in first_row..first_row + h.len() / world_size.0 {
+        let myf = (y * MASK_SIZE) as f32 / world_size.1 as f32;
         let my = myf as usize;
         let yalpha = myf.fract();
         for x in 0..world_size.0 {
@@ -474,10 +855,13 @@ fn apply_mask(world_size: (usize, usize), mask: &[f32], prev: Option<&[f32]>, h:
                     mask_value = (1.0 - yalpha) * mask_value + yalpha * bottom_mask;
                 }
             }
-            if let Some(prev) = prev {
-                h[off] = (1.0 - mask_value) * prev[off] + mask_value * h[off];
-            } else {
-                h[off] = (1.0 - mask_value) * min + mask_value * (h[off] - min);
+            match base {
+                MaskBase::Previous(prev) => {
+                    h[off] = (1.0 - mask_value) * prev[off] + mask_value * h[off];
+                }
+                MaskBase::Min(min) => {
+                    h[off] = (1.0 - mask_value) * min + mask_value * (h[off] - min);
+                }
             }
             off += 1;
         }
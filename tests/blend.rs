@@ -0,0 +1,182 @@
+//! Step blend modes : the step output is combined with the incoming heightmap.
+mod common;
+
+use std::sync::mpsc;
+
+use common::{export_png, export_settings, SEED};
+use worldgen::generators::{Blur, DynGenerator, Hills, MidPoint, Noise, Normalize};
+use worldgen::{
+    estimate_export_memory, BlendMode, CancelToken, ExportSettings, Step, StepType, WorldGenerator,
+    MASK_SIZE,
+};
+
+const SIZE: (usize, usize) = (128, 128);
+
+fn step(generator: &'static dyn DynGenerator, id: u64, blend: BlendMode, opacity: f32) -> Step {
+    Step {
+        typ: StepType::new(generator),
+        id,
+        blend,
+        opacity,
+        ..Default::default()
+    }
+}
+
+fn generate(steps: &[Step], lean: bool) -> Vec<f32> {
+    let (tx, _rx) = mpsc::channel();
+    let mut wgen = if lean {
+        WorldGenerator::new_lean(SEED, SIZE)
+    } else {
+        WorldGenerator::new(SEED, SIZE)
+    };
+    wgen.generate(steps, tx, 1.0, &CancelToken::default());
+    wgen.get_export_map().heights().to_vec()
+}
+
+fn assert_close(a: &[f32], b: &[f32]) {
+    for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+        assert!((a - b).abs() < 1e-5, "cell {} : {} != {}", i, a, b);
+    }
+}
+
+/// a project mixing all kinds of blended steps, with a mask
+fn blended_project() -> Vec<Step> {
+    let mask: Vec<f32> = (0..MASK_SIZE * MASK_SIZE)
+        .map(|i| (i % MASK_SIZE) as f32 / MASK_SIZE as f32)
+        .collect();
+    vec![
+        step(&Hills, 1, BlendMode::Add, 0.8),
+        step(&Normalize, 2, BlendMode::Replace, 1.0),
+        Step {
+            mask: Some(mask),
+            ..step(&Noise, 3, BlendMode::Difference, 0.5)
+        },
+        step(&MidPoint, 4, BlendMode::Screen, 0.3),
+        step(&Noise, 5, BlendMode::Max, 1.0),
+        step(&Blur, 6, BlendMode::Subtract, 0.5),
+    ]
+}
+
+#[test]
+fn add_blends_a_fresh_layer() {
+    let hills = generate(&[step(&Hills, 1, BlendMode::Replace, 1.0)], false);
+    let noise = generate(&[step(&Noise, 2, BlendMode::Replace, 1.0)], false);
+    let blended = generate(
+        &[
+            step(&Hills, 1, BlendMode::Replace, 1.0),
+            step(&Noise, 2, BlendMode::Add, 1.0),
+        ],
+        false,
+    );
+    let expected: Vec<f32> = hills.iter().zip(noise.iter()).map(|(h, n)| h + n).collect();
+    assert_close(&blended, &expected);
+    let blended = generate(
+        &[
+            step(&Hills, 1, BlendMode::Replace, 1.0),
+            step(&Noise, 2, BlendMode::Min, 1.0),
+        ],
+        false,
+    );
+    let expected: Vec<f32> = hills
+        .iter()
+        .zip(noise.iter())
+        .map(|(h, n)| h.min(*n))
+        .collect();
+    assert_close(&blended, &expected);
+}
+
+#[test]
+fn filters_blend_the_filtered_heightmap() {
+    let hills = generate(&[step(&Hills, 1, BlendMode::Replace, 1.0)], false);
+    let normalized = generate(
+        &[
+            step(&Hills, 1, BlendMode::Replace, 1.0),
+            step(&Normalize, 2, BlendMode::Replace, 1.0),
+        ],
+        false,
+    );
+    let blended = generate(
+        &[
+            step(&Hills, 1, BlendMode::Replace, 1.0),
+            step(&Normalize, 2, BlendMode::Difference, 1.0),
+        ],
+        false,
+    );
+    let expected: Vec<f32> = hills
+        .iter()
+        .zip(normalized.iter())
+        .map(|(h, n)| (h - n).abs())
+        .collect();
+    assert_close(&blended, &expected);
+}
+
+#[test]
+fn opacity_mixes_with_the_incoming_heightmap() {
+    let hills = generate(&[step(&Hills, 1, BlendMode::Replace, 1.0)], false);
+    let normalized = generate(
+        &[
+            step(&Hills, 1, BlendMode::Replace, 1.0),
+            step(&Normalize, 2, BlendMode::Replace, 1.0),
+        ],
+        false,
+    );
+    let half = generate(
+        &[
+            step(&Hills, 1, BlendMode::Replace, 1.0),
+            step(&Normalize, 2, BlendMode::Replace, 0.5),
+        ],
+        false,
+    );
+    let expected: Vec<f32> = hills
+        .iter()
+        .zip(normalized.iter())
+        .map(|(h, n)| (h + n) * 0.5)
+        .collect();
+    assert_close(&half, &expected);
+    let none = generate(
+        &[
+            step(&Hills, 1, BlendMode::Replace, 1.0),
+            step(&Noise, 2, BlendMode::Subtract, 0.0),
+        ],
+        false,
+    );
+    assert_close(&none, &hills);
+}
+
+#[test]
+fn lean_generator_blends_identically() {
+    let steps = blended_project();
+    assert_close(&generate(&steps, true), &generate(&steps, false));
+}
+
+#[test]
+fn streaming_export_blends_identically() {
+    let steps = blended_project();
+    let export = |streaming: bool| {
+        let settings = ExportSettings {
+            streaming,
+            ..export_settings(&format!("blend_{}", streaming), SIZE)
+        };
+        export_png(&steps, &settings)
+    };
+    assert_eq!(export(false), export(true), "streaming export differs");
+}
+
+#[test]
+fn blended_export_estimates_two_buffers() {
+    let settings = ExportSettings {
+        export_width: SIZE.0 as f32,
+        export_height: SIZE.1 as f32,
+        ..Default::default()
+    };
+    let cells = SIZE.0 * SIZE.1 * std::mem::size_of::<f32>();
+    let opaque = [step(&Normalize, 1, BlendMode::Replace, 1.0)];
+    assert!(estimate_export_memory(&opaque, &settings) < 2 * cells);
+    // the lean generator keeps a copy of the incoming heightmap to blend the step output with
+    for blended in [
+        step(&Normalize, 1, BlendMode::Replace, 0.5),
+        step(&Normalize, 1, BlendMode::Max, 1.0),
+    ] {
+        assert_eq!(estimate_export_memory(&[blended], &settings), 2 * cells);
+    }
+}
@@ -0,0 +1,79 @@
+//! Cached steps give the same terrain as a full computation and are only recomputed when their inputs change.
+mod common;
+
+use std::sync::mpsc;
+
+use common::{project, SEED};
+use worldgen::generators::{Hills, Noise, NoiseConf, Normalize};
+use worldgen::{CancelToken, Step, StepCache, StepType, WorldGenerator};
+
+fn steps() -> Vec<Step> {
+    project(&[&Hills, &Noise, &Normalize])
+}
+
+fn generate(wgen: &mut WorldGenerator, steps: &[Step]) -> Vec<f32> {
+    let (tx, _rx) = mpsc::channel();
+    wgen.generate(steps, tx, 1.0, &CancelToken::default());
+    wgen.get_export_map().heights().to_vec()
+}
+
+#[test]
+fn cached_steps_match_computed_steps() {
+    let size = (64, 64);
+    let mut steps = steps();
+    let mut cached = WorldGenerator::new_cached(SEED, size, StepCache::default());
+    generate(&mut cached, &steps);
+    assert_eq!(cached.cache().unwrap().len(), 3);
+    // only the modified step and the following ones are recomputed
+    steps[1].typ.conf_mut::<NoiseConf>().unwrap().mulx = 4.0;
+    let hmap = generate(&mut cached, &steps);
+    assert_eq!(cached.cache().unwrap().len(), 5);
+    assert_eq!(hmap, generate(&mut WorldGenerator::new(SEED, size), &steps));
+    // going back to the previous configuration only uses the cache
+    steps[1].typ = StepType::new(&Noise);
+    generate(&mut cached, &steps);
+    assert_eq!(cached.cache().unwrap().len(), 5);
+    // a different seed invalidates all the steps
+    let mut cached = WorldGenerator::new_cached(SEED + 1, size, cached.cache().unwrap().clone());
+    generate(&mut cached, &steps);
+    assert_eq!(cached.cache().unwrap().len(), 8);
+}
+
+#[test]
+fn disk_cache_survives_the_generator() {
+    let size = (256, 256);
+    let steps = steps();
+    let dir = std::env::temp_dir().join(format!("wgen_test_cache_{}", std::process::id()));
+    let mut wgen = WorldGenerator::new_cached(SEED, size, StepCache::new(Some(dir.clone())));
+    let hmap = generate(&mut wgen, &steps);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+    let mut wgen = WorldGenerator::new_cached(SEED, size, StepCache::new(Some(dir.clone())));
+    assert_eq!(generate(&mut wgen, &steps), hmap);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn disk_cache_keeps_the_most_recent_heightmaps() {
+    let size = (256, 256);
+    let mut steps = steps();
+    let dir = std::env::temp_dir().join(format!("wgen_test_cache_budget_{}", std::process::id()));
+    let map_bytes = (size.0 * size.1 * std::mem::size_of::<f32>()) as u64;
+    let cache = || StepCache::new(Some(dir.clone())).with_disk_budget(2 * map_bytes as usize);
+    let mut wgen = WorldGenerator::new_cached(SEED, size, cache());
+    generate(&mut wgen, &steps);
+    // every change writes new files, the oldest are deleted
+    for mulx in [2.0, 4.0, 8.0] {
+        steps[1].typ.conf_mut::<NoiseConf>().unwrap().mulx = mulx;
+        generate(&mut wgen, &steps);
+        let total: u64 = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().metadata().unwrap().len())
+            .sum();
+        assert!(total <= 2 * map_bytes, "disk cache uses {} bytes", total);
+    }
+    // the last steps can be loaded by a new generator
+    let hmap = generate(&mut WorldGenerator::new(SEED, size), &steps);
+    let mut wgen = WorldGenerator::new_cached(SEED, size, cache());
+    assert_eq!(generate(&mut wgen, &steps), hmap);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
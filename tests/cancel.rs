@@ -0,0 +1,98 @@
+//! A cancelled computation stops during the running step and doesn't report the following steps as done.
+mod common;
+
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use common::{export_settings, project, SEED};
+use worldgen::generators::{Hills, HydraulicErosion, Normalize};
+use worldgen::{
+    export_heightmap, CancelToken, ExportSettings, Step, ThreadMessage, WorldGenerator,
+};
+
+const SIZE: (usize, usize) = (256, 256);
+
+/// a fast first step followed by a slow one
+fn steps() -> Vec<Step> {
+    project(&[&Hills, &HydraulicErosion, &Normalize])
+}
+
+/// run the computation, cancelling it as soon as the second step reports some progress.
+/// Returns the result of the computation, the indexes of the steps reported as done
+/// and the last progress reported by the second step
+fn run_cancelled<T, F: FnOnce(Sender<ThreadMessage>, &CancelToken) -> T>(
+    compute: F,
+) -> (T, Vec<usize>, f32) {
+    let (tx, rx) = mpsc::channel();
+    let cancel = CancelToken::default();
+    let watcher = {
+        let cancel = cancel.clone();
+        thread::spawn(move || {
+            let mut done = Vec::new();
+            let mut last_progress = 0.0;
+            // ends when the computation drops its senders
+            for msg in rx {
+                match msg {
+                    ThreadMessage::ExporterStepDone(i) => done.push(i),
+                    ThreadMessage::ExporterStepProgress(p) if done == [0] => {
+                        last_progress = p;
+                        cancel.cancel();
+                    }
+                    _ => (),
+                }
+            }
+            (done, last_progress)
+        })
+    };
+    let res = compute(tx, &cancel);
+    let (done, last_progress) = watcher.join().unwrap();
+    (res, done, last_progress)
+}
+
+fn assert_stopped_during_second_step(done: &[usize], last_progress: f32) {
+    assert_eq!(done, [0], "steps reported as done");
+    assert!(
+        last_progress > 0.0 && last_progress < 1.0,
+        "the second step progress reached {}",
+        last_progress
+    );
+}
+
+#[test]
+fn cancelled_generate_stops_during_the_step() {
+    let steps = steps();
+    let (_, done, last_progress) = run_cancelled(|tx, cancel| {
+        let mut wgen = WorldGenerator::new(SEED, SIZE);
+        wgen.generate(&steps, tx, 0.0, cancel);
+    });
+    assert_stopped_during_second_step(&done, last_progress);
+}
+
+fn check_cancelled_export(streaming: bool) {
+    let settings = ExportSettings {
+        tiles_h: 2.0,
+        tiles_v: 2.0,
+        streaming,
+        ..export_settings(&format!("cancel_{}", streaming), (SIZE.0 / 2, SIZE.1 / 2))
+    };
+    let steps = steps();
+    let (res, done, last_progress) =
+        run_cancelled(|tx, cancel| export_heightmap(SEED, &steps, &settings, tx, 0.0, cancel));
+    assert!(res.is_err(), "cancelled export returned Ok");
+    assert_stopped_during_second_step(&done, last_progress);
+    for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+        let tile = format!("{}_x{}_y{}.png", settings.file_path, x, y);
+        assert!(!Path::new(&tile).exists(), "{} was written", tile);
+    }
+}
+
+#[test]
+fn cancelled_export_writes_no_tile() {
+    check_cancelled_export(false);
+}
+
+#[test]
+fn cancelled_streaming_export_writes_no_tile() {
+    check_cancelled_export(true);
+}
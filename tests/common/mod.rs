@@ -0,0 +1,59 @@
+//! Fixtures shared by the integration tests.
+// each test crate only uses some of them
+#![allow(dead_code)]
+
+use std::sync::mpsc;
+
+use worldgen::generators::DynGenerator;
+use worldgen::{assign_step_ids, export_heightmap, CancelToken, ExportSettings, Step, StepType};
+
+pub const SEED: u64 = 0xdeadbeef;
+
+/// a project with a step of each generator, with their default parameters
+pub fn project(generators: &[&'static dyn DynGenerator]) -> Vec<Step> {
+    let mut steps: Vec<Step> = generators
+        .iter()
+        .map(|g| Step {
+            typ: StepType::new(*g),
+            ..Default::default()
+        })
+        .collect();
+    assign_step_ids(&mut steps);
+    steps
+}
+
+/// settings exporting a single PNG tile of the given size in the temporary directory.
+/// name makes the files unique among the tests
+pub fn export_settings(name: &str, size: (usize, usize)) -> ExportSettings {
+    let file_path = std::env::temp_dir()
+        .join(format!("wgen_test_{}_{}", name, std::process::id()))
+        .display()
+        .to_string();
+    ExportSettings {
+        export_width: size.0 as f32,
+        export_height: size.1 as f32,
+        file_path,
+        ..Default::default()
+    }
+}
+
+/// read and delete a 16 bits PNG file of the given size
+pub fn read_png(path: &str, size: (usize, usize)) -> Vec<u16> {
+    let img = image::open(path).unwrap().into_luma16();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(img.dimensions(), (size.0 as u32, size.1 as u32));
+    img.into_raw()
+}
+
+/// export the project and return the heights of the first tile. The additional layers files are left for read_png
+pub fn export_png(steps: &[Step], settings: &ExportSettings) -> Vec<u16> {
+    let (tx, _rx) = mpsc::channel();
+    export_heightmap(SEED, steps, settings, tx, 1.0, &CancelToken::default()).unwrap();
+    read_png(
+        &format!("{}_x0_y0.png", settings.file_path),
+        (
+            settings.export_width as usize,
+            settings.export_height as usize,
+        ),
+    )
+}
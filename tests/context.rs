@@ -0,0 +1,80 @@
+//! Steps read the water level of the nearest enabled LandMass step before them.
+mod common;
+
+use std::sync::mpsc;
+
+use common::{export_png, export_settings, project, SEED};
+use worldgen::generators::{
+    DynGenerator, Hills, LandMass, LandMassConf, Noise, Normalize, Rivers, Terrace,
+};
+use worldgen::{CancelToken, ExportSettings, Step, WorldGenerator};
+
+const SIZE: (usize, usize) = (128, 128);
+const WATER_LEVEL: f32 = 0.3;
+
+fn generate(steps: &[Step]) -> Vec<f32> {
+    let (tx, _rx) = mpsc::channel();
+    let mut wgen = WorldGenerator::new(SEED, SIZE);
+    wgen.generate(steps, tx, 1.0, &CancelToken::default());
+    wgen.get_export_map().heights().to_vec()
+}
+
+/// a terrain with a LandMass step using WATER_LEVEL followed by the step
+fn steps(step: &'static dyn DynGenerator) -> Vec<Step> {
+    let mut steps = project(&[&Hills, &Noise, &Normalize, &LandMass, step]);
+    steps[3].typ.conf_mut::<LandMassConf>().unwrap().water_level = WATER_LEVEL;
+    steps
+}
+
+#[test]
+fn terrace_keeps_the_terrain_under_the_land_mass_water_level() {
+    let mut steps = steps(&Terrace);
+    let terrain = generate(&steps[..4]);
+    let terraced = generate(&steps);
+    let mut land_changed = false;
+    for (t, h) in terraced.iter().zip(terrain.iter()) {
+        if *h < WATER_LEVEL {
+            assert_eq!(t, h, "underwater terrain changed");
+        } else {
+            land_changed |= t != h;
+        }
+    }
+    assert!(land_changed, "the land is not terraced");
+    // without water level, the whole terrain is terraced
+    steps[3].disabled = true;
+    let terrain = generate(&steps[..4]);
+    let terraced = generate(&steps);
+    assert!(
+        terraced
+            .iter()
+            .zip(terrain.iter())
+            .any(|(t, h)| *h < WATER_LEVEL && t != h),
+        "the terrain under the disabled LandMass water level is kept"
+    );
+}
+
+#[test]
+fn rivers_are_not_carved_under_the_land_mass_water_level() {
+    let steps = steps(&Rivers);
+    let terrain = generate(&steps[..4]);
+    let carved = generate(&steps);
+    for (c, h) in carved.iter().zip(terrain.iter()) {
+        if *h < WATER_LEVEL {
+            assert_eq!(c, h, "underwater terrain carved");
+        }
+    }
+    assert_ne!(carved, terrain, "no river carved");
+}
+
+#[test]
+fn streaming_export_reads_the_water_level() {
+    let steps = steps(&Terrace);
+    let export = |streaming: bool| {
+        let settings = ExportSettings {
+            streaming,
+            ..export_settings(&format!("context_{}", streaming), SIZE)
+        };
+        export_png(&steps, &settings)
+    };
+    assert_eq!(export(false), export(true), "streaming export differs");
+}
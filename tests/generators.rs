@@ -0,0 +1,208 @@
+//! Behaviour of the generators, independently of the rest of the project.
+use std::sync::mpsc;
+
+use worldgen::generators::{
+    gen_blur, gen_curve, gen_fill_depressions, gen_noise, gen_sharpen, gen_terrace,
+    gen_thermal_erosion, normalize, BlurConf, CurveConf, FillDepressionsConf, NoiseConf, Progress,
+    SharpenConf, TerraceConf, ThermalErosionConf,
+};
+use worldgen::CancelToken;
+
+const SEED: u64 = 0xdeadbeef;
+const SIZE: (usize, usize) = (256, 256);
+
+fn progress() -> Progress {
+    let (tx, _rx) = mpsc::channel();
+    Progress::new(tx, false, 1.0, CancelToken::default())
+}
+
+/// a noise terrain with heights in the 0.0-1.0 range
+fn terrain() -> Vec<f32> {
+    let mut hmap = vec![0.0; SIZE.0 * SIZE.1];
+    gen_noise(
+        SEED,
+        SIZE,
+        &mut hmap,
+        &NoiseConf::default(),
+        &mut progress(),
+    );
+    normalize(&mut hmap, 0.0, 1.0);
+    hmap
+}
+
+/// the right and bottom neighbours of each cell
+fn neighbour_pairs() -> impl Iterator<Item = (usize, usize)> {
+    (0..SIZE.1).flat_map(|y| {
+        (0..SIZE.0).flat_map(move |x| {
+            let off = x + y * SIZE.0;
+            let right = (x + 1 < SIZE.0).then_some((off, off + 1));
+            let bottom = (y + 1 < SIZE.1).then_some((off, off + SIZE.0));
+            right.into_iter().chain(bottom)
+        })
+    })
+}
+
+/// largest height difference between two neighbour cells
+fn max_slope(hmap: &[f32]) -> f32 {
+    neighbour_pairs()
+        .map(|(a, b)| (hmap[a] - hmap[b]).abs())
+        .fold(0.0, f32::max)
+}
+
+/// number of cells inside the world without a lower neighbour
+fn local_minima(hmap: &[f32]) -> usize {
+    let mut count = 0;
+    for y in 1..SIZE.1 - 1 {
+        for x in 1..SIZE.0 - 1 {
+            let h = hmap[x + y * SIZE.0];
+            let drains = (y - 1..=y + 1)
+                .flat_map(|ny| (x - 1..=x + 1).map(move |nx| (nx, ny)))
+                .any(|(nx, ny)| hmap[nx + ny * SIZE.0] < h);
+            if !drains {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// mean height difference between two neighbour cells
+fn local_contrast(hmap: &[f32]) -> f32 {
+    let (sum, count) = neighbour_pairs().fold((0.0, 0), |(sum, count), (a, b)| {
+        (sum + (hmap[a] - hmap[b]).abs(), count + 1)
+    });
+    sum / count as f32
+}
+
+/// horizontal standard deviation of a blurred single cell spike, in fraction of the world width
+fn blur_spread(size: usize, radius: f32) -> f32 {
+    let mut hmap = vec![0.0; size * size];
+    let center = size / 2;
+    hmap[center + center * size] = 1.0;
+    let conf = BlurConf {
+        radius,
+        ..Default::default()
+    };
+    gen_blur((size, size), &mut hmap, &conf, &mut progress());
+    let (mut sum, mut variance) = (0.0, 0.0);
+    for (i, h) in hmap.iter().enumerate() {
+        let dx = (i % size) as f32 - center as f32;
+        sum += h;
+        variance += dx * dx * h;
+    }
+    (variance / sum).sqrt() / size as f32
+}
+
+#[test]
+fn blur_spreads_as_much_in_the_preview_as_in_the_export() {
+    // 1.5 cells in the preview, 6 cells in the export
+    let preview = blur_spread(128, 1.16);
+    let export = blur_spread(512, 1.16);
+    assert!(
+        (preview - export).abs() < 0.15 * export,
+        "preview spread {} != export spread {}",
+        preview,
+        export
+    );
+}
+
+#[test]
+fn terrace_yields_at_most_levels_plateaus() {
+    let mut hmap = terrain();
+    let conf = TerraceConf {
+        levels: 5.0,
+        sharpness: 1.0,
+        keep_underwater: false,
+        ..Default::default()
+    };
+    gen_terrace(SEED, SIZE, &mut hmap, &conf, None, &mut progress());
+    // heights rounded to a hundredth of a level. The cliffs between the plateaus are a few scattered values
+    let mut cells = std::collections::HashMap::new();
+    for h in hmap.iter() {
+        *cells
+            .entry((h * conf.levels * 100.0).round() as i32)
+            .or_insert(0) += 1;
+    }
+    let plateaus: Vec<usize> = cells
+        .values()
+        .copied()
+        .filter(|count| *count > hmap.len() / 200)
+        .collect();
+    assert!(
+        plateaus.len() <= conf.levels as usize && plateaus.len() > 1,
+        "{} plateaus for {} levels",
+        plateaus.len(),
+        conf.levels
+    );
+    let plateau_cells: usize = plateaus.iter().sum();
+    assert!(
+        plateau_cells > hmap.len() * 9 / 10,
+        "only {} cells out of {} are on a plateau",
+        plateau_cells,
+        hmap.len()
+    );
+}
+
+#[test]
+fn thermal_erosion_lowers_the_maximum_slope() {
+    let mut hmap = terrain();
+    let before = max_slope(&hmap);
+    gen_thermal_erosion(
+        SIZE,
+        &mut hmap,
+        &ThermalErosionConf::default(),
+        &mut progress(),
+    );
+    let after = max_slope(&hmap);
+    assert!(
+        after < before,
+        "maximum slope {} before erosion, {} after",
+        before,
+        after
+    );
+}
+
+#[test]
+fn fill_depressions_leaves_no_local_minimum() {
+    let mut hmap = terrain();
+    assert!(local_minima(&hmap) > 0, "no depression in the test terrain");
+    let conf = FillDepressionsConf {
+        epsilon: 0.0001,
+        ..Default::default()
+    };
+    gen_fill_depressions(SIZE, &mut hmap, &conf, &mut progress());
+    assert_eq!(local_minima(&hmap), 0);
+}
+
+#[test]
+fn curve_keeps_monotonic_heights_monotonic() {
+    let mut hmap: Vec<f32> = (0..1000).map(|i| i as f32 / 999.0).collect();
+    let conf = CurveConf {
+        // a flat part followed by a steep one
+        points: vec![(0.0, 0.0), (0.3, 0.5), (0.6, 0.5), (0.7, 0.9), (1.0, 1.0)],
+    };
+    gen_curve(&mut hmap, &conf, &mut progress());
+    for (i, pair) in hmap.windows(2).enumerate() {
+        assert!(
+            pair[1] >= pair[0],
+            "height decreases at {} : {} > {}",
+            i,
+            pair[0],
+            pair[1]
+        );
+    }
+}
+
+#[test]
+fn sharpen_increases_the_local_contrast() {
+    let mut hmap = terrain();
+    let before = local_contrast(&hmap);
+    gen_sharpen(SIZE, &mut hmap, &SharpenConf::default(), &mut progress());
+    let after = local_contrast(&hmap);
+    assert!(
+        after > before,
+        "local contrast {} before sharpening, {} after",
+        before,
+        after
+    );
+}
@@ -0,0 +1,72 @@
+//! The additional layers computed by the steps are exported next to the heightmap.
+mod common;
+
+use common::{export_png, export_settings, project, read_png};
+use worldgen::generators::{
+    FillDepressions, FillDepressionsConf, Hills, LandMass, Noise, Normalize, Rivers,
+};
+use worldgen::{ExportSettings, Step};
+
+const SIZE: (usize, usize) = (256, 256);
+
+/// export the project and return the heightmap and the layer_name layer
+fn export(steps: &[Step], layer_name: &str, streaming: bool) -> (Vec<u16>, Vec<u16>) {
+    let settings = ExportSettings {
+        streaming,
+        ..export_settings(&format!("layers_{}_{}", layer_name, streaming), SIZE)
+    };
+    (
+        export_png(steps, &settings),
+        read_png(
+            &format!("{}_{}_x0_y0.png", settings.file_path, layer_name),
+            SIZE,
+        ),
+    )
+}
+
+#[test]
+fn rivers_layer_is_exported() {
+    let steps = project(&[&Hills, &Noise, &Normalize, &LandMass, &Rivers]);
+    let (_, rivers) = export(&steps, "rivers", false);
+    let river_cells = rivers.iter().filter(|v| **v > 0).count();
+    assert!(river_cells > 0, "no river in the exported layer");
+    assert!(
+        river_cells < rivers.len() / 4,
+        "rivers cover {} cells out of {}",
+        river_cells,
+        rivers.len()
+    );
+    assert_eq!(
+        rivers,
+        export(&steps, "rivers", true).1,
+        "streaming export differs"
+    );
+}
+
+#[test]
+fn lakes_are_flat() {
+    let mut steps = project(&[&Hills, &Noise, &Normalize, &FillDepressions]);
+    steps[3]
+        .typ
+        .conf_mut::<FillDepressionsConf>()
+        .unwrap()
+        .lakes = true;
+    let (hmap, lakes) = export(&steps, "lakes", false);
+    assert!(
+        lakes.iter().any(|v| *v > 0),
+        "no lake in the exported layer"
+    );
+    for y in 0..SIZE.1 {
+        for x in 0..SIZE.0 - 1 {
+            let off = x + y * SIZE.0;
+            if lakes[off] > 0 && lakes[off + 1] > 0 {
+                assert_eq!(hmap[off], hmap[off + 1], "lake at {},{} is not flat", x, y);
+            }
+        }
+    }
+    assert_eq!(
+        (hmap, lakes),
+        export(&steps, "lakes", true),
+        "streaming export differs"
+    );
+}
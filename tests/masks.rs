@@ -0,0 +1,38 @@
+//! Step masks cover the whole world, whatever its proportions.
+use std::sync::mpsc;
+
+use worldgen::generators::Noise;
+use worldgen::{assign_step_ids, CancelToken, Step, StepType, WorldGenerator, MASK_SIZE};
+
+#[test]
+fn mask_covers_rectangular_world() {
+    let size = (128, 64);
+    // mask the bottom half of the world
+    let mask: Vec<f32> = (0..MASK_SIZE * MASK_SIZE)
+        .map(|i| {
+            if i / MASK_SIZE < MASK_SIZE / 2 {
+                1.0
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    let mut steps = vec![Step {
+        typ: StepType::new(&Noise),
+        mask: Some(mask),
+        ..Default::default()
+    }];
+    assign_step_ids(&mut steps);
+    let (tx, _rx) = mpsc::channel();
+    let mut wgen = WorldGenerator::new(0xdeadbeef, size);
+    wgen.generate(&steps, tx, 1.0, &CancelToken::default());
+    let hmap = wgen.get_export_map();
+    let (min, _) = hmap.get_min_max();
+    let row_max = |y: usize| (0..size.0).map(|x| hmap.height(x, y)).fold(min, f32::max);
+    for y in 0..size.1 / 2 - 2 {
+        assert!(row_max(y) > min, "row {} should not be masked", y);
+    }
+    for y in size.1 / 2 + 1..size.1 {
+        assert_eq!(row_max(y), min, "row {} should be masked", y);
+    }
+}
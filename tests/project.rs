@@ -0,0 +1,164 @@
+//! Project files : saved projects load back identically and projects saved by older versions can still be loaded.
+//! Each step of a project keeps its own id, from which its seed is derived.
+use std::sync::mpsc;
+
+use worldgen::generators::{Curve, CurveConf, FractalType, Hills, Noise, NoiseConf, VoronoiConf};
+use worldgen::{
+    assign_new_step_ids, assign_step_ids, check_project_version, migrate_project, BlendMode,
+    CancelToken, Project, Step, StepType, WorldGenerator, MASK_SIZE, VERSION,
+};
+
+#[test]
+fn examples_are_migrated() {
+    for name in ["ex_continent.wgen", "ex_hills.wgen", "ex_island.wgen"] {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(name);
+        let (project, warning) = Project::load(&path.display().to_string()).unwrap();
+        assert!(warning.is_some(), "{} : no migration warning", name);
+        assert_eq!(project.version, VERSION);
+        assert!(!project.steps.is_empty());
+        // 0.3.1 steps all used the project seed
+        assert!(project
+            .steps
+            .iter()
+            .all(|step| step.seed == Some(project.seed)));
+    }
+}
+
+#[test]
+fn renamed_steps_are_loaded() {
+    let contents = format!(
+        "(version:\"{}\",steps:[(disabled:false,mask:None,typ:Fbm((mulx:16.0,octaves:10.0)))])",
+        VERSION
+    );
+    let (project, _) = Project::from_ron(&contents).unwrap();
+    assert_eq!(project.steps[0].typ.name(), "Noise");
+    let conf = project.steps[0].typ.conf::<NoiseConf>().unwrap();
+    assert_eq!(conf.mulx, 16.0);
+    assert_eq!(conf.octaves, 10.0);
+    assert_eq!(conf.fractal, FractalType::Fbm);
+}
+
+#[test]
+fn renamed_steps_are_reported() {
+    let contents =
+        "(version:\"0.3.1\",seed:1,steps:[(disabled:false,mask:None,typ:Fbm((mulx:16.0)))])";
+    let (project, warning) = Project::from_ron(contents).unwrap();
+    assert_eq!(project.steps[0].typ.name(), "Noise");
+    let warning = warning.expect("no migration warning");
+    assert!(
+        warning.contains("the Fbm step is renamed Noise"),
+        "warning : {}",
+        warning
+    );
+}
+
+#[test]
+fn voronoi_blend_becomes_the_step_blend() {
+    let contents = format!(
+        "(version:\"{}\",steps:[(disabled:false,mask:None,typ:Voronoi((blend:Max,cells:8.0)))])",
+        VERSION
+    );
+    let (project, warning) = Project::from_ron(&contents).unwrap();
+    assert!(warning.is_some(), "no migration warning");
+    assert_eq!(project.steps[0].blend, BlendMode::Max);
+    let conf = project.steps[0].typ.conf::<VoronoiConf>().unwrap();
+    assert_eq!(conf.cells, 8.0);
+    assert_eq!(conf.legacy_blend, None);
+}
+
+#[test]
+fn current_version_is_not_migrated() {
+    let mut project = Project {
+        steps: vec![Step::default()],
+        ..Default::default()
+    };
+    assert_eq!(migrate_project(&mut project), Ok(None));
+    assert_eq!(project.steps[0].seed, None);
+}
+
+#[test]
+fn unsupported_versions_are_rejected() {
+    assert!(check_project_version("99.0.0").is_err());
+    assert!(check_project_version("0.2.0").is_err());
+    assert!(check_project_version("not a version").is_err());
+    assert!(check_project_version(VERSION).is_ok());
+}
+
+#[test]
+fn saved_project_loads_identically() {
+    let mut project = Project {
+        seed: 42,
+        preview_size: 256,
+        steps: vec![
+            Step {
+                typ: StepType::new(&Hills),
+                mask: Some(vec![0.5; MASK_SIZE * MASK_SIZE]),
+                seed: Some(7),
+                ..Default::default()
+            },
+            Step {
+                typ: StepType::new(&Curve),
+                blend: BlendMode::Screen,
+                opacity: 0.4,
+                ..Default::default()
+            },
+            Step::default(),
+        ],
+        ..Default::default()
+    };
+    project.steps[1].typ.conf_mut::<CurveConf>().unwrap().points =
+        vec![(0.0, 0.1), (0.3, 0.2), (0.8, 0.9), (1.0, 0.95)];
+    project.export.tiles_h = 2.0;
+    project.export.seamless = true;
+    project.view_3d.water_level = 12.0;
+    assign_new_step_ids(&mut project.steps, &mut project.last_step_id);
+    let path = std::env::temp_dir()
+        .join(format!("wgen_test_project_{}.wgen", std::process::id()))
+        .display()
+        .to_string();
+    project.save(&path).unwrap();
+    let loaded = Project::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, Ok((project, None)));
+}
+
+#[test]
+fn deleted_step_ids_are_not_reused() {
+    let mut project = Project {
+        steps: vec![Step::default(), Step::default()],
+        ..Default::default()
+    };
+    assign_new_step_ids(&mut project.steps, &mut project.last_step_id);
+    project.steps.pop();
+    project.steps.push(Step::default());
+    assign_new_step_ids(&mut project.steps, &mut project.last_step_id);
+    assert_eq!(project.steps[1].id, 3);
+    // projects saved before the counter existed continue after their largest id
+    let (loaded, _) = Project::from_ron(&format!(
+        "(version:\"{}\",steps:[(disabled:false,mask:None,typ:Hills(()),id:5),(disabled:false,mask:None,typ:Hills(()))])",
+        VERSION
+    ))
+    .unwrap();
+    assert_eq!(loaded.steps[1].id, 6);
+    assert_eq!(loaded.last_step_id, 6);
+}
+
+#[test]
+fn steps_without_id_get_different_seeds() {
+    let generate = |steps: &[Step]| {
+        let (tx, _rx) = mpsc::channel();
+        let mut wgen = WorldGenerator::new(42, (64, 64));
+        wgen.generate(steps, tx, 1.0, &CancelToken::default());
+        wgen.get_export_map().heights().to_vec()
+    };
+    let noise = Step {
+        typ: StepType::new(&Noise),
+        blend: BlendMode::Add,
+        ..Default::default()
+    };
+    let steps = vec![noise.clone(), noise];
+    let mut numbered = steps.clone();
+    assign_step_ids(&mut numbered);
+    assert_ne!(numbered[0].seed(42), numbered[1].seed(42));
+    assert_eq!(generate(&steps), generate(&numbered));
+}
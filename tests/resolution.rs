@@ -0,0 +1,287 @@
+//! The same project must give visually the same terrain in the preview and in a higher resolution export.
+mod common;
+
+use std::sync::mpsc;
+
+use common::{export_png, export_settings, project, SEED};
+use worldgen::generators::{
+    normalize, resample, Blur, Craters, Curve, CurveConf, DomainWarp, DynGenerator,
+    FillDepressions, Hills, HydraulicErosion, Island, LandMass, MidPoint, MudSlide, Noise,
+    Normalize, Rivers, Sharpen, Terrace, ThermalErosion, ThermalErosionConf, Voronoi, WaterErosion,
+};
+use worldgen::{CancelToken, Step, WorldGenerator};
+
+const PREVIEW_SIZE: (usize, usize) = (128, 128);
+const EXPORT_SIZE: (usize, usize) = (512, 512);
+/// maximum root mean square difference between the normalized preview and downsampled export
+const MAX_RMS: f32 = 0.02;
+/// maximum root mean square difference between the preview and downsampled export slopes,
+/// relative to the preview slopes. Cell based generators like erosion mostly change the slopes
+const MAX_SLOPE_DIFF: f32 = 0.375;
+
+fn preview(steps: &[Step], size: (usize, usize)) -> Vec<f32> {
+    let (tx, _rx) = mpsc::channel();
+    let mut wgen = WorldGenerator::new(SEED, size);
+    wgen.generate(steps, tx, 1.0, &CancelToken::default());
+    let mut hmap = wgen.get_export_map().heights().to_vec();
+    normalize(&mut hmap, 0.0, 1.0);
+    hmap
+}
+
+fn export(
+    steps: &[Step],
+    name: &str,
+    size: (usize, usize),
+    preview_size: (usize, usize),
+) -> Vec<f32> {
+    let hmap = export_png(steps, &export_settings(name, size));
+    let hmap: Vec<f32> = hmap.iter().map(|h| *h as f32 / 65535.0).collect();
+    let mut hmap = resample(&hmap, size, preview_size);
+    normalize(&mut hmap, 0.0, 1.0);
+    hmap
+}
+
+/// root mean square difference. An empty b is considered as all zeros
+fn rms(a: &[f32], b: &[f32]) -> f32 {
+    let sum: f32 = a
+        .iter()
+        .enumerate()
+        .map(|(i, a)| (a - b.get(i).unwrap_or(&0.0)).powi(2))
+        .sum();
+    (sum / a.len() as f32).sqrt()
+}
+
+fn slope(hmap: &[f32], size: (usize, usize)) -> Vec<f32> {
+    let mut res = vec![0.0; size.0 * size.1];
+    for y in 0..size.1 - 1 {
+        for x in 0..size.0 - 1 {
+            let off = x + y * size.0;
+            let dx = hmap[off + 1] - hmap[off];
+            let dy = hmap[off + size.0] - hmap[off];
+            res[off] = (dx * dx + dy * dy).sqrt();
+        }
+    }
+    res
+}
+
+fn check(name: &str, generators: &[&'static dyn DynGenerator]) {
+    check_sizes(name, generators, PREVIEW_SIZE, EXPORT_SIZE);
+}
+
+fn check_sizes(
+    name: &str,
+    generators: &[&'static dyn DynGenerator],
+    preview_size: (usize, usize),
+    export_size: (usize, usize),
+) {
+    check_steps(name, &project(generators), preview_size, export_size);
+}
+
+fn check_steps(
+    name: &str,
+    steps: &[Step],
+    preview_size: (usize, usize),
+    export_size: (usize, usize),
+) {
+    let preview = preview(steps, preview_size);
+    let export = export(steps, name, export_size, preview_size);
+    compare(name, &preview, &export, preview_size);
+}
+
+fn compare(name: &str, preview: &[f32], export: &[f32], preview_size: (usize, usize)) {
+    let diff = rms(preview, export);
+    assert!(
+        diff < MAX_RMS,
+        "{} : export heights differ from preview (rms {})",
+        name,
+        diff
+    );
+    let preview_slope = slope(preview, preview_size);
+    let slope_diff = rms(&preview_slope, &slope(export, preview_size)) / rms(&preview_slope, &[]);
+    assert!(
+        slope_diff < MAX_SLOPE_DIFF,
+        "{} : export slopes differ from preview (relative rms {})",
+        name,
+        slope_diff
+    );
+}
+
+#[test]
+fn hills_export_matches_preview() {
+    check("hills", &[&Hills, &Normalize, &LandMass, &Island]);
+}
+
+#[test]
+fn noise_export_matches_preview() {
+    check("noise", &[&Noise, &Normalize, &MudSlide]);
+}
+
+#[test]
+fn mid_point_export_matches_preview() {
+    check("mid_point", &[&MidPoint, &Normalize, &MudSlide]);
+}
+
+#[test]
+fn water_erosion_export_matches_preview() {
+    check(
+        "water_erosion",
+        &[&Hills, &Noise, &Normalize, &WaterErosion],
+    );
+}
+
+#[test]
+fn thermal_erosion_export_matches_preview() {
+    check("thermal_erosion", &[&MidPoint, &Normalize, &ThermalErosion]);
+}
+
+#[test]
+fn hydraulic_erosion_export_matches_preview() {
+    check(
+        "hydraulic_erosion",
+        &[&Hills, &Noise, &Normalize, &HydraulicErosion],
+    );
+}
+
+#[test]
+fn domain_warp_export_matches_preview() {
+    check("domain_warp", &[&Hills, &Noise, &Normalize, &DomainWarp]);
+}
+
+#[test]
+fn voronoi_export_matches_preview() {
+    check("voronoi", &[&Voronoi, &Noise, &Normalize]);
+}
+
+#[test]
+fn terrace_export_matches_preview() {
+    // the cliffs between the levels are one cell wide at any resolution : MudSlide smooths them as in a real project
+    check(
+        "terrace",
+        &[&Hills, &Noise, &Normalize, &Terrace, &MudSlide],
+    );
+}
+
+#[test]
+fn craters_export_matches_preview() {
+    check("craters", &[&Noise, &Normalize, &Craters]);
+}
+
+#[test]
+fn rivers_export_matches_preview() {
+    check("rivers", &[&Hills, &Noise, &Normalize, &Rivers]);
+}
+
+#[test]
+fn fill_depressions_export_matches_preview() {
+    check(
+        "fill_depressions",
+        &[&Hills, &Noise, &Normalize, &FillDepressions],
+    );
+}
+
+#[test]
+fn blur_export_matches_preview() {
+    check("blur", &[&MidPoint, &Normalize, &Blur]);
+}
+
+#[test]
+fn sharpen_export_matches_preview() {
+    check("sharpen", &[&Hills, &Noise, &Normalize, &Sharpen]);
+}
+
+#[test]
+fn curve_export_matches_preview() {
+    let mut steps = project(&[&Hills, &Noise, &Normalize, &Curve]);
+    // flatten the lowlands and steepen the mountains
+    steps[3].typ.conf_mut::<CurveConf>().unwrap().points =
+        vec![(0.0, 0.0), (0.4, 0.1), (0.7, 0.6), (1.0, 1.0)];
+    check_steps("curve", &steps, PREVIEW_SIZE, EXPORT_SIZE);
+}
+
+#[test]
+fn rectangular_export_matches_preview() {
+    check_sizes(
+        "rectangular",
+        &[
+            &Hills,
+            &Noise,
+            &Normalize,
+            &LandMass,
+            &WaterErosion,
+            &Island,
+        ],
+        (128, 64),
+        (512, 256),
+    );
+}
+
+/// mean absolute height change of the last generator of the project in a world of the given size
+fn moved_material(steps: &[Step], size: (usize, usize)) -> f32 {
+    let heights = |steps: &[Step]| {
+        let (tx, _rx) = mpsc::channel();
+        let mut wgen = WorldGenerator::new(SEED, size);
+        wgen.generate(steps, tx, 1.0, &CancelToken::default());
+        wgen.get_export_map().heights().to_vec()
+    };
+    let before = heights(&steps[..steps.len() - 1]);
+    let after = heights(steps);
+    let sum: f32 = before
+        .iter()
+        .zip(after.iter())
+        .map(|(a, b)| (a - b).abs())
+        .sum();
+    sum / before.len() as f32
+}
+
+/// the simulation of a finer world must move as much material as in the preview.
+/// max_ratio is the tolerated ratio between both amounts
+fn check_moved_material(name: &str, steps: &[Step], max_ratio: f32) {
+    let preview = moved_material(steps, PREVIEW_SIZE);
+    let export = moved_material(steps, EXPORT_SIZE);
+    let ratio = export / preview;
+    assert!(
+        ratio < max_ratio && ratio > 1.0 / max_ratio,
+        "{} : the export moves {} times the preview material",
+        name,
+        ratio
+    );
+}
+
+#[test]
+fn mud_slide_moves_as_much_material_at_any_size() {
+    check_moved_material(
+        "mud_slide",
+        &project(&[&Hills, &Noise, &Normalize, &MudSlide]),
+        1.3,
+    );
+}
+
+#[test]
+fn water_erosion_moves_as_much_material_at_any_size() {
+    check_moved_material(
+        "water_erosion",
+        &project(&[&Hills, &Noise, &Normalize, &WaterErosion]),
+        1.3,
+    );
+}
+
+#[test]
+fn thermal_erosion_moves_as_much_material_at_any_size() {
+    let mut steps = project(&[&Hills, &Noise, &Normalize, &ThermalErosion]);
+    // the hills slopes collapse, not only the noise details which are finer in the export
+    steps[3]
+        .typ
+        .conf_mut::<ThermalErosionConf>()
+        .unwrap()
+        .talus_angle = 10.0;
+    check_moved_material("thermal_erosion", &steps, 1.3);
+}
+
+#[test]
+fn hydraulic_erosion_moves_as_much_material_at_any_size() {
+    check_moved_material(
+        "hydraulic_erosion",
+        &project(&[&Hills, &Noise, &Normalize, &HydraulicErosion]),
+        1.3,
+    );
+}
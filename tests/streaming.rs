@@ -0,0 +1,154 @@
+//! The streaming exporter computes the local steps by chunks.
+mod common;
+
+use std::sync::mpsc;
+
+use common::{export_png, export_settings, project, SEED};
+use worldgen::generators::{
+    reference_size, Blur, Craters, Curve, DomainWarp, DynGenerator, Generator, Hills, LandMass,
+    Locality, MudSlide, Noise, Normalize, Progress, Sharpen, StepContext, ThermalErosion,
+    ThermalErosionConf,
+};
+use worldgen::{export_heightmap, CancelToken, ExportSettings, Step};
+
+const SIZE: (usize, usize) = (256, 256);
+/// 7 bands, the last one shorter
+const BAND_ROWS: usize = 37;
+
+/// a terrain followed by the step
+fn steps(step: &'static dyn DynGenerator) -> Vec<Step> {
+    project(&[&Hills, &Noise, &Normalize, step])
+}
+
+/// export the steps with both exporters, the streaming one using bands of band_rows rows
+fn check_bands_at(steps: &[Step], size: (usize, usize), band_rows: usize) {
+    let typ = &steps.last().unwrap().typ;
+    let step = typ.name();
+    assert!(
+        matches!(typ.locality(size), Locality::Local(_)),
+        "{} is not local",
+        step
+    );
+    let export = |streaming: bool| {
+        let settings = ExportSettings {
+            streaming,
+            band_cells: size.0 * band_rows,
+            ..export_settings(&format!("bands_{}_{}", step, streaming), size)
+        };
+        export_png(steps, &settings)
+    };
+    assert_eq!(
+        export(false),
+        export(true),
+        "{} streaming export differs",
+        step
+    );
+}
+
+fn check_bands(step: &'static dyn DynGenerator) {
+    check_bands_at(&steps(step), SIZE, BAND_ROWS);
+}
+
+#[test]
+fn blur_bands() {
+    check_bands(&Blur);
+}
+
+#[test]
+fn sharpen_bands() {
+    check_bands(&Sharpen);
+}
+
+#[test]
+fn domain_warp_bands() {
+    check_bands(&DomainWarp);
+}
+
+#[test]
+fn craters_bands() {
+    check_bands(&Craters);
+}
+
+#[test]
+fn curve_bands() {
+    check_bands(&Curve);
+}
+
+#[test]
+fn land_mass_bands() {
+    check_bands(&LandMass);
+}
+
+#[test]
+fn mud_slide_bands() {
+    check_bands(&MudSlide);
+}
+
+#[test]
+fn thermal_erosion_bands() {
+    let mut steps = steps(&ThermalErosion);
+    // with the default iterations, the halo covers most of the world and the erosion is computed in memory
+    assert_eq!(steps[3].typ.locality(SIZE), Locality::Global, "locality");
+    steps[3]
+        .typ
+        .conf_mut::<ThermalErosionConf>()
+        .unwrap()
+        .iterations = 10.0;
+    check_bands_at(&steps, SIZE, BAND_ROWS);
+}
+
+#[test]
+fn reference_grid_of_uneven_size() {
+    // a reference cell covers one or two cells of the world in both directions
+    let size = (1500, 40);
+    assert_eq!(reference_size(size), (1024, 27));
+    check_bands_at(&steps(&Craters), size, 7);
+}
+
+/// a local step that forgot to implement run_chunk
+struct NoChunk;
+
+impl Generator for NoChunk {
+    type Conf = ();
+    fn name(&self) -> &'static str {
+        "NoChunk"
+    }
+    fn description(&self) -> &'static str {
+        "local step without run_chunk"
+    }
+    fn run(
+        &self,
+        _seed: u64,
+        _size: (usize, usize),
+        _hmap: &mut Vec<f32>,
+        _conf: &(),
+        _ctx: &StepContext,
+        _progress: &mut Progress,
+    ) {
+    }
+    fn locality(&self, _size: (usize, usize), _conf: &()) -> Locality {
+        Locality::Local(0)
+    }
+}
+
+#[test]
+fn missing_run_chunk_fails_the_export() {
+    let settings = ExportSettings {
+        streaming: true,
+        ..export_settings("no_chunk", (64, 64))
+    };
+    let (tx, _rx) = mpsc::channel();
+    let res = export_heightmap(
+        SEED,
+        &project(&[&NoChunk]),
+        &settings,
+        tx,
+        1.0,
+        &CancelToken::default(),
+    );
+    assert_eq!(
+        res.unwrap_err(),
+        "NoChunk cannot be computed by chunks",
+        "export error"
+    );
+}